@@ -1,6 +1,6 @@
 use crate::{
-    game::{FenProcessor, GameManager, GameState, Piece, PieceKind, PlayerColor, Position},
-    engine::{EngineProtocol, EngineThinkingInfo, EngineGoResult},
+    game::{GameAnalysis, Hint, Threat, MoveAnalysis, MoveClass, SideSummary, BookEntry, Continuation, Database, Endgame, EndgameRecord, ExploreSort, FenProcessor, GameEntry, GameHit, GameManager, GuessResult, GuessSession, GameMetadata, GameRecord, GameResult, Session, GameState, Move, Piece, PieceKind, PlayerColor, Position, Puzzle, PuzzleSession, PuzzleStats, MoveFormat, Variant, ENDGAMES, HANDICAPS},
+    engine::{mate_in, EngineProtocol, EngineThinkingInfo, EngineGoResult},
     utils::*,
 };
 
@@ -10,6 +10,17 @@ pub const BOARD_HEIGHT: u16 = 10 * 2 + 1; // 10行 * 2行高 + 边框
 pub const INPUT_AREA_Y: u16 = BOARD_HEIGHT + 3; // 输入区域起始位置
 pub const INFO_PANEL_WIDTH: u16 = 100;           // 右侧信息面板宽度
 pub const INFO_START_COL: u16 = BOARD_WIDTH + 4; // 信息面板起始列
+pub const EVAL_BAR_COL: u16 = BOARD_WIDTH + 2;   // 形势条所在列
+
+/// 形势条和形势图显示的分数范围，超出时截断
+const EVAL_RANGE: isize = 1000;
+/// 形势图的最大列数和行数
+const GRAPH_WIDTH: usize = 60;
+const GRAPH_HEIGHT: u16 = 4;
+/// 形势图最下一行与棋盘底边对齐
+const GRAPH_Y: u16 = BOARD_HEIGHT + 1 - GRAPH_HEIGHT;
+/// 八分之一格的方块，用于形势图
+const EIGHTHS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
 /// 棋盘坐标标签
 pub const COL_LABELS: [char; 9] = ['a', 'b', 'c', 'd', 'e', 'f', 'g', 'h', 'i'];
@@ -18,6 +29,8 @@ pub const ROW_LABELS: [char; 10] = ['9', '8', '7', '6', '5', '4', '3', '2', '1',
 /// 棋子符号
 pub const RED_PIECES: [char; 7] = ['帅', '仕', '相', '马', '车', '炮', '兵'];
 pub const BLACK_PIECES: [char; 7] = ['将', '士', '象', '马', '车', '炮', '卒'];
+/// 揭棋暗子符号
+pub const HIDDEN_PIECE: char = '暗';
 
 /// 颜色主题
 #[derive(Clone, Copy)]
@@ -27,6 +40,7 @@ pub struct Theme {
     board_fg: Color,
     board_bg: Color,
     highlight: Color,
+    hidden_bg: Color,
 }
 
 impl Default for Theme {
@@ -37,6 +51,7 @@ impl Default for Theme {
             board_fg: Color::White,
             board_bg: Color::Reset,
             highlight: Color::Yellow,
+            hidden_bg: Color::DarkGrey,
         }
     }
 }
@@ -57,16 +72,30 @@ pub fn render_view(game_manager: Option<&GameManager>) -> Result<()> {
     
     // 如果有游戏状态，绘制棋盘和状态信息
     if let Some(game) = game_manager {
-        // 绘制棋盘
-        render_board(&game.state)?;
+        // 绘制棋盘，复盘时为正在查看的局面
+        render_board(game.view_state())?;
+
+        // 正在查看的局面有分数时绘制形势条
+        let eval: Option<isize> = game.tree.node(game.view_node()).eval;
+        if let Some(score) = eval {
+            draw_eval_bar(score, game.view_state().flipped)?;
+        }
         
         // 绘制状态信息
-        draw_status_bar(&game.state)?;
+        draw_status_bar(game)?;
+
+        // 复盘和打谱时绘制着法列表
+        if game.review.is_some() || game.engine.is_none() {
+            draw_move_list(game)?;
+        }
         
         // 绘制思考信息
         if let Some(info) = game.think_info.as_ref() {
-            draw_think_info(&info)?;
+            draw_think_info(info, eval)?;
         }
+
+        // 绘制整局形势图
+        draw_eval_graph(game)?;
     }
     
     // 绘制命令提示
@@ -81,6 +110,33 @@ pub fn render_view(game_manager: Option<&GameManager>) -> Result<()> {
     Ok(())
 }
 
+/// 渲染摆棋模式界面
+pub fn render_setup(state: &GameState) -> Result<()> {
+    execute!(stdout(), Clear(ClearType::All))?;
+    render_board(state)?;
+
+    let side_text: StyledContent<String> = match state.current_player {
+        PlayerColor::Red => "红方先走".to_string().red(),
+        PlayerColor::Black => "黑方先走".to_string().dark_yellow(),
+    };
+    execute!(
+        stdout(),
+        MoveTo(INFO_START_COL, 0),
+        SetForegroundColor(Color::Cyan),
+        Print("摆棋模式 | "),
+        Print(side_text),
+        SetForegroundColor(Color::Cyan),
+        MoveTo(INFO_START_COL, 1),
+        Print(format!("FEN: {}", state.to_fen())),
+        ResetColor,
+        MoveTo(0, INPUT_AREA_Y + 2),
+        Print("摆棋: put <棋子> <格子> | clear <格子> | side <red|black> | done <引擎> <red|black|analyze> | cancel"),
+    )?;
+
+    stdout().flush()?;
+    Ok(())
+}
+
 /// 渲染棋盘画面
 pub fn render_board(state: &GameState) -> Result<()> {
     let theme: Theme = Theme::default();
@@ -136,36 +192,17 @@ pub fn render_board(state: &GameState) -> Result<()> {
         }
     }
     
+    // 最后一步的起止格子
+    let last_move: Option<(Position, Position)> = state.moves.last().map(|mv| (mv.source(), mv.target()));
+
     // 绘制棋子
     for row in 0..10 {
         for col in 0..9 {
-            // 根据翻转状态调整行坐标和列坐标
-            let (screen_row, screen_col) = if state.flipped {
-                (row, 8 - col)
-            } else {
-                (9 - row, col)
-            };
-            
             // 计算屏幕坐标
-            let x: u16 = (screen_col * 4 + 2) as u16;
-            let y: u16 = (screen_row * 2 + 1) as u16;
+            let (x, y) = square_coords(state, Position { row, col });
             
-            if let Some(piece) = state.board[row][col] {
-                // 获取棋子字符
-                let piece_char: usize = match piece.kind {
-                    PieceKind::General => 0,
-                    PieceKind::Advisor => 1,
-                    PieceKind::Elephant => 2,
-                    PieceKind::Horse => 3,
-                    PieceKind::Rook => 4,
-                    PieceKind::Cannon => 5,
-                    PieceKind::Pawn => 6,
-                };
-                
-                let char: char = match piece.color {
-                    PlayerColor::Red => RED_PIECES[piece_char],
-                    PlayerColor::Black => BLACK_PIECES[piece_char],
-                };
+            if let Some(piece) = state.board.at(row, col) {
+                let char: char = piece_symbol(piece);
                 
                 // 设置棋子颜色
                 let color: Color = match piece.color {
@@ -173,19 +210,36 @@ pub fn render_board(state: &GameState) -> Result<()> {
                     PlayerColor::Black => theme.black_piece,
                 };
                 
+                // 暗子使用独立背景色
+                let bg: Color = if piece.hidden { theme.hidden_bg } else { theme.board_bg };
+
+                // 最后一步落点反色显示
+                let attr: Attribute = match last_move {
+                    Some((_, to)) if to == (Position { row, col }) => Attribute::Reverse,
+                    _ => Attribute::NoReverse,
+                };
+                
                 execute!(
                     stdout(),
                     MoveTo(x, y),
                     SetForegroundColor(color),
+                    SetBackgroundColor(bg),
+                    SetAttribute(attr),
                     Print(char),
+                    SetAttribute(Attribute::NoReverse),
+                    SetBackgroundColor(theme.board_bg),
                 )?;
             } else {
-                // 空位置
+                // 空位置，最后一步起点高亮
+                let (fg, mark): (Color, char) = match last_move {
+                    Some((from, _)) if from == (Position { row, col }) => (theme.highlight, '○'),
+                    _ => (theme.board_fg, '·'),
+                };
                 execute!(
                     stdout(),
                     MoveTo(x, y),
-                    SetForegroundColor(theme.board_fg),
-                    Print('·')
+                    SetForegroundColor(fg),
+                    Print(mark)
                 )?;
             }
         }
@@ -229,9 +283,60 @@ pub fn render_board(state: &GameState) -> Result<()> {
     Ok(())
 }
 
+/// 格子在屏幕上的坐标，根据翻转状态调整
+fn square_coords(state: &GameState, pos: Position) -> (u16, u16) {
+    let (screen_row, screen_col) = if state.flipped {
+        (pos.row, 8 - pos.col)
+    } else {
+        (9 - pos.row, pos.col)
+    };
+    ((screen_col * 4 + 2) as u16, (screen_row * 2 + 1) as u16)
+}
+
+/// 棋子的显示字符
+fn piece_symbol(piece: Piece) -> char {
+    let piece_char: usize = match piece.kind {
+        PieceKind::General => 0,
+        PieceKind::Advisor => 1,
+        PieceKind::Elephant => 2,
+        PieceKind::Horse => 3,
+        PieceKind::Rook => 4,
+        PieceKind::Cannon => 5,
+        PieceKind::Pawn => 6,
+    };
+    match piece.color {
+        _ if piece.hidden => HIDDEN_PIECE,
+        PlayerColor::Red => RED_PIECES[piece_char],
+        PlayerColor::Black => BLACK_PIECES[piece_char],
+    }
+}
+
+/// 在棋盘上用指定背景色标出着法的起止格子
+fn highlight_move(state: &GameState, mv: Move, bg: Color) -> Result<()> {
+    let theme: Theme = Theme::default();
+    for pos in [mv.source(), mv.target()] {
+        let (x, y) = square_coords(state, pos);
+        let (fg, char): (Color, char) = match state.board.at(pos.row, pos.col) {
+            Some(piece) if piece.color == PlayerColor::Red => (theme.red_piece, piece_symbol(piece)),
+            Some(piece) => (theme.black_piece, piece_symbol(piece)),
+            None => (theme.board_fg, '·'),
+        };
+        execute!(
+            stdout(),
+            MoveTo(x, y),
+            SetForegroundColor(fg),
+            SetBackgroundColor(bg),
+            Print(char),
+            ResetColor,
+        )?;
+    }
+    Ok(())
+}
+
 /// 绘制状态栏
-fn draw_status_bar(state: &GameState) -> Result<()> {
+fn draw_status_bar(game: &GameManager) -> Result<()> {
     let theme: Theme = Theme::default();
+    let state: &GameState = game.view_state();
     
     // 当前玩家
     let player_text: StyledContent<String> = match state.current_player {
@@ -251,28 +356,239 @@ fn draw_status_bar(state: &GameState) -> Result<()> {
         }
     };
     
+    // 非标准开局标记
+    let mut start_text: String = match game.handicap {
+        Some(handicap) => format!(" | {}", handicap.name),
+        None if !game.is_standard_start() => " | 自定义局面".to_string(),
+        None => String::new(),
+    };
+    if let Some(opening) = game.opening() {
+        start_text.push_str(&format!(" | {}", opening));
+    }
+    if game.engine.is_none() {
+        start_text.push_str(" | 打谱");
+    }
+    if game.review.is_some() {
+        start_text.push_str(&format!(" | 复盘 {}/{}", game.view_ply(), game.ply_count()));
+    }
+    if !game.tree.is_main_line(game.view_node()) {
+        start_text.push_str(" | 变着");
+    }
+    let repetitions: usize = state.repetition_count();
+    if repetitions > 0 {
+        start_text.push_str(&format!(" | 局面重复{}次", repetitions));
+    }
+    if state.variant == Variant::Jieqi {
+        start_text.push_str(&format!(
+            " | 揭棋 暗子: 红{} 黑{}",
+            state.pool.remaining(PlayerColor::Red).len(),
+            state.pool.remaining(PlayerColor::Black).len(),
+        ));
+    }
+    
     // 绘制状态信息
     execute!(
         stdout(),
         MoveTo(INFO_START_COL, 0),
         SetForegroundColor(theme.board_fg),
         Print(player_text),
+        Print(start_text),
         MoveTo(INFO_START_COL, 1),
         Print(history_text),
+        MoveTo(INFO_START_COL, 2),
+        SetForegroundColor(Color::DarkGrey),
+        Print(game.metadata.summary()),
         ResetColor
     )?;
     
     Ok(())
 }
 
-/// 绘制思考信息
-fn draw_think_info(info: &EngineThinkingInfo) -> Result<()> {
+/// 绘制当前路线的着法列表，高亮正在查看的着法，带变着的着法后加 *
+fn draw_move_list(game: &GameManager) -> Result<()> {
+    const VISIBLE_ROUNDS: usize = 10;
+    let theme: Theme = Theme::default();
+    let history: &[String] = &game.state.history;
+    let path: Vec<usize> = game.tree.path(game.node);
+    let current: usize = game.view_ply();
+
+    // 黑方先走时第一回合红方位置留空
+    let offset: usize = usize::from(game.start_fen.ends_with(" b"));
+    let rounds: usize = (history.len() + offset).div_ceil(2);
+    let current_round: usize = (current + offset).saturating_sub(1) / 2;
+    let first_round: usize = current_round.saturating_sub(VISIBLE_ROUNDS / 2).min(rounds.saturating_sub(VISIBLE_ROUNDS));
+    let start_y: u16 = 7;
+
+    // 当前着法的注释
+    if let Some(comment) = &game.tree.node(game.view_node()).comment {
+        let text: String = comment.replace('\n', " ");
+        let text: String = text.chars().take(INFO_PANEL_WIDTH as usize / 2 - 4).collect();
+        execute!(
+            stdout(),
+            MoveTo(INFO_START_COL, start_y - 1),
+            SetForegroundColor(Color::Green),
+            Print(format!("注释: {}", text)),
+            ResetColor
+        )?;
+    }
+
+    for (line, round) in (first_round..rounds.min(first_round + VISIBLE_ROUNDS)).enumerate() {
+        execute!(
+            stdout(),
+            MoveTo(INFO_START_COL, start_y + line as u16),
+            SetForegroundColor(theme.board_fg),
+            Print(format!("{:>3}. ", round + 1)),
+        )?;
+        for side in 0..2 {
+            let ply: usize = (round * 2 + side + 1).wrapping_sub(offset);
+            let text: String = match ply.checked_sub(1).and_then(|i| Some((history.get(i)?, path[i]))) {
+                Some((text, node)) if ply > 0 => {
+                    let glyph: &str = game.tree.node(node).glyph.map_or("", |g| g.symbol());
+                    let mark: &str = if game.tree.alternatives(node).len() > 1 { "*" } else { "" };
+                    format!("{}{}{}", text, glyph, mark)
+                }
+                _ => "......".to_string(),
+            };
+            let (fg, bg): (Color, Color) = if ply == current && ply > 0 {
+                (Color::Black, theme.highlight)
+            } else {
+                (theme.board_fg, theme.board_bg)
+            };
+            execute!(
+                stdout(),
+                SetForegroundColor(fg),
+                SetBackgroundColor(bg),
+                Print(text),
+                SetBackgroundColor(theme.board_bg),
+                Print("  "),
+            )?;
+        }
+    }
+    execute!(stdout(), ResetColor)?;
+    Ok(())
+}
+
+/// 红方视角分数的文字表示，杀棋标明步数
+fn format_eval(score: isize) -> String {
+    match mate_in(score) {
+        Some(0) if score > 0 => "红胜".to_string(),
+        Some(0) => "黑胜".to_string(),
+        Some(mate) if mate > 0 => format!("红{}步杀", mate),
+        Some(mate) => format!("黑{}步杀", -mate),
+        None => format!("{:+.2}", score as f64 / 100.0_f64),
+    }
+}
+
+/// 红方视角分数的显示颜色
+fn eval_color(theme: &Theme, score: isize) -> Color {
+    match score {
+        0 => theme.board_fg,
+        1.. => theme.red_piece,
+        _ => theme.black_piece,
+    }
+}
+
+/// 在棋盘右侧绘制形势条：红方一侧的红色部分随红方优势增长，杀棋时在胜方对面的一端标记
+fn draw_eval_bar(score: isize, flipped: bool) -> Result<()> {
+    let theme: Theme = Theme::default();
+    let height: isize = BOARD_HEIGHT as isize + 1;
+    let clamped: isize = score.clamp(-EVAL_RANGE, EVAL_RANGE);
+    // 红色部分的格数，均势时各占一半
+    let red_cells: isize = ((clamped + EVAL_RANGE) * height + EVAL_RANGE) / (2 * EVAL_RANGE);
+
+    for y in 0..height {
+        // 红方在棋盘下方，翻转时在上方
+        let from_red: isize = if flipped { y } else { height - 1 - y };
+        let color: Color = if from_red < red_cells { theme.red_piece } else { theme.black_piece };
+        execute!(
+            stdout(),
+            MoveTo(EVAL_BAR_COL, y as u16),
+            SetForegroundColor(color),
+            Print('█'),
+        )?;
+    }
+
+    if mate_in(score).is_some() {
+        let red_end: u16 = if flipped { 0 } else { height as u16 - 1 };
+        let y: u16 = if score > 0 { height as u16 - 1 - red_end } else { red_end };
+        execute!(
+            stdout(),
+            MoveTo(EVAL_BAR_COL, y),
+            SetForegroundColor(eval_color(&theme, score)),
+            Print('杀'),
+        )?;
+    }
+    execute!(stdout(), ResetColor)?;
+    Ok(())
+}
+
+/// 在信息面板底部绘制当前路线的形势图，红方视角，越高红方越优，中线为均势
+/// 局面多于列数时抽样显示，杀棋截断并用紫色标出，正在查看的局面反色显示
+fn draw_eval_graph(game: &GameManager) -> Result<()> {
+    let theme: Theme = Theme::default();
+    let evals: Vec<Option<isize>> = game.eval_line();
+    if evals.iter().flatten().count() < 2 {
+        return Ok(());
+    }
+    let columns: usize = evals.len().min(GRAPH_WIDTH);
+    let current: usize = game.view_ply() * columns / evals.len();
+    let levels: isize = GRAPH_HEIGHT as isize * 8;
+
+    let mut title: String = format!("形势图（红方视角，±{}分截断）", EVAL_RANGE / 100);
+    if let Some(score) = evals[game.view_ply()] {
+        title.push_str(&format!(" 当前: {}", format_eval(score)));
+    }
+    execute!(
+        stdout(),
+        MoveTo(INFO_START_COL, GRAPH_Y - 1),
+        SetForegroundColor(Color::DarkGrey),
+        Print(title),
+    )?;
+
+    for col in 0..columns {
+        let score: Option<isize> = evals[col * evals.len() / columns];
+        let level: isize = score.map_or(0, |score| {
+            let clamped: isize = score.clamp(-EVAL_RANGE, EVAL_RANGE);
+            ((clamped + EVAL_RANGE) * levels + EVAL_RANGE) / (2 * EVAL_RANGE)
+        });
+        let color: Color = match score {
+            Some(score) if mate_in(score).is_some() => Color::Magenta,
+            Some(score) => eval_color(&theme, score),
+            None => theme.board_fg,
+        };
+        let attr: Attribute = if col == current { Attribute::Reverse } else { Attribute::NoReverse };
+        for row in 0..GRAPH_HEIGHT {
+            let base: isize = (GRAPH_HEIGHT - 1 - row) as isize * 8;
+            let fill: usize = (level - base).clamp(0, 8) as usize;
+            execute!(
+                stdout(),
+                MoveTo(INFO_START_COL + col as u16, GRAPH_Y + row),
+                SetForegroundColor(color),
+                SetAttribute(attr),
+                Print(EIGHTHS[fill]),
+            )?;
+        }
+    }
+    execute!(stdout(), SetAttribute(Attribute::NoReverse), ResetColor)?;
+    Ok(())
+}
+
+/// 绘制思考信息，eval 为正在查看的局面的红方视角分数
+fn draw_think_info(info: &EngineThinkingInfo, eval: Option<isize>) -> Result<()> {
+    let theme: Theme = Theme::default();
     let mut lines: Vec<String> = Vec::new();
     
-    // 第一行：基本指标
+    // 第一行：基本指标，有红方视角分数时代替引擎的原始分数
     let mut line1: String = format!("深度: {}", info.depth);
-    if let Some(score) = info.score {
-        line1.push_str(&format!(" | 分数: {}", score));
+    if let Some(eval) = eval {
+        line1.push_str(&format!(" | 形势: {}", format_eval(eval)));
+    } else {
+        if let Some(score) = info.score {
+            line1.push_str(&format!(" | 分数: {}", score));
+        }
+        if let Some(mate) = info.mate {
+            line1.push_str(&format!(" | {}", if mate > 0 { format!("{}步杀", mate) } else { format!("{}步被杀", -mate) }));
+        }
     }
     if let Some(nps) = info.nps {
         line1.push_str(&format!(" | NPS: {}k", (nps as f64 / 1024.0_f64).round() as usize));
@@ -291,11 +607,10 @@ fn draw_think_info(info: &EngineThinkingInfo) -> Result<()> {
         lines.push(format!("主变: {}", pv.join(" ")));
     }
     
-    // 设置颜色
-    let color = if let Some(score) = info.score {
-        if score >= 0 { Color::Blue } else { Color::Red }
-    } else {
-        Color::Reset
+    // 设置颜色：红优为红色，黑优为黑方棋子颜色
+    let color: Color = match eval {
+        Some(eval) => eval_color(&theme, eval),
+        None => Color::Reset,
     };
     
     // 显示思考信息
@@ -381,12 +696,60 @@ pub fn show_error(msg: &str) -> Result<()> {
 /// 显示帮助信息
 pub fn show_help() -> Result<()> {
     const HELP_TEXT: &str = "可用命令:
-    new <引擎> <red|black> [FEN] - 开始新游戏
-    move <走法> - 走子(如'h2e2')
+    new <引擎> <red|black> [FEN|--handicap <预设>] [--variant jieqi] - 开始新游戏
+    handicaps - 列出所有让子预设
+    move <走法> - 走子(如'h2e2'、'C2.5'或'炮二平五')
+    save <文件> [iccs|wxf|chinese|ubb] - 保存对局为PGN或DhtmlXQ
+    load <文件> [序号] - 载入PGN或XQF棋谱中的第几局
+    paste - 粘贴DhtmlXQ棋谱块，以[/DhtmlXQ]结束
+    replay [间隔毫秒] - 从头回放当前对局
+    play <引擎> <red|black> - 载入棋谱后与引擎继续对弈
+    setup [empty|current|start] - 进入摆棋模式
+    put <棋子> <格子> - 摆放棋子(如'put r a9'，大写红方小写黑方)
+    clear <格子> - 移除格子上的棋子
+    side <red|black> - 设置先走方
+    done <引擎> <red|black|analyze> - 校验局面并开始对弈或分析
+    cancel - 退出摆棋模式
     reverse|flip - 翻转棋盘显示
     board - 重新显示棋盘
     history - 显示走子历史
+    first|prev|next|last|goto <步数> - 复盘浏览对局
+    analyze [引擎] - 引擎分析正在查看的局面
+    analyze game [depth <深度>|time <毫秒>] - 逐步分析整局，标注缓着、错着、败着和较好的着法，显示双方准确率
+    hint [n] [nohl] - 引擎给出正在查看的局面的 n 个候选着法（默认3个）并标出最佳着法，nohl 不标出，记入提示次数
+    threat - 引擎分析假如对方连走一步时的最佳着法，在棋盘上标出威胁
+    branch [<引擎> <red|black>] - 从正在查看的局面继续走子（复盘时直接走子会作为变着保存）
+    var [n] - 列出此处的候选着法，或切换到第n个变着
+    promote - 把当前变着提升为主线
+    delete - 删除正在查看的着法及其后续着法
+    comment [文字] - 为正在查看的着法添加注释，不带文字时清除
+    mark <!|?|!!|??|!?|?!|none> - 为正在查看的着法添加评注符号
+    sessions - 列出自动保存的对局
+    resume [n] - 恢复第n个自动保存的对局，默认最近一局
+    db [import <文件>] - 查看棋谱库，或导入PGN、XQF棋谱
+    db find [player|red|black|event|result|date <值>]... [opening <ECCO、开局名或着法>] - 在棋谱库中查找对局
+    db position - 查找出现过正在查看的局面的对局（包括不同着法次序形成的相同局面）
+    db load [n] - 载入查找结果中的第n局并复盘
+    book build <输出> [棋谱]... [--min-games n] [--min-score 百分比] [--max-ply n] [--self-play n [--time 毫秒]] - 由棋谱和引擎自对弈生成开局库
+    book dump <开局库> [FEN] - 列出开局库中指定局面（默认正在查看的局面）的着法
+    rep open <文件> - 编辑开局库或开局谱（.toml 文件保存标记和注释），文件不存在时新建
+    rep [show] - 列出正在查看的局面在开局谱中的着法
+    rep add <着法> [权重] | rep remove <着法|序号> | rep weight <着法|序号> <权重> - 增删着法、设置权重
+    rep comment <着法|序号> [文字] | rep mark <着法|序号> <main|avoid|none> - 设置注释和标记
+    rep save [文件] | rep close - 保存或关闭开局谱
+    train <开局谱> <red|black> - 按间隔重复安排复习，程序走对方着法，玩家走出准备的应着
+    train stop - 结束训练
+    puzzle <题库> [n] - 从第n题或第一道未解出的题开始解题，杀法题接受引擎确认的其他杀法
+    puzzle [next|stop] - 查看当前题目和统计、换下一题或结束解题
+    guess <棋谱文件> <red|black> [局号] - 逐步猜测名局中一方的着法，引擎按分差计分
+    guess stop - 结束猜着并显示总结
+    endgame - 列出内置实用残局和练习记录
+    endgame <n|名称> [引擎] - 与引擎对弈练习残局，限着内达到理论结果即成功
+    explore [games|score|rating|n] - 列出棋谱库中此局面的后续着法并排序，或走入第n个着法
+    tag [<标签> [值]] - 查看对局信息，或设置 red、black、event、site、date、round、time、result 等标签，不带值时清除
     set <参数> <值> - 设置引擎参数
+    perft <深度> - 统计当前局面指定深度的叶子节点数，深度最大为5
+    divide <深度> - 按着法分别统计叶子节点数
     listengines - 列出所有可用引擎
     help - 显示帮助
     quit - 退出程序";
@@ -405,20 +768,401 @@ pub fn show_engines(engines: &[String]) -> Result<()> {
     display_info_panel(&content, 3, Color::Reset, Some("可用引擎:"))
 }
 
+/// 显示让子预设列表
+pub fn show_handicaps() -> Result<()> {
+    let content: String = HANDICAPS.iter()
+        .enumerate()
+        .map(|(i, h)| format!("{}. {} ({})", i + 1, h.name, h.alias))
+        .collect::<Vec<_>>()
+        .join("\n");
+    
+    display_info_panel(&content, 3, Color::Reset, Some("让子预设:"))
+}
+
 /// 显示历史记录
-pub fn show_history(history: &[String]) -> Result<()> {
-    if history.is_empty() {
+pub fn show_history(movetext: &str) -> Result<()> {
+    if movetext.trim().is_empty() {
         return show_message("没有走子历史");
     }
 
-    let content: String = history.iter()
+    // 着法较多时只显示最后若干行
+    let lines: Vec<&str> = movetext.lines().collect();
+    let content: String = lines[lines.len().saturating_sub(20)..].join("\n");
+    display_info_panel(&content, 3, Color::Reset, Some("走子历史:"))
+}
+
+/// 显示候选着法，标出正在查看的一个
+pub fn show_variations(names: &[String], current: usize) -> Result<()> {
+    if names.len() <= 1 {
+        return show_message("此处没有变着");
+    }
+    let content: String = names.iter()
         .enumerate()
+        .map(|(i, name)| {
+            let mark: &str = if i == current { " <" } else { "" };
+            let main: &str = if i == 0 { " (主线)" } else { "" };
+            format!("{}. {}{}{}", i + 1, name, main, mark)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    display_info_panel(&content, 3, Color::Reset, Some("候选着法:"))
+}
+
+/// 显示对局信息标签
+pub fn show_metadata(tags: &[(String, String)]) -> Result<()> {
+    let content: String = tags.iter()
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect::<Vec<_>>()
+        .join("\n");
+    display_info_panel(&content, 3, Color::Reset, Some("对局信息:"))
+}
+
+/// 显示自动保存的对局列表
+pub fn show_sessions(sessions: &[Session]) -> Result<()> {
+    if sessions.is_empty() {
+        return show_message("没有自动保存的对局");
+    }
+    let content: String = sessions.iter()
         .take(10)
-        .map(|(i, m)| format!("{}. {}", i + 1, m))
+        .enumerate()
+        .map(|(i, session)| {
+            let metadata: GameMetadata = GameMetadata::from_tags(&session.record.tags);
+            let engine: String = session.engine_type.map_or("打谱".to_string(), |e| e.to_string());
+            format!("{}. {} | {} | {}步 | {}", i + 1, format_time(session.saved_at), metadata.summary(), session.line.len(), engine)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    display_info_panel(&content, 3, Color::Reset, Some("自动保存的对局（resume <序号> 恢复）:"))
+}
+
+/// 显示棋谱库查找结果
+pub fn show_db_hits(database: &Database, hits: &[GameHit]) -> Result<()> {
+    if hits.is_empty() {
+        return show_message(&format!("棋谱库（共{}局）中没有符合条件的对局", database.len()));
+    }
+    let content: String = hits.iter()
+        .take(20)
+        .enumerate()
+        .map(|(i, hit)| {
+            let entry: &GameEntry = database.entry(hit.id);
+            let or_unknown = |s: &str| if s.is_empty() { "?".to_string() } else { s.to_string() };
+            let mut line: String = format!(
+                "{}. {} vs {} | {} | {}步 | {}",
+                i + 1,
+                or_unknown(&entry.red),
+                or_unknown(&entry.black),
+                or_unknown(&entry.date),
+                entry.moves.len(),
+                entry.result.name(),
+            );
+            for extra in [&entry.event, &entry.opening] {
+                if !extra.is_empty() {
+                    line.push_str(&format!(" | {}", extra));
+                }
+            }
+            if hit.ply > 0 {
+                line.push_str(&format!(" | 第{}步", hit.ply));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let more: String = if hits.len() > 20 { "，仅显示前20局".to_string() } else { String::new() };
+    display_info_panel(&content, 3, Color::Reset, Some(&format!("找到{}局{}（db load <序号> 载入）:", hits.len(), more)))
+}
+
+/// 显示开局浏览结果，得分率和平均等级分按行棋方计算
+pub fn show_explorer(state: &GameState, continuations: &[Continuation], sort: ExploreSort) -> Result<()> {
+    if continuations.is_empty() {
+        return show_message("棋谱库中没有从此局面继续的对局");
+    }
+    let side: PlayerColor = state.current_player;
+    let content: String = continuations.iter()
+        .take(20)
+        .enumerate()
+        .map(|(i, c)| {
+            let name: String = state.move_to_chinese(&c.mv.to_string()).unwrap_or_else(|_| c.mv.to_string());
+            let score: String = c.score(side).map_or("-".to_string(), |score| format!("{:.1}%", score));
+            let rating: String = c.average_rating().map_or("-".to_string(), |rating| rating.to_string());
+            format!(
+                "{}. {} | {}局 | 红胜{} 和{} 黑胜{} | 得分 {} | 均分 {}",
+                i + 1, name, c.games, c.red_wins, c.draws, c.black_wins, score, rating,
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let order: &str = match sort {
+        ExploreSort::Games => "对局数",
+        ExploreSort::Score => "得分率",
+        ExploreSort::Rating => "等级分",
+    };
+    let total: usize = continuations.iter().map(|c| c.games).sum();
+    display_info_panel(&content, 3, Color::Reset, Some(&format!("开局浏览（{}局，按{}排序，explore <序号> 走入）:", total, order)))
+}
+
+/// 开局库条目列表
+fn format_book_entries(state: &GameState, entries: &[BookEntry]) -> String {
+    let total: u32 = entries.iter().map(|e| e.weight as u32).sum::<u32>().max(1);
+    entries.iter()
+        .enumerate()
+        .map(|(i, e)| {
+            let name: String = state.move_to_chinese(&e.mv.to_string()).unwrap_or_else(|_| e.mv.to_string());
+            let mut line: String = format!(
+                "{}. {} ({}) | 权重 {} ({:.1}%)",
+                i + 1, name, e.mv, e.weight, e.weight as f64 * 100.0 / total as f64,
+            );
+            if e.games > 0 {
+                line.push_str(&format!(" | {}局", e.games));
+            }
+            if let Some(mark) = e.mark {
+                line.push_str(&format!(" | {}", mark.name()));
+            }
+            if let Some(comment) = &e.comment {
+                line.push_str(&format!(" | {}", comment));
+            }
+            line
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// 显示开局库中某一局面的条目
+pub fn show_book_entries(state: &GameState, entries: &[BookEntry]) -> Result<()> {
+    if entries.is_empty() {
+        return show_message("开局库中没有此局面");
+    }
+    display_info_panel(&format_book_entries(state, entries), 3, Color::Reset, Some(&format!("开局库条目（{:016x}）:", state.key())))
+}
+
+/// 显示正在编辑的开局谱中当前局面的着法
+pub fn show_repertoire(path: &str, state: &GameState, entries: &[BookEntry]) -> Result<()> {
+    let content: String = if entries.is_empty() {
+        "此局面还没有着法，使用 rep add <着法> [权重] 添加".to_string()
+    } else {
+        format_book_entries(state, entries)
+    };
+    display_info_panel(&content, 3, Color::Reset, Some(&format!("编辑开局谱 {}:", path)))
+}
+
+/// 显示整局分析：双方准确率、平均损失和错着列表
+pub fn show_game_analysis(analysis: &GameAnalysis) -> Result<()> {
+    let mut lines: Vec<String> = Vec::new();
+    for (color, side) in [(PlayerColor::Red, "红方"), (PlayerColor::Black, "黑方")] {
+        let summary: SideSummary = analysis.summary(color);
+        lines.push(format!(
+            "{}: 准确率 {:.1}% | 平均损失 {:.0} | 缓着 {} | 错着 {} | 败着 {}",
+            side, summary.accuracy, summary.average_loss, summary.inaccuracies, summary.mistakes, summary.blunders,
+        ));
+    }
+    let mut errors: Vec<&MoveAnalysis> = analysis.moves.iter().filter(|m| m.class >= MoveClass::Mistake).collect();
+    errors.sort_by_key(|m| std::cmp::Reverse(m.loss));
+    for m in errors.iter().take(5) {
+        lines.push(format!(
+            "第{}回合 {} {}（{}，损失{}）较好: {}",
+            m.ply / 2 + 1,
+            if m.color == PlayerColor::Red { "红" } else { "黑" },
+            m.name,
+            m.class.name(),
+            m.loss,
+            m.best_name,
+        ));
+    }
+    lines.push("已在棋谱中标注，使用 var 查看引擎给出的变着".to_string());
+    display_info_panel(&lines.join("\n"), 3, Color::Reset, Some("整局分析:"))
+}
+
+/// 从 state 开始按指定格式写出一串着法，遇到无效着法时截止
+fn format_line(state: &GameState, line: &[Move], format: MoveFormat) -> Vec<String> {
+    let mut position: GameState = state.snapshot();
+    let mut names: Vec<String> = Vec::new();
+    for &mv in line {
+        let Ok(name) = position.format_move(mv, format) else { break };
+        names.push(name);
+        if position.apply_move(&mv.to_string()).is_err() {
+            break;
+        }
+    }
+    names
+}
+
+/// 行棋方视角的分数转为红方视角后的文字表示
+fn format_side_eval(side: PlayerColor, score: Option<isize>) -> String {
+    match score {
+        Some(score) if side == PlayerColor::Red => format_eval(score),
+        Some(score) => format_eval(-score),
+        None => "-".to_string(),
+    }
+}
+
+/// 显示提示的候选着法，分数为红方视角；highlight 时在棋盘上标出最佳着法
+pub fn show_hints(state: &GameState, hints: &[Hint], format: MoveFormat, highlight: bool) -> Result<()> {
+    let mut lines: Vec<String> = Vec::new();
+    for (i, hint) in hints.iter().enumerate() {
+        let names: Vec<String> = format_line(state, &hint.line, format);
+        let score: String = format_side_eval(state.current_player, hint.score);
+        let line: String = names.get(1..).map_or(String::new(), |rest| rest.join(" "));
+        lines.push(format!("{}. {} | {} | {}", i + 1, names.first().map_or("?", |n| n.as_str()), score, line));
+    }
+    if highlight && let Some(best) = hints.first() {
+        highlight_move(state, best.mv, Color::Green)?;
+    }
+    display_info_panel(&lines.join("\n"), 3, Color::Green, Some("提示（分数为红方视角）:"))
+}
+
+/// 显示对方的威胁着法和主变，分数为红方视角，并在棋盘上标出威胁着法
+pub fn show_threat(threat: &Threat, format: MoveFormat) -> Result<()> {
+    let names: Vec<String> = format_line(&threat.state, &threat.line, format);
+    let side: &str = match threat.state.current_player {
+        PlayerColor::Red => "红方",
+        PlayerColor::Black => "黑方",
+    };
+    let content: String = format!(
+        "{}威胁: {}\n主变: {}\n若不应对，形势: {}",
+        side,
+        names.first().map_or("?", |n| n.as_str()),
+        names.join(" "),
+        format_side_eval(threat.state.current_player, threat.score),
+    );
+    if let Some(&mv) = threat.line.first() {
+        highlight_move(&threat.state, mv, Color::Magenta)?;
+    }
+    display_info_panel(&content, 3, Color::Magenta, Some("威胁分析:"))
+}
+
+/// 显示一步猜着的结果
+pub fn show_guess_result(result: &GuessResult) -> Result<()> {
+    let text: String = if result.is_exact() {
+        format!("猜中！实战: {}（{}分）", result.actual_name, result.points())
+    } else {
+        format!("实战: {} | 你的着法: {} | 相差{}分（{}分）", result.actual_name, result.guess_name, result.loss, result.points())
+    };
+    show_message(&text)
+}
+
+/// 显示猜着总结：得分率、猜中数和分差最大的几步
+pub fn show_guess_summary(session: &GuessSession) -> Result<()> {
+    let exact: usize = session.results.iter().filter(|r| r.is_exact()).count();
+    let mut content: String = format!(
+        "共猜{}步（全局{}步）| 猜中{}步 | 得分率: {}",
+        session.results.len(),
+        session.total(),
+        exact,
+        session.accuracy().map_or("-".to_string(), |accuracy| format!("{:.1}%", accuracy)),
+    );
+    let misses: Vec<&GuessResult> = session.worst_misses(3);
+    if !misses.is_empty() {
+        content.push_str("\n最大失误:");
+        for miss in misses {
+            content.push_str(&format!(
+                "\n  第{}回合 你的着法 {}，实战 {}，相差{}分",
+                miss.ply / 2 + 1,
+                miss.guess_name,
+                miss.actual_name,
+                miss.loss,
+            ));
+        }
+    }
+    display_info_panel(&content, 3, Color::Reset, Some("猜着总结:"))
+}
+
+/// 列出内置残局和各自的练习记录
+pub fn show_endgames(records: &HashMap<String, EndgameRecord>) -> Result<()> {
+    let content: String = ENDGAMES.iter().enumerate()
+        .map(|(i, endgame)| {
+            let side: &str = match endgame.player {
+                PlayerColor::Red => "执红",
+                PlayerColor::Black => "执黑",
+            };
+            let mut line: String = format!("{}. {} | {}{} | 限{}回合", i + 1, endgame.name, side, endgame.result.name(), endgame.move_limit);
+            if let Some(record) = records.get(endgame.name) {
+                line.push_str(&format!(" | 成功 {}/{}", record.successes, record.attempts));
+                if let Some(best) = record.best {
+                    line.push_str(&format!(" 最快{}回合", best));
+                }
+            }
+            line
+        })
         .collect::<Vec<_>>()
         .join("\n");
+    display_info_panel(&content, 3, Color::Reset, Some("实用残局（endgame <n> [引擎] 开始练习）:"))
+}
+
+/// 显示残局练习的开始信息
+pub fn show_endgame_start(endgame: &Endgame) -> Result<()> {
+    let goal: String = match endgame.result {
+        GameResult::Draw => format!("守和{}回合", endgame.move_limit),
+        _ => format!("{}回合内取胜", endgame.move_limit),
+    };
+    show_message(&format!("残局练习: {}，理论结果{}，目标: {}", endgame.name, endgame.result.name(), goal))
+}
+
+/// 显示当前题目和解题统计
+pub fn show_puzzle(session: &PuzzleSession) -> Result<()> {
+    let puzzle: &Puzzle = session.current();
+    let side: &str = match puzzle.side {
+        PlayerColor::Red => "红方",
+        PlayerColor::Black => "黑方",
+    };
+    let mut content: String = format!("{}先走", side);
+    if puzzle.mate {
+        content.push_str(&format!("，{}步杀", puzzle.solution.len().div_ceil(2)));
+    }
+    if let Some(rating) = puzzle.rating {
+        content.push_str(&format!(" | 等级分: {}", rating));
+    }
+    if !puzzle.themes.is_empty() {
+        content.push_str(&format!(" | 主题: {}", puzzle.themes.join("、")));
+    }
+    let stats: &PuzzleStats = &session.stats;
+    content.push_str(&format!(
+        "\n已解出{}/{}题 | 解出率: {} | 连续解出: {}（最佳{}）",
+        session.solved_count(),
+        session.len(),
+        stats.solve_rate().map_or("-".to_string(), |rate| format!("{:.1}%", rate)),
+        stats.streak,
+        stats.best_streak,
+    ));
+    display_info_panel(&content, 3, Color::Reset, Some(&format!("第{}题（共{}题）:", session.index + 1, session.len())))
+}
+
+/// 显示载入的棋谱信息
+pub fn show_loaded(record: &GameRecord, index: usize, total: usize) -> Result<()> {
+    let mut content: String = format!(
+        "第{}局（共{}局）: {} vs {} | {}步 | {}",
+        index,
+        total,
+        record.tag("Red").unwrap_or("?"),
+        record.tag("Black").unwrap_or("?"),
+        record.moves.len(),
+        record.result().name(),
+    );
+    if let Some(event) = record.tag("Title").or(record.tag("Event")) {
+        content.push_str(&format!("\n{}", event));
+    }
+    content.push_str("\n使用 replay 回放，play <引擎> <颜色> 继续对弈");
+    display_info_panel(&content, 3, Color::Reset, Some("已载入棋谱:"))
+}
+
+/// 显示perft统计结果
+pub fn show_perft(depth: usize, nodes: u64, elapsed: Duration) -> Result<()> {
+    let secs: f64 = elapsed.as_secs_f64();
+    let nps: u64 = if secs > 0.0 { (nodes as f64 / secs) as u64 } else { nodes };
+    show_message(&format!("perft {}: {} 节点 | 用时 {:.3}s | {} 节点/秒", depth, nodes, secs, nps))
+}
+
+/// 显示divide统计结果，每个根着法一项
+pub fn show_divide(state: &GameState, depth: usize, results: &[(Move, u64)], elapsed: Duration) -> Result<()> {
+    let total: u64 = results.iter().map(|(_, n)| n).sum();
+    let mut content: String = results.iter()
+        .map(|(mv, n)| {
+            let zh: String = state.move_to_chinese(&mv.to_string()).unwrap_or_default();
+            format!("{}({}): {}", mv, zh, n)
+        })
+        .collect::<Vec<_>>()
+        .join(" | ");
+    content.push_str(&format!("\n合计: {} 着法 {} 节点 | 用时 {:.3}s", results.len(), total, elapsed.as_secs_f64()));
     
-    display_info_panel(&content, 3, Color::Reset, Some("走子历史:"))
+    display_info_panel(&content, 3, Color::Reset, Some(&format!("divide {}:", depth)))
 }
 
 /// 显示设置成功消息
@@ -495,7 +1239,11 @@ fn wrap_text(text: &str, width: usize) -> Vec<String> {
                 if word.len() > width {
                     let mut remaining = word;
                     while !remaining.is_empty() {
-                        let split_point = width.min(remaining.len());
+                        // 在字符边界处切分，避免截断多字节字符
+                        let mut split_point = width.min(remaining.len());
+                        while !remaining.is_char_boundary(split_point) {
+                            split_point -= 1;
+                        }
                         let (part, rest) = remaining.split_at(split_point);
                         lines.push(part.to_string());
                         remaining = rest;
@@ -1,6 +1,6 @@
 use crate::{
-    cli::interface::Command,
-    game::state::PlayerColor,
+    cli::interface::{Command, DbCommand, GuessCommand, Navigation, PuzzleCommand, RepCommand, RepEdit, SaveFormat, SetupMode},
+    game::{AnalysisLimit, BookOptions, ExploreSort, MoveMark, FenProcessor, GameQuery, Glyph, Handicap, MoveFormat, MAX_PERFT_DEPTH, Piece, PlayerColor, Position, SetupBase, Variant},
     engine::EngineType,
     cli::display::*,
 };
@@ -11,13 +11,16 @@ pub async fn listen_for_commands(tx: UnboundedSender<Command>) {
     let stdin: Stdin = stdin();
     let mut reader: Lines<BufReader<Stdin>> = BufReader::new(stdin).lines();
     let _ = reset_input_prompt();
+
+    // 粘贴模式下累积的多行棋谱文本
+    let mut paste_buffer: Option<String> = None;
     
     loop {
         execute!(
             stdout(),
             MoveTo(0, INPUT_AREA_Y),
             Clear(ClearType::CurrentLine),
-            Print("> "),
+            Print(if paste_buffer.is_some() { "粘贴> " } else { "> " }),
             Show
         ).unwrap();
         stdout().flush().unwrap();
@@ -25,6 +28,24 @@ pub async fn listen_for_commands(tx: UnboundedSender<Command>) {
         match reader.next_line().await {
             Ok(Some(line)) => {
                 let line: String = line.trim().to_string();
+
+                // 粘贴模式：累积到 [/DhtmlXQ] 为止，输入 cancel 放弃
+                if let Some(buffer) = &mut paste_buffer {
+                    if line.eq_ignore_ascii_case("cancel") {
+                        paste_buffer = None;
+                        continue;
+                    }
+                    buffer.push_str(&line);
+                    buffer.push('\n');
+                    if line.contains("[/DhtmlXQ]")
+                        && let Some(text) = paste_buffer.take()
+                        && tx.send(Command::Paste(text)).is_err()
+                    {
+                        break;
+                    }
+                    continue;
+                }
+
                 if line.is_empty() {
                     let _ = reset_input_prompt();
                     continue;
@@ -37,6 +58,18 @@ pub async fn listen_for_commands(tx: UnboundedSender<Command>) {
                     Print(format!("输入: {}\n", line))
                 ).unwrap();
                 stdout().flush().unwrap();
+
+                // paste 命令或直接粘贴的棋谱块
+                if let Some(text) = paste_start(&line) {
+                    if text.contains("[/DhtmlXQ]") {
+                        if tx.send(Command::Paste(text)).is_err() {
+                            break;
+                        }
+                    } else {
+                        paste_buffer = Some(text + "\n");
+                    }
+                    continue;
+                }
                 
                 match parse_command(&line) {
                     Ok(cmd) => {
@@ -66,31 +99,299 @@ fn parse_command(input: &str) -> Result<Command> {
         "new" => {
             let engine_type: EngineType = EngineType::from_str(parts.next().ok_or_else(|| anyhow!("缺少引擎类型"))?)
                 .map_err(|_| anyhow!("无效引擎类型"))?;
-            let color: &str = parts.next().ok_or_else(|| anyhow!("缺少颜色参数"))?;
-            
-            let player_color: PlayerColor = match color.to_lowercase().as_str() {
-                "红" | "red" => PlayerColor::Red,
-                "黑" | "black" => PlayerColor::Black,
-                _ => return Err(anyhow!("无效颜色，使用 '红' 或 '黑'")),
-            };
+            let player_color: PlayerColor = parse_color(parts.next().ok_or_else(|| anyhow!("缺少颜色参数"))?)?;
+
+            // 让子预设
+            let mut remaining_parts: Vec<&str> = parts.collect();
+            let mut handicap: Option<&'static Handicap> = None;
+            if let Some(idx) = remaining_parts.iter().position(|&p| p == "--handicap") {
+                let name: &str = remaining_parts.get(idx + 1).ok_or_else(|| anyhow!("缺少让子预设名称"))?;
+                handicap = Some(Handicap::find(name)?);
+                remaining_parts.drain(idx..idx + 2);
+            }
+
+            // 棋类变体
+            let mut variant: Variant = Variant::Standard;
+            if let Some(idx) = remaining_parts.iter().position(|&p| p == "--variant") {
+                let name: &str = remaining_parts.get(idx + 1).ok_or_else(|| anyhow!("缺少变体名称"))?;
+                variant = Variant::from_str(name)?;
+                remaining_parts.drain(idx..idx + 2);
+            }
 
             // 剩余的部分组合成FEN字符串
-            let remaining_parts: Vec<&str> = parts.collect();
             let fen: Option<String> = if !remaining_parts.is_empty() {
                 Some(remaining_parts.join(" "))
             } else {
                 None
             };
+            if fen.is_some() && handicap.is_some() {
+                return Err(anyhow!("不能同时指定FEN和让子预设"));
+            }
 
-            Ok(Command::NewGame { engine_type, player_color, fen })
+            Ok(Command::NewGame { engine_type, player_color, fen, handicap, variant })
         },
         "move" => {
-            let move_str: String = parts.next().ok_or_else(|| anyhow!("缺少走法"))?.to_string();
-            if move_str.len() != 4 {
-                return Err(anyhow!("走法格式应为4字符，如 'h2e2'"));
+            let move_str: String = parts.collect::<Vec<_>>().join("");
+            if move_str.is_empty() {
+                return Err(anyhow!("缺少走法"));
             }
             Ok(Command::MakeMove(move_str))
         },
+        "save" => {
+            let path: String = parts.next().ok_or_else(|| anyhow!("缺少文件路径"))?.to_string();
+            let format: SaveFormat = match parts.next() {
+                Some(f) if f.eq_ignore_ascii_case("ubb") || f.eq_ignore_ascii_case("dhtmlxq") => SaveFormat::DhtmlXq,
+                Some(f) => SaveFormat::Pgn(MoveFormat::from_str(f)?),
+                None => SaveFormat::Pgn(MoveFormat::default()),
+            };
+            Ok(Command::Save { path, format })
+        },
+        "load" => {
+            let path: String = parts.next().ok_or_else(|| anyhow!("缺少文件路径"))?.to_string();
+            let index: usize = match parts.next() {
+                Some(n) => n.parse().ok().filter(|&n| n > 0).ok_or_else(|| anyhow!("序号必须是正整数"))?,
+                None => 1,
+            };
+            Ok(Command::Load { path, index })
+        },
+        "replay" => {
+            let delay: u64 = match parts.next() {
+                Some(ms) => ms.parse().map_err(|_| anyhow!("间隔必须是毫秒数"))?,
+                None => 800,
+            };
+            Ok(Command::Replay(delay))
+        },
+        "play" => {
+            let engine_type: EngineType = EngineType::from_str(parts.next().ok_or_else(|| anyhow!("缺少引擎类型"))?)
+                .map_err(|_| anyhow!("无效引擎类型"))?;
+            let player_color: PlayerColor = parse_color(parts.next().ok_or_else(|| anyhow!("缺少颜色参数"))?)?;
+            Ok(Command::Play { engine_type, player_color })
+        },
+        "first" => Ok(Command::Navigate(Navigation::First)),
+        "prev" => Ok(Command::Navigate(Navigation::Prev)),
+        "next" => Ok(Command::Navigate(Navigation::Next)),
+        "last" => Ok(Command::Navigate(Navigation::Last)),
+        "goto" => {
+            let ply: usize = parts.next().ok_or_else(|| anyhow!("缺少步数"))?
+                .parse().map_err(|_| anyhow!("步数必须是非负整数"))?;
+            Ok(Command::Navigate(Navigation::Ply(ply)))
+        },
+        "analyze" => match parts.next() {
+            Some(arg) if arg.eq_ignore_ascii_case("game") => {
+                let limit: AnalysisLimit = match (parts.next(), parts.next()) {
+                    (None, _) => AnalysisLimit::default(),
+                    (Some("depth"), Some(n)) => AnalysisLimit::Depth(n.parse().ok().filter(|&n| n > 0).ok_or_else(|| anyhow!("深度必须是正整数"))?),
+                    (Some("time"), Some(n)) => AnalysisLimit::Time(n.parse().ok().filter(|&n| n > 0).ok_or_else(|| anyhow!("时间必须是正整数（毫秒）"))?),
+                    _ => return Err(anyhow!("用法: analyze game [depth <深度>|time <毫秒>]")),
+                };
+                Ok(Command::AnalyzeGame(limit))
+            },
+            engine => {
+                let engine_type: Option<EngineType> = engine
+                    .map(|e| EngineType::from_str(e).map_err(|_| anyhow!("无效引擎类型")))
+                    .transpose()?;
+                Ok(Command::Analyze(engine_type))
+            },
+        },
+        "hint" => {
+            let mut count: usize = 3;
+            let mut highlight: bool = true;
+            for arg in parts {
+                if arg.eq_ignore_ascii_case("nohl") {
+                    highlight = false;
+                } else {
+                    count = arg.parse().ok().filter(|&n| n > 0).ok_or_else(|| anyhow!("用法: hint [候选着法数] [nohl]"))?;
+                }
+            }
+            Ok(Command::Hint { count, highlight })
+        },
+        "threat" => Ok(Command::Threat),
+        "branch" => {
+            let play: Option<(EngineType, PlayerColor)> = match parts.next() {
+                Some(engine) => {
+                    let engine_type: EngineType = EngineType::from_str(engine).map_err(|_| anyhow!("无效引擎类型"))?;
+                    let player_color: PlayerColor = parse_color(parts.next().ok_or_else(|| anyhow!("缺少颜色参数"))?)?;
+                    Some((engine_type, player_color))
+                }
+                None => None,
+            };
+            Ok(Command::Branch(play))
+        },
+        "comment" => {
+            let text: String = parts.collect::<Vec<_>>().join(" ");
+            Ok(Command::Comment(Some(text).filter(|t| !t.is_empty())))
+        },
+        "mark" => {
+            let glyph: Option<Glyph> = match parts.next().ok_or_else(|| anyhow!("缺少评注符号"))? {
+                "none" => None,
+                symbol => Some(Glyph::from_str(symbol)?),
+            };
+            Ok(Command::Mark(glyph))
+        },
+        "var" | "variation" => {
+            let index: Option<usize> = parts.next()
+                .map(|n| n.parse().map_err(|_| anyhow!("变着序号必须是正整数")))
+                .transpose()?;
+            Ok(Command::Variation(index))
+        },
+        "promote" => Ok(Command::Promote),
+        "delete" => Ok(Command::DeleteBranch),
+        "tag" => {
+            let key: Option<String> = parts.next().map(|k| k.to_string());
+            let value: String = parts.collect::<Vec<_>>().join(" ");
+            Ok(Command::Tag { key, value })
+        },
+        "sessions" => Ok(Command::Sessions),
+        "explore" => {
+            let (sort, step) = match parts.next() {
+                None => (None, None),
+                Some(arg) => match arg.parse::<usize>() {
+                    Ok(n) if n > 0 => (None, Some(n)),
+                    Ok(_) => return Err(anyhow!("序号必须是正整数")),
+                    Err(_) => (Some(ExploreSort::from_str(arg)?), None),
+                },
+            };
+            Ok(Command::Explore { sort, step })
+        },
+        "book" => match parts.next().map(|s| s.to_lowercase()).as_deref() {
+            Some("build") => {
+                let output: String = parts.next().ok_or_else(|| anyhow!("缺少开局库输出路径"))?.to_string();
+                let mut options: BookOptions = BookOptions::default();
+                let mut inputs: Vec<String> = Vec::new();
+                let mut self_play: usize = 0;
+                let mut think_time: Option<usize> = None;
+                while let Some(arg) = parts.next() {
+                    let mut value = || parts.next().ok_or_else(|| anyhow!("{} 缺少取值", arg));
+                    match arg {
+                        "--min-games" => options.min_games = value()?.parse().map_err(|_| anyhow!("对局数必须是整数"))?,
+                        "--min-score" => options.min_score = value()?.parse().map_err(|_| anyhow!("得分率必须是数字"))?,
+                        "--max-ply" => options.max_ply = value()?.parse().map_err(|_| anyhow!("半回合数必须是整数"))?,
+                        "--self-play" => self_play = value()?.parse().map_err(|_| anyhow!("自对弈局数必须是整数"))?,
+                        "--time" => think_time = Some(value()?.parse().map_err(|_| anyhow!("思考时间必须是毫秒数"))?),
+                        _ => inputs.push(arg.to_string()),
+                    }
+                }
+                if inputs.is_empty() && self_play == 0 {
+                    return Err(anyhow!("缺少棋谱文件或 --self-play 局数"));
+                }
+                Ok(Command::BookBuild { output, inputs, options, self_play, think_time })
+            },
+            Some("dump") => {
+                let path: String = parts.next().ok_or_else(|| anyhow!("缺少开局库路径"))?.to_string();
+                let fen: String = parts.collect::<Vec<_>>().join(" ");
+                Ok(Command::BookDump { path, fen: (!fen.is_empty()).then_some(fen) })
+            },
+            _ => Err(anyhow!("用法: book build <输出> [棋谱]... [--self-play n] 或 book dump <开局库> [FEN]")),
+        },
+        "rep" | "repertoire" => {
+            let sub: String = parts.next().map(|s| s.to_lowercase()).unwrap_or_else(|| "show".to_string());
+            let mut mv = || parts.next().map(|s| s.to_string()).ok_or_else(|| anyhow!("缺少着法或序号"));
+            let rep_command: RepCommand = match sub.as_str() {
+                "open" => RepCommand::Open(parts.next().ok_or_else(|| anyhow!("缺少文件路径"))?.to_string()),
+                "show" => RepCommand::Edit(RepEdit::Show),
+                "add" => {
+                    let mv: String = mv()?;
+                    let weight: u16 = match parts.next() {
+                        Some(w) => w.parse().map_err(|_| anyhow!("权重必须是0-65535的整数"))?,
+                        None => 1,
+                    };
+                    RepCommand::Edit(RepEdit::Add { mv, weight })
+                },
+                "remove" | "rm" => RepCommand::Edit(RepEdit::Remove(mv()?)),
+                "weight" => {
+                    let mv: String = mv()?;
+                    let weight: u16 = parts.next().ok_or_else(|| anyhow!("缺少权重"))?
+                        .parse().map_err(|_| anyhow!("权重必须是0-65535的整数"))?;
+                    RepCommand::Edit(RepEdit::Weight { mv, weight })
+                },
+                "comment" => {
+                    let mv: String = mv()?;
+                    let text: String = parts.collect::<Vec<_>>().join(" ");
+                    RepCommand::Edit(RepEdit::Comment { mv, text: (!text.is_empty()).then_some(text) })
+                },
+                "mark" => {
+                    let mv: String = mv()?;
+                    let mark: Option<MoveMark> = match parts.next().ok_or_else(|| anyhow!("缺少标记"))? {
+                        "none" => None,
+                        mark => Some(MoveMark::from_str(mark)?),
+                    };
+                    RepCommand::Edit(RepEdit::Mark { mv, mark })
+                },
+                "save" => RepCommand::Save(parts.next().map(|s| s.to_string())),
+                "close" => RepCommand::Close,
+                other => return Err(anyhow!("未知的开局谱命令: {}", other)),
+            };
+            Ok(Command::Repertoire(rep_command))
+        },
+        "puzzle" => {
+            let puzzle_command: PuzzleCommand = match parts.next() {
+                None => PuzzleCommand::Show,
+                Some("next") => PuzzleCommand::Next,
+                Some("stop") => PuzzleCommand::Stop,
+                Some(path) => {
+                    let index: Option<usize> = match parts.next() {
+                        Some(n) => Some(n.parse().ok().filter(|&n| n > 0).ok_or_else(|| anyhow!("题号必须是正整数"))?),
+                        None => None,
+                    };
+                    PuzzleCommand::Start { path: path.to_string(), index }
+                },
+            };
+            Ok(Command::Puzzle(puzzle_command))
+        },
+        "guess" => match parts.next() {
+            Some("stop") => Ok(Command::Guess(GuessCommand::Stop)),
+            Some(path) => {
+                let color: PlayerColor = parse_color(parts.next().ok_or_else(|| anyhow!("缺少猜着方颜色"))?)?;
+                let index: usize = match parts.next() {
+                    Some(n) => n.parse().ok().filter(|&n| n > 0).ok_or_else(|| anyhow!("局号必须是正整数"))?,
+                    None => 1,
+                };
+                Ok(Command::Guess(GuessCommand::Start { path: path.to_string(), index, color }))
+            },
+            None => Err(anyhow!("用法: guess <棋谱文件> <red|black> [局号] 或 guess stop")),
+        },
+        "endgame" => match parts.next() {
+            None => Ok(Command::Endgames),
+            Some(name) => {
+                let engine_type: EngineType = match parts.next() {
+                    Some(engine) => EngineType::from_str(engine).map_err(|_| anyhow!("无效引擎类型"))?,
+                    None => EngineType::Pikafish,
+                };
+                Ok(Command::Endgame { name: name.to_string(), engine_type })
+            },
+        },
+        "train" => match parts.next() {
+            Some("stop") => Ok(Command::TrainStop),
+            Some(path) => {
+                let color: PlayerColor = parse_color(parts.next().ok_or_else(|| anyhow!("缺少训练方颜色"))?)?;
+                Ok(Command::Train { path: path.to_string(), color })
+            },
+            None => Err(anyhow!("用法: train <开局谱> <red|black> 或 train stop")),
+        },
+        "db" => {
+            let db_command: DbCommand = match parts.next().map(|s| s.to_lowercase()).as_deref() {
+                None | Some("info") => DbCommand::Info,
+                Some("import") => DbCommand::Import(parts.next().ok_or_else(|| anyhow!("缺少文件路径"))?.to_string()),
+                Some("find") => DbCommand::Find(GameQuery::parse(&parts.collect::<Vec<_>>())?),
+                Some("position" | "pos") => DbCommand::Position,
+                Some("load") => {
+                    let index: usize = match parts.next() {
+                        Some(n) => n.parse().ok().filter(|&n| n > 0).ok_or_else(|| anyhow!("序号必须是正整数"))?,
+                        None => 1,
+                    };
+                    DbCommand::Load(index)
+                },
+                Some(other) => return Err(anyhow!("未知的棋谱库命令: {}，可用 import find position load", other)),
+            };
+            Ok(Command::Database(db_command))
+        },
+        "resume" => {
+            let index: usize = match parts.next() {
+                Some(n) => n.parse().ok().filter(|&n| n > 0).ok_or_else(|| anyhow!("序号必须是正整数"))?,
+                None => 1,
+            };
+            Ok(Command::Resume(index))
+        },
         "board" => Ok(Command::ShowBoard),
         "history" => Ok(Command::History),
         "set" => {
@@ -101,10 +402,105 @@ fn parse_command(input: &str) -> Result<Command> {
                 Ok(Command::SetOption { name, value: None })
             }
         }
+        "setup" => {
+            let base: Option<SetupBase> = parts.next().map(SetupBase::from_str).transpose()?;
+            Ok(Command::Setup(base))
+        },
+        "put" => {
+            let piece_str: &str = parts.next().ok_or_else(|| anyhow!("缺少棋子，如 'R' 或 'r'"))?;
+            let mut chars: std::str::Chars<'_> = piece_str.chars();
+            let piece: Piece = match (chars.next(), chars.next()) {
+                (Some(c), None) => FenProcessor::char_to_piece(c)?,
+                _ => return Err(anyhow!("棋子应为单个FEN字符，如 'R' 或 'r'")),
+            };
+            let pos: Position = Position::from_str(parts.next().ok_or_else(|| anyhow!("缺少格子，如 'a9'"))?)?;
+            Ok(Command::Put { piece, pos })
+        },
+        "clear" => {
+            let pos: Position = Position::from_str(parts.next().ok_or_else(|| anyhow!("缺少格子，如 'e5'"))?)?;
+            Ok(Command::ClearSquare(pos))
+        },
+        "side" => {
+            let color: PlayerColor = parse_color(parts.next().ok_or_else(|| anyhow!("缺少颜色参数"))?)?;
+            Ok(Command::SetSide(color))
+        },
+        "done" => {
+            let engine_type: EngineType = EngineType::from_str(parts.next().ok_or_else(|| anyhow!("缺少引擎类型"))?)
+                .map_err(|_| anyhow!("无效引擎类型"))?;
+            let mode: SetupMode = match parts.next().ok_or_else(|| anyhow!("缺少模式，使用 red/black/analyze"))? {
+                "analyze" | "分析" => SetupMode::Analyze,
+                color => SetupMode::Play(parse_color(color)?),
+            };
+            Ok(Command::SetupDone { engine_type, mode })
+        },
+        "cancel" => Ok(Command::SetupCancel),
+        "perft" | "divide" => {
+            let depth: usize = parts.next().ok_or_else(|| anyhow!("缺少深度参数"))?
+                .parse().map_err(|_| anyhow!("深度必须是正整数"))?;
+            if depth > MAX_PERFT_DEPTH {
+                return Err(anyhow!("深度最大为{}", MAX_PERFT_DEPTH));
+            }
+            if cmd.eq_ignore_ascii_case("perft") {
+                Ok(Command::Perft(depth))
+            } else {
+                Ok(Command::Divide(depth))
+            }
+        },
         "listengines" => Ok(Command::ListEngines),
+        "handicaps" => Ok(Command::ListHandicaps),
         "reverse" | "flip" => Ok(Command::Reverse),
         "help" => Ok(Command::Help),
         "quit" | "exit" => Ok(Command::Quit),
         _ => Err(anyhow!("未知命令: {}", cmd)),
     }
-}
\ No newline at end of file
+}
+
+/// 判断输入是否开始粘贴 DhtmlXQ 棋谱块，返回已输入的部分
+fn paste_start(line: &str) -> Option<String> {
+    if line.starts_with("[DhtmlXQ") {
+        return Some(line.to_string());
+    }
+    let mut parts: std::str::SplitN<'_, char> = line.splitn(2, ' ');
+    match parts.next() {
+        Some(cmd) if cmd.eq_ignore_ascii_case("paste") => Some(parts.next().unwrap_or("").trim().to_string()),
+        _ => None,
+    }
+}
+
+/// 解析颜色参数
+fn parse_color(color: &str) -> Result<PlayerColor> {
+    match color.to_lowercase().as_str() {
+        "红" | "red" => Ok(PlayerColor::Red),
+        "黑" | "black" => Ok(PlayerColor::Black),
+        _ => Err(anyhow!("无效颜色，使用 '红' 或 '黑'")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::PieceKind;
+
+    #[test]
+    fn parse_setup_commands() {
+        let Ok(Command::Put { piece, pos }) = parse_command("put r A9") else { panic!("应为摆子命令") };
+        assert_eq!((piece.color, piece.kind, pos), (PlayerColor::Black, PieceKind::Rook, Position { row: 9, col: 0 }));
+        assert!(matches!(parse_command("clear e5"), Ok(Command::ClearSquare(Position { row: 5, col: 4 }))));
+        assert!(matches!(parse_command("setup"), Ok(Command::Setup(None))));
+        assert!(matches!(parse_command("setup current"), Ok(Command::Setup(Some(SetupBase::Current)))));
+
+        assert!(parse_command("put RR e0").is_err());
+        assert!(parse_command("put X a0").is_err());
+        assert!(parse_command("put R j0").is_err());
+        assert!(parse_command("put R").is_err());
+        assert!(parse_command("clear e10").is_err());
+    }
+
+    #[test]
+    fn parse_perft_depth() {
+        assert!(matches!(parse_command("perft 3"), Ok(Command::Perft(3))));
+        assert!(matches!(parse_command(&format!("divide {}", MAX_PERFT_DEPTH)), Ok(Command::Divide(_))));
+        assert!(parse_command(&format!("perft {}", MAX_PERFT_DEPTH + 1)).is_err());
+        assert!(parse_command("perft").is_err());
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{
     cli::{display, input}, 
     engine::{EngineManager, EngineProtocol, EngineType}, 
-    game::{GameManager, GameState, PlayerColor}
+    game::{divide, AnalysisLimit, GameAnalysis, Hint, Threat, explore, Answer, Book, BookBuilder, BookOptions, MoveMark, SELF_PLAY_LINES, self_play_game, GuessResult, GuessSession, Endgame, EndgameAttempt, EndgameOutcome, load_endgame_records, PuzzleAnswer, PuzzleSession, PuzzleStats, Trainer, parse_dhtmlxq, read_game_file, perft, Continuation, Database, ExploreSort, FenProcessor, GameHit, GameQuery, Session, GameManager, GameRecord, GameState, Glyph, Handicap, Move, MoveFormat, Piece, PlayerColor, Position, SetupBase, Variant}
 };
 use crate::utils::*;
 
@@ -11,19 +11,169 @@ pub enum Command {
     NewGame { 
         engine_type: EngineType, 
         player_color: PlayerColor,
-        fen: Option<String>
+        fen: Option<String>,
+        handicap: Option<&'static Handicap>,
+        variant: Variant,
     },
     MakeMove(String),
+    Save { path: String, format: SaveFormat },
+    Load { path: String, index: usize },
+    Paste(String),
+    Replay(u64),
+    Navigate(Navigation),
+    Analyze(Option<EngineType>),
+    /// 引擎逐步分析当前路线
+    AnalyzeGame(AnalysisLimit),
+    /// 引擎给出正在查看的局面的若干候选着法，highlight 表示在棋盘上标出最佳着法
+    Hint { count: usize, highlight: bool },
+    /// 引擎分析假如对方连走一步时的威胁
+    Threat,
+    Branch(Option<(EngineType, PlayerColor)>),
+    /// 设置正在查看的着法的注释，None 表示清除
+    Comment(Option<String>),
+    /// 设置正在查看的着法的评注符号，None 表示清除
+    Mark(Option<Glyph>),
+    /// 列出候选着法或切换到指定变着
+    Variation(Option<usize>),
+    Promote,
+    DeleteBranch,
+    /// 查看对局信息，或设置指定标签，值为空表示清除
+    Tag { key: Option<String>, value: String },
+    /// 列出自动保存的对局
+    Sessions,
+    /// 恢复第 n 个自动保存的对局（从1开始，最近的为1）
+    Resume(usize),
+    /// 本地棋谱库操作
+    Database(DbCommand),
+    /// 开局浏览：按指定方式排序列出后续着法，或走入上次列出的第 n 个着法（从1开始）
+    Explore { sort: Option<ExploreSort>, step: Option<usize> },
+    /// 由棋谱文件和引擎自对弈的对局生成开局库，think_time 为自对弈每步思考时间（毫秒）
+    BookBuild { output: String, inputs: Vec<String>, options: BookOptions, self_play: usize, think_time: Option<usize> },
+    /// 列出开局库中指定局面的条目，None 表示正在查看的局面
+    BookDump { path: String, fen: Option<String> },
+    /// 开局谱编辑
+    Repertoire(RepCommand),
+    /// 用开局谱训练指定一方的应着
+    Train { path: String, color: PlayerColor },
+    TrainStop,
+    /// 解题模式
+    Puzzle(PuzzleCommand),
+    /// 猜着训练
+    Guess(GuessCommand),
+    /// 列出内置残局
+    Endgames,
+    /// 与引擎对弈练习指定残局，name 为序号（从1开始）或名称
+    Endgame { name: String, engine_type: EngineType },
+    Play { engine_type: EngineType, player_color: PlayerColor },
     ShowBoard,
     History,
     SetOption { name: String, value: Option<String> },
+    Setup(Option<SetupBase>),
+    Put { piece: Piece, pos: Position },
+    ClearSquare(Position),
+    SetSide(PlayerColor),
+    SetupDone { engine_type: EngineType, mode: SetupMode },
+    SetupCancel,
+    Perft(usize),
+    Divide(usize),
     ListEngines,
+    ListHandicaps,
     Reverse,
     Help,
     Quit,
     Error(String),
 }
 
+/// 摆棋完成后的开局方式
+#[derive(Debug, Clone, Copy)]
+pub enum SetupMode {
+    /// 执指定颜色与引擎对弈
+    Play(PlayerColor),
+    /// 由引擎分析摆出的局面
+    Analyze,
+}
+
+/// 棋谱库命令
+#[derive(Debug, Clone)]
+pub enum DbCommand {
+    /// 显示库中对局数
+    Info,
+    /// 导入棋谱文件
+    Import(String),
+    /// 按条件查找对局
+    Find(GameQuery),
+    /// 查找出现过正在查看的局面的对局
+    Position,
+    /// 载入上次查找结果中的第 n 局（从1开始）
+    Load(usize),
+}
+
+/// 开局谱文件命令
+#[derive(Debug, Clone)]
+pub enum RepCommand {
+    /// 打开开局库或开局谱文件进入编辑，文件不存在时新建
+    Open(String),
+    /// 保存，可以另存为其他文件
+    Save(Option<String>),
+    Close,
+    /// 修改正在查看的局面
+    Edit(RepEdit),
+}
+
+/// 开局谱编辑命令，着法可以是序号（从1开始）或任意记谱格式
+#[derive(Debug, Clone)]
+pub enum RepEdit {
+    /// 列出正在查看的局面的着法
+    Show,
+    Add { mv: String, weight: u16 },
+    Remove(String),
+    Weight { mv: String, weight: u16 },
+    /// 设置注释，None 表示清除
+    Comment { mv: String, text: Option<String> },
+    /// 设置标记，None 表示清除
+    Mark { mv: String, mark: Option<MoveMark> },
+}
+
+/// 解题命令
+#[derive(Debug, Clone)]
+pub enum PuzzleCommand {
+    /// 打开题库，从第 n 题（从1开始）或第一道没有解出过的题开始
+    Start { path: String, index: Option<usize> },
+    /// 显示当前题目和统计
+    Show,
+    /// 换下一道没有解出过的题
+    Next,
+    Stop,
+}
+
+/// 猜着训练命令
+#[derive(Debug, Clone)]
+pub enum GuessCommand {
+    /// 载入棋谱文件中的第 index 局（从1开始），猜测 color 一方的着法
+    Start { path: String, index: usize, color: PlayerColor },
+    Stop,
+}
+
+/// 复盘浏览的跳转目标
+#[derive(Debug, Clone, Copy)]
+pub enum Navigation {
+    First,
+    Prev,
+    Next,
+    Last,
+    /// 跳转到指定半回合
+    Ply(usize),
+}
+
+/// 棋谱保存格式
+#[derive(Debug, Clone, Copy)]
+pub enum SaveFormat {
+    /// PGN，着法使用指定记谱格式
+    Pgn(MoveFormat),
+    /// DhtmlXQ 论坛棋谱块
+    DhtmlXq,
+}
+
 /// 运行交互式主循环
 pub async fn run_interactive_loop() -> Result<()> {
     // 初始化显示
@@ -41,47 +191,896 @@ pub async fn run_interactive_loop() -> Result<()> {
     
     // 初始化游戏管理器
     let mut game_manager: Option<GameManager> = None;
+
+    // 摆棋模式下正在编辑的局面
+    let mut setup_state: Option<GameState> = None;
+
+    // 棋谱库在首次使用时打开，之后沿用已读入的索引
+    let mut database: Option<Database> = None;
+
+    // 最近一次棋谱库查找的结果
+    let mut db_hits: Vec<GameHit> = Vec::new();
+
+    // 开局浏览的排序方式和上次列出的后续着法
+    let mut explore_sort: ExploreSort = ExploreSort::default();
+    let mut explore_moves: Vec<Move> = Vec::new();
+
+    // 正在编辑的开局谱及其文件路径
+    let mut repertoire: Option<(String, Book)> = None;
+
+    // 正在进行的开局谱训练
+    let mut trainer: Option<Trainer> = None;
+
+    // 正在进行的解题
+    let mut puzzles: Option<PuzzleSession> = None;
+
+    // 正在进行的猜着训练
+    let mut guess: Option<GuessSession> = None;
+
+    // 正在进行的残局练习
+    let mut endgame: Option<EndgameAttempt> = None;
+
+    // 提示恢复上次未完成的对局
+    if let Some(latest) = Session::list().ok().and_then(|sessions| sessions.into_iter().next()) {
+        display::show_message(&format!(
+            "发现自动保存的对局（{}，{}步），输入 'resume' 继续，'sessions' 查看全部",
+            format_time(latest.saved_at),
+            latest.line.len(),
+        ))?;
+        display::reset_input_prompt()?;
+    }
     
     // 主事件循环
     while let Some(cmd) = rx.recv().await {
         // 先清空消息区域
         display::clear_message_area()?;
         // 渲染视图
-        display::render_view(game_manager.as_ref())?;
+        if let Some(state) = &setup_state {
+            display::render_setup(state)?;
+        } else {
+            display::render_view(game_manager.as_ref())?;
+        }
         
         match cmd {
-            Command::NewGame { engine_type, player_color, fen } => {
-                match handle_new_game(&engine_manager, engine_type, player_color, fen).await {
+            Command::NewGame { engine_type, player_color, fen, handicap, variant } => {
+                match handle_new_game(&engine_manager, engine_type, player_color, fen, handicap, variant).await {
                     Ok(game) => {
                         game_manager = Some(game);
+                        setup_state = None;
                         display::render_view(game_manager.as_ref())?;
                     }
                     Err(e) => display::show_error(&e.to_string())?,
                 }
             },
             Command::MakeMove(move_str) => {
-                if let Some(game) = &mut game_manager {
-                    if let Err(e) = game.player_move(&move_str).await {
-                        display::show_error(&e.to_string())?;
-                        continue;
+                // 训练中的着法由训练器检查
+                if let Some(active) = &mut trainer
+                    && let Some(game) = &game_manager
+                    && active.owns(game)
+                {
+                    let state: GameState = game.view_state().clone();
+                    let (answer, next) = match active.answer(game, &move_str) {
+                        Ok(result) => result,
+                        Err(e) => {
+                            display::show_error(&e.to_string())?;
+                            continue;
+                        }
+                    };
+                    let mut text: String = match answer {
+                        Answer::Correct => "正确".to_string(),
+                        Answer::Wrong(moves) => {
+                            let names: Vec<String> = moves.iter()
+                                .map(|mv| state.move_to_chinese(&mv.to_string()).unwrap_or_else(|_| mv.to_string()))
+                                .collect();
+                            format!("错误，准备的着法: {}", names.join("、"))
+                        }
+                    };
+                    let finished: bool = next.is_none();
+                    if finished {
+                        text.push_str(&format!("\n{}", training_summary(active)));
+                    } else {
+                        text.push_str(&format!("，剩余{}题", active.remaining()));
+                    }
+                    if let Some(next) = next {
+                        game_manager = Some(next);
                     }
+                    if finished {
+                        trainer = None;
+                    }
+                    display::render_view(game_manager.as_ref())?;
+                    display::show_message(&text)?;
+                    continue;
+                }
+
+                // 猜着训练中的着法由引擎评估后走出实战着法
+                if let Some(active) = &mut guess
+                    && let Some(game) = &mut game_manager
+                    && active.owns(game)
+                {
+                    display::show_message("引擎正在评估...")?;
+                    let result: Result<GuessResult> = active.answer(game, &move_str).await;
+                    display::render_view(game_manager.as_ref())?;
+                    match result {
+                        Ok(result) => display::show_guess_result(&result)?,
+                        Err(e) => {
+                            display::show_error(&e.to_string())?;
+                            continue;
+                        }
+                    }
+                    if active.is_finished() {
+                        display::show_guess_summary(active)?;
+                        let _ = active.close().await;
+                        guess = None;
+                    }
+                    continue;
+                }
+
+                // 解题中的着法由解题模式检查并应着
+                if let Some(active) = &mut puzzles
+                    && let Some(game) = &mut game_manager
+                    && active.owns(game)
+                {
+                    let state: GameState = game.state.clone();
+                    let answer: PuzzleAnswer = match active.answer(game, &move_str).await {
+                        Ok(answer) => answer,
+                        Err(e) => {
+                            display::show_error(&e.to_string())?;
+                            continue;
+                        }
+                    };
+                    display::render_view(game_manager.as_ref())?;
+                    match answer {
+                        PuzzleAnswer::Continue(_) => display::show_message("正确，请继续")?,
+                        PuzzleAnswer::Solved => {
+                            display::show_puzzle(active)?;
+                            display::show_message("解出！输入 'puzzle next' 继续下一题")?;
+                        },
+                        PuzzleAnswer::Failed(solution) => {
+                            let line: Vec<String> = solution.iter().map(|mv| mv.to_string()).collect();
+                            let names: String = state.pv_to_chinese(&line).map_or_else(|_| line.join(" "), |names| names.join(" "));
+                            display::show_puzzle(active)?;
+                            let text: String = if names.is_empty() { "错误".to_string() } else { format!("错误，正解: {}", names) };
+                            display::show_message(&format!("{}\n输入 'puzzle next' 继续下一题", text))?;
+                        },
+                    }
+                    continue;
+                }
+
+                if let Some(game) = &mut game_manager
+                    && let Err(e) = game.player_move(&move_str).await
+                {
+                    display::show_error(&e.to_string())?;
+                    continue;
                 }
                 
-                if game_manager.is_some() {
+                if let Some(game) = &game_manager {
+                    // 引擎思考前先保存，引擎崩溃时不丢失玩家着法
+                    if let Err(e) = game.autosave() {
+                        log_warn!(e);
+                    }
                     display::render_view(game_manager.as_ref())?;
                     
-                    if let Some(game) = &mut game_manager {
+                    if let Some(game) = &mut game_manager
+                        && game.is_engine_turn()
+                    {
                         display::show_message("引擎正在思考...")?;
                         if let Err(e) = game.engine_move().await {
                             display::show_error(&e.to_string())?;
                             continue;
                         }
+                        display::render_view(game_manager.as_ref())?;
+                    }
+
+                    // 残局练习达到结论时记录结果
+                    if let Some(attempt) = &endgame
+                        && let Some(game) = &game_manager
+                        && attempt.owns(game)
+                        && let Some(outcome) = attempt.check(game)
+                    {
+                        let text: String = match outcome {
+                            EndgameOutcome::Achieved(moves) => format!("残局练习成功！用了{}回合", moves),
+                            EndgameOutcome::Failed(reason) => format!("残局练习失败: {}", reason),
+                        };
+                        match attempt.record(outcome) {
+                            Ok(record) => display::show_message(&format!("{}（累计成功 {}/{}）", text, record.successes, record.attempts))?,
+                            Err(e) => display::show_error(&format!("{}，但保存记录失败: {}", text, e))?,
+                        }
+                        endgame = None;
                     }
-                    display::render_view(game_manager.as_ref())?;
                 } else {
                     display::show_error("请先使用 'new' 命令开始游戏")?;
                 }
             },
+            Command::Save { path, format } => {
+                let Some(game) = &game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                let (text, name) = match format {
+                    SaveFormat::Pgn(move_format) => (game.to_record().to_pgn(move_format), move_format.name()),
+                    SaveFormat::DhtmlXq => (game.to_record().to_dhtmlxq(), "DhtmlXQ"),
+                };
+                let result: Result<()> = text
+                    .and_then(|text| std::fs::write(&path, text).with_context(|| format!("无法写入文件 {}", path)));
+                match result {
+                    Ok(()) => display::show_message(&format!("已保存到 {}（{}格式）", path, name))?,
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Load { path, index } => {
+                match handle_load(&path, index, &engine_manager.player_name) {
+                    Ok((game, record, total)) => {
+                        if let Some(mut old_game) = game_manager.replace(game) {
+                            let _ = old_game.quit().await;
+                        }
+                        setup_state = None;
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_loaded(&record, index, total)?;
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Paste(text) => {
+                match parse_dhtmlxq(&text).and_then(|record| Ok((GameManager::from_record(&record, &engine_manager.player_name)?, record))) {
+                    Ok((game, record)) => {
+                        if let Some(mut old_game) = game_manager.replace(game) {
+                            let _ = old_game.quit().await;
+                        }
+                        setup_state = None;
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_loaded(&record, 1, 1)?;
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Navigate(target) => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                let ply: usize = match target {
+                    Navigation::First => 0,
+                    Navigation::Prev => game.view_ply().saturating_sub(1),
+                    Navigation::Next => (game.view_ply() + 1).min(game.line_end()),
+                    Navigation::Last => game.line_end(),
+                    Navigation::Ply(ply) => ply,
+                };
+                match game.goto(ply) {
+                    Ok(()) => display::render_view(game_manager.as_ref())?,
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Analyze(engine_type) => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                let result: Result<()> = async {
+                    if let Some(engine_type) = engine_type {
+                        let mut engine: Box<dyn EngineProtocol> = engine_manager.create_engine_instance(&engine_type).await?;
+                        engine.init().await?;
+                        game.set_engine(engine, engine_type, &engine_manager.display_name(&engine_type)).await?;
+                    }
+                    display::show_message("引擎正在分析...")?;
+                    game.analyze().await
+                }.await;
+                display::render_view(game_manager.as_ref())?;
+                if let Err(e) = result {
+                    display::show_error(&e.to_string())?;
+                }
+            },
+            Command::AnalyzeGame(limit) => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                let result: Result<GameAnalysis> = async {
+                    ensure_engine(game, &engine_manager).await?;
+                    game.analyze_game(limit, |done, total| display::show_message(&format!("引擎正在分析整局 {}/{}...", done, total))).await
+                }.await;
+                display::render_view(game_manager.as_ref())?;
+                match result {
+                    Ok(analysis) => display::show_game_analysis(&analysis)?,
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Hint { count, highlight } => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                if trainer.as_ref().is_some_and(|active| active.owns(game))
+                    || guess.as_ref().is_some_and(|active| active.owns(game))
+                    || puzzles.as_ref().is_some_and(|active| active.owns(game))
+                {
+                    display::show_error("训练中不能使用提示")?;
+                    continue;
+                }
+                let result: Result<Vec<Hint>> = async {
+                    ensure_engine(game, &engine_manager).await?;
+                    let multipv: Option<String> = game.engine_type.and_then(|engine_type| engine_manager.default_option(&engine_type, "MultiPV"));
+                    display::show_message("引擎正在寻找候选着法...")?;
+                    game.hint(count, engine_manager.hint_time, multipv.as_deref()).await
+                }.await;
+                let state: GameState = game.view_state().clone();
+                display::render_view(game_manager.as_ref())?;
+                match result {
+                    Ok(hints) => display::show_hints(&state, &hints, engine_manager.notation, highlight)?,
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Threat => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                if trainer.as_ref().is_some_and(|active| active.owns(game))
+                    || guess.as_ref().is_some_and(|active| active.owns(game))
+                    || puzzles.as_ref().is_some_and(|active| active.owns(game))
+                {
+                    display::show_error("训练中不能分析威胁")?;
+                    continue;
+                }
+                let result: Result<Threat> = async {
+                    ensure_engine(game, &engine_manager).await?;
+                    display::show_message("引擎正在分析对方的威胁...")?;
+                    game.threat(engine_manager.hint_time).await
+                }.await;
+                display::render_view(game_manager.as_ref())?;
+                match result {
+                    Ok(threat) => display::show_threat(&threat, engine_manager.notation)?,
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Branch(play) => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                game.branch();
+                let result: Result<()> = async {
+                    if let Some((engine_type, player_color)) = play {
+                        let mut engine: Box<dyn EngineProtocol> = engine_manager.create_engine_instance(&engine_type).await?;
+                        engine.init().await?;
+                        game.book = engine_manager.load_book(&engine_type)?;
+                        game.attach_engine(engine, engine_type, &engine_manager.display_name(&engine_type), player_color).await?;
+                    }
+                    Ok(())
+                }.await;
+                display::render_view(game_manager.as_ref())?;
+                match result {
+                    Ok(()) => display::show_message(&format!("已从第{}步开始新分支", game_manager.as_ref().map_or(0, |g| g.ply_count())))?,
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Replay(delay) => {
+                let Some(game) = &game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                if game.state.variant == Variant::Jieqi {
+                    display::show_error("揭棋翻子具有随机性，无法回放")?;
+                    continue;
+                }
+                if let Err(e) = replay_game(game, delay).await {
+                    display::show_error(&e.to_string())?;
+                }
+            },
+            Command::Comment(comment) => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                let cleared: bool = comment.is_none();
+                game.set_comment(comment);
+                display::render_view(game_manager.as_ref())?;
+                display::show_message(if cleared { "注释已清除" } else { "注释已保存" })?;
+            },
+            Command::Mark(glyph) => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                match game.set_glyph(glyph) {
+                    Ok(()) => display::render_view(game_manager.as_ref())?,
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Variation(index) => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                let result: Result<()> = match index {
+                    Some(index) => game.select_variation(index),
+                    None => game.alternative_names().and_then(|names| {
+                        let current: usize = game.alternatives().iter().position(|&id| id == game.view_node()).unwrap_or(0);
+                        display::show_variations(&names, current)
+                    }),
+                };
+                if index.is_some() {
+                    display::render_view(game_manager.as_ref())?;
+                }
+                if let Err(e) = result {
+                    display::show_error(&e.to_string())?;
+                }
+            },
+            Command::Promote => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                match game.promote() {
+                    Ok(()) => {
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_message("已提升为主线")?;
+                    }
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::DeleteBranch => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                match game.delete_branch() {
+                    Ok(()) => {
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_message("已删除该着法及其后续着法")?;
+                    }
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Tag { key, value } => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                let Some(key) = key else {
+                    display::show_metadata(&game.to_record().tags)?;
+                    continue;
+                };
+                match game.metadata.set(&key, &value) {
+                    Ok(()) => {
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_message(&format!("已设置 {}", key))?;
+                    }
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Sessions => {
+                match Session::list() {
+                    Ok(sessions) => display::show_sessions(&sessions)?,
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Resume(index) => {
+                let session: Result<Session> = Session::list().and_then(|mut sessions| {
+                    if index > sessions.len() {
+                        return Err(anyhow!("共有{}个自动保存的对局", sessions.len()));
+                    }
+                    Ok(sessions.swap_remove(index - 1))
+                });
+                let restored: Result<(Session, GameManager)> = session
+                    .and_then(|session| Ok((session.clone(), session.restore(&engine_manager.player_name)?)));
+                let (session, game) = match restored {
+                    Ok(restored) => restored,
+                    Err(e) => {
+                        display::show_error(&format!("{:#}", e))?;
+                        continue;
+                    }
+                };
+                if let Some(mut old_game) = game_manager.replace(game) {
+                    let _ = old_game.quit().await;
+                }
+                setup_state = None;
+                display::render_view(game_manager.as_ref())?;
+
+                // 重新创建引擎并同步局面，失败时保留打谱状态
+                if let (Some(engine_type), Some(game)) = (session.engine_type, &mut game_manager) {
+                    display::show_message("正在启动引擎...")?;
+                    let result: Result<()> = async {
+                        let engine: Box<dyn EngineProtocol> = engine_manager.create_engine_instance(&engine_type).await?;
+                        game.set_engine(engine, engine_type, &engine_manager.display_name(&engine_type)).await?;
+                        for (name, value) in &session.options {
+                            game.set_option(name, value.as_deref()).await?;
+                        }
+                        game.book = engine_manager.load_book(&engine_type)?;
+                        if let Some(color) = session.player_color {
+                            game.play_as(color).await?;
+                        }
+                        Ok(())
+                    }.await;
+                    display::render_view(game_manager.as_ref())?;
+                    if let Err(e) = result {
+                        display::show_error(&format!("引擎启动失败，对局以打谱方式恢复: {}", e))?;
+                        continue;
+                    }
+                }
+                display::show_message(&format!("已恢复 {} 保存的对局", format_time(session.saved_at)))?;
+            },
+            Command::Database(DbCommand::Info) => {
+                match open_database(&mut database) {
+                    Ok(database) => display::show_message(&format!("棋谱库共{}局，使用 'db import <文件>' 导入棋谱", database.len()))?,
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Database(DbCommand::Import(path)) => {
+                display::show_message("正在导入...")?;
+                match open_database(&mut database).and_then(|database| Ok((database.import(&path)?, database.len()))) {
+                    Ok((summary, total)) => display::show_message(&format!(
+                        "导入{}局，跳过重复{}局、无效{}局，棋谱库共{}局",
+                        summary.added,
+                        summary.duplicates,
+                        summary.failed,
+                        total,
+                    ))?,
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Database(DbCommand::Find(query)) => {
+                match open_database(&mut database) {
+                    Ok(database) => {
+                        db_hits = database.find(&query);
+                        display::show_db_hits(database, &db_hits)?;
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Database(DbCommand::Position) => {
+                let Some(game) = &game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                let key: u64 = game.view_state().key();
+                match open_database(&mut database) {
+                    Ok(database) => {
+                        db_hits = database.find_position(key);
+                        display::show_db_hits(database, &db_hits)?;
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Database(DbCommand::Load(index)) => {
+                let Some(&hit) = db_hits.get(index - 1) else {
+                    if db_hits.is_empty() {
+                        display::show_error("请先使用 'db find' 或 'db position' 查找对局")?;
+                    } else {
+                        display::show_error(&format!("查找结果共{}局", db_hits.len()))?;
+                    }
+                    continue;
+                };
+                let loaded: Result<(GameManager, GameRecord, usize)> = open_database(&mut database).and_then(|database| {
+                    let record: GameRecord = database.load(hit.id)?;
+                    let mut game: GameManager = GameManager::from_record(&record, &engine_manager.player_name)?;
+                    game.goto(hit.ply)?;
+                    Ok((game, record, database.len()))
+                });
+                match loaded {
+                    Ok((game, record, total)) => {
+                        if let Some(mut old_game) = game_manager.replace(game) {
+                            let _ = old_game.quit().await;
+                        }
+                        setup_state = None;
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_loaded(&record, hit.id + 1, total)?;
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Explore { sort, step } => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                if let Some(sort) = sort {
+                    explore_sort = sort;
+                }
+                if let Some(index) = step {
+                    let Some(&mv) = explore_moves.get(index - 1) else {
+                        if explore_moves.is_empty() {
+                            display::show_error("请先使用 'explore' 查看后续着法")?;
+                        } else {
+                            display::show_error(&format!("共有{}个后续着法", explore_moves.len()))?;
+                        }
+                        continue;
+                    };
+                    if let Err(e) = game.player_move(&mv.to_string()).await {
+                        display::show_error(&e.to_string())?;
+                        continue;
+                    }
+                    if let Err(e) = game.autosave() {
+                        log_warn!(e);
+                    }
+                    if game.is_engine_turn() {
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_message("引擎正在思考...")?;
+                        if let Some(game) = &mut game_manager
+                            && let Err(e) = game.engine_move().await
+                        {
+                            display::show_error(&e.to_string())?;
+                            continue;
+                        }
+                    }
+                }
+                let Some(game) = &game_manager else { continue };
+                let continuations: Vec<Continuation> = match open_database(&mut database) {
+                    Ok(database) => explore(database, game.view_state(), explore_sort),
+                    Err(e) => {
+                        display::show_error(&format!("{:#}", e))?;
+                        continue;
+                    }
+                };
+                explore_moves = continuations.iter().map(|c| c.mv).collect();
+                display::render_view(game_manager.as_ref())?;
+                display::show_explorer(game.view_state(), &continuations, explore_sort)?;
+            },
+            Command::BookBuild { output, inputs, options, self_play, think_time } => {
+                let mut records: Vec<GameRecord> = Vec::new();
+                if self_play > 0 {
+                    display::show_message(&format!("正在进行{}局引擎自对弈...", self_play))?;
+                    let think_time: usize = think_time.unwrap_or(engine_manager.hint_time);
+                    match self_play_games(&engine_manager, self_play, options.max_ply, think_time).await {
+                        Ok(games) => records = games,
+                        Err(e) => {
+                            display::show_error(&format!("自对弈失败: {:#}", e))?;
+                            continue;
+                        }
+                    }
+                }
+                display::show_message("正在生成开局库...")?;
+                match handle_book_build(&output, &inputs, records, options) {
+                    Ok((book, games, failed, unreadable)) => {
+                        let mut message: String = format!("已由{}局生成开局库 {}，共{}个条目", games, output, book.len());
+                        if failed > 0 {
+                            message.push_str(&format!("，跳过无效对局{}局", failed));
+                        }
+                        if !unreadable.is_empty() {
+                            message.push_str(&format!("，跳过无法读取的文件: {}", unreadable.join(", ")));
+                        }
+                        display::show_message(&message)?;
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::BookDump { path, fen } => {
+                let state: Result<GameState> = match (&fen, &game_manager) {
+                    (Some(fen), _) => FenProcessor::parse_fen(fen),
+                    (None, Some(game)) => Ok(game.view_state().clone()),
+                    (None, None) => Err(anyhow!("没有游戏进行中，请指定FEN")),
+                };
+                match state.and_then(|state| Ok((Book::load(Path::new(&path))?, state))) {
+                    Ok((book, state)) => display::show_book_entries(&state, book.probe(state.key()))?,
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Repertoire(RepCommand::Open(path)) => {
+                let book: Result<Book> = if Path::new(&path).exists() { Book::load(Path::new(&path)) } else { Ok(Book::default()) };
+                match book {
+                    Ok(book) => {
+                        display::show_message(&format!("正在编辑 {}（{}个条目），浏览局面时使用 rep 命令修改", path, book.len()))?;
+                        repertoire = Some((path, book));
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Repertoire(RepCommand::Save(save_as)) => {
+                let Some((path, book)) = &mut repertoire else {
+                    display::show_error("请先使用 'rep open <文件>' 打开开局谱")?;
+                    continue;
+                };
+                if let Some(save_as) = save_as {
+                    *path = save_as;
+                }
+                match book.save(Path::new(path.as_str())) {
+                    Ok(()) => display::show_message(&format!("已保存到 {}（{}个条目）", path, book.len()))?,
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Repertoire(RepCommand::Close) => {
+                match repertoire.take() {
+                    Some((path, _)) => display::show_message(&format!("已关闭 {}，未保存的修改已丢弃", path))?,
+                    None => display::show_error("没有打开的开局谱")?,
+                }
+            },
+            Command::Repertoire(RepCommand::Edit(edit)) => {
+                let Some((path, book)) = &mut repertoire else {
+                    display::show_error("请先使用 'rep open <文件>' 打开开局谱")?;
+                    continue;
+                };
+                let Some(game) = &game_manager else {
+                    display::show_error("没有游戏进行中")?;
+                    continue;
+                };
+                let state: &GameState = game.view_state();
+                if let Err(e) = handle_repertoire_edit(book, state, edit) {
+                    display::show_error(&format!("{:#}", e))?;
+                    continue;
+                }
+                display::show_repertoire(path, state, book.probe(state.key()))?;
+            },
+            Command::Train { path, color } => {
+                let started: Result<(Trainer, Option<GameManager>)> = Trainer::start(Path::new(&path), color)
+                    .and_then(|mut active| {
+                        let game: Option<GameManager> = active.next(&engine_manager.player_name)?;
+                        Ok((active, game))
+                    });
+                match started {
+                    Ok((active, Some(game))) => {
+                        if let Some(mut old_game) = game_manager.replace(game) {
+                            let _ = old_game.quit().await;
+                        }
+                        setup_state = None;
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_message(&format!("开局训练开始，共{}题，请走出准备的应着，'train stop' 结束", active.remaining()))?;
+                        trainer = Some(active);
+                    }
+                    Ok((active, None)) => display::show_message(&format!("没有需要复习的局面\n{}", training_summary(&active)))?,
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::TrainStop => {
+                match trainer.take() {
+                    Some(active) => display::show_message(&training_summary(&active))?,
+                    None => display::show_error("没有正在进行的训练")?,
+                }
+            },
+            Command::Puzzle(PuzzleCommand::Start { path, index }) => {
+                // 有引擎时用于验证其他杀法，启动失败不影响解题
+                let engine: Option<Box<dyn EngineProtocol>> = match async {
+                    let mut engine: Box<dyn EngineProtocol> = engine_manager.create_engine_instance(&EngineType::Pikafish).await?;
+                    engine.init().await?;
+                    Ok::<_, anyhow::Error>(engine)
+                }.await {
+                    Ok(engine) => Some(engine),
+                    Err(e) => {
+                        log_warn!(e);
+                        None
+                    }
+                };
+                let started: Result<(PuzzleSession, GameManager)> = PuzzleSession::open(Path::new(&path), engine)
+                    .and_then(|mut active| {
+                        let index: usize = match index {
+                            Some(n) => n - 1,
+                            None => active.first_unsolved(0),
+                        };
+                        let game: GameManager = active.present(index, &engine_manager.player_name)?;
+                        Ok((active, game))
+                    });
+                match started {
+                    Ok((active, game)) => {
+                        if let Some(mut old_game) = game_manager.replace(game) {
+                            let _ = old_game.quit().await;
+                        }
+                        setup_state = None;
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_puzzle(&active)?;
+                        let hint: &str = if active.has_engine() { "" } else { "（未能启动引擎，只接受题解和直接取胜的着法）" };
+                        display::show_message(&format!("解题开始，'puzzle next' 换题，'puzzle stop' 结束{}", hint))?;
+                        if let Some(mut old) = puzzles.replace(active) {
+                            let _ = old.close().await;
+                        }
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Puzzle(PuzzleCommand::Show) => {
+                match &puzzles {
+                    Some(active) => display::show_puzzle(active)?,
+                    None => display::show_error("没有正在进行的解题")?,
+                }
+            },
+            Command::Puzzle(PuzzleCommand::Next) => {
+                let Some(active) = &mut puzzles else {
+                    display::show_error("没有正在进行的解题")?;
+                    continue;
+                };
+                match active.next(&engine_manager.player_name) {
+                    Ok(game) => {
+                        if let Some(mut old_game) = game_manager.replace(game) {
+                            let _ = old_game.quit().await;
+                        }
+                        setup_state = None;
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_puzzle(active)?;
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Puzzle(PuzzleCommand::Stop) => {
+                let Some(mut active) = puzzles.take() else {
+                    display::show_error("没有正在进行的解题")?;
+                    continue;
+                };
+                let _ = active.close().await;
+                let stats: &PuzzleStats = &active.stats;
+                display::show_message(&format!(
+                    "解题结束，已解出{}/{}题，连续解出{}题（最佳{}题）",
+                    active.solved_count(),
+                    active.len(),
+                    stats.streak,
+                    stats.best_streak,
+                ))?;
+            },
+            Command::Guess(GuessCommand::Start { path, index, color }) => {
+                let started: Result<(GuessSession, GameManager)> = async {
+                    let mut records: Vec<GameRecord> = read_game_file(&path)?;
+                    if index > records.len() {
+                        return Err(anyhow!("文件中只有{}局棋谱", records.len()));
+                    }
+                    let record: GameRecord = records.swap_remove(index - 1);
+                    let mut engine: Box<dyn EngineProtocol> = engine_manager.create_engine_instance(&EngineType::Pikafish).await?;
+                    engine.init().await?;
+                    GuessSession::start(record, color, engine, &engine_manager.player_name)
+                }.await;
+                match started {
+                    Ok((active, game)) => {
+                        if let Some(mut old_game) = game_manager.replace(game) {
+                            let _ = old_game.quit().await;
+                        }
+                        if let Some(mut old) = guess.replace(active) {
+                            let _ = old.close().await;
+                        }
+                        setup_state = None;
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_message("猜着开始，请走出你认为实战中的着法，'guess stop' 结束")?;
+                    }
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Guess(GuessCommand::Stop) => {
+                let Some(mut active) = guess.take() else {
+                    display::show_error("没有正在进行的猜着训练")?;
+                    continue;
+                };
+                let _ = active.close().await;
+                display::show_guess_summary(&active)?;
+            },
+            Command::Endgames => {
+                match load_endgame_records() {
+                    Ok(records) => display::show_endgames(&records)?,
+                    Err(e) => display::show_error(&format!("{:#}", e))?,
+                }
+            },
+            Command::Endgame { name, engine_type } => {
+                let (_, selected) = match Endgame::find(&name) {
+                    Ok(found) => found,
+                    Err(e) => {
+                        display::show_error(&e.to_string())?;
+                        continue;
+                    }
+                };
+                display::show_message("正在启动引擎...")?;
+                match handle_new_game(&engine_manager, engine_type, selected.player, Some(selected.fen.to_string()), None, Variant::Standard).await {
+                    Ok(mut game) => {
+                        game.metadata.event = format!("残局练习: {}", selected.name);
+                        endgame = Some(EndgameAttempt::new(selected, &game));
+                        if let Some(mut old_game) = game_manager.replace(game) {
+                            let _ = old_game.quit().await;
+                        }
+                        setup_state = None;
+                        display::render_view(game_manager.as_ref())?;
+                        display::show_endgame_start(selected)?;
+                    }
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::Play { engine_type, player_color } => {
+                let Some(game) = &mut game_manager else {
+                    display::show_error("请先使用 'load' 载入棋谱或 'new' 开始游戏")?;
+                    continue;
+                };
+                let result: Result<()> = async {
+                    let mut engine: Box<dyn EngineProtocol> = engine_manager.create_engine_instance(&engine_type).await?;
+                    engine.init().await?;
+                    game.book = engine_manager.load_book(&engine_type)?;
+                    game.attach_engine(engine, engine_type, &engine_manager.display_name(&engine_type), player_color).await
+                }.await;
+                display::render_view(game_manager.as_ref())?;
+                if let Err(e) = result {
+                    display::show_error(&e.to_string())?;
+                }
+            },
             Command::ShowBoard => {
                 if let Some(game) = &game_manager {
                     display::render_view(game_manager.as_ref())?;
@@ -91,26 +1090,133 @@ pub async fn run_interactive_loop() -> Result<()> {
             },
             Command::History => {
                 if let Some(game) = &game_manager {
-                    display::show_history(&game.state.history)?;
+                    match game.to_record().movetext(MoveFormat::Chinese) {
+                        Ok(movetext) => display::show_history(&movetext)?,
+                        Err(e) => display::show_error(&format!("{:#}", e))?,
+                    }
                 } else {
                     display::show_error("没有游戏进行中")?;
                 }
             },
             Command::SetOption { name, value } => { 
                 if let Some(game) = &mut game_manager {
-                    game.engine.set_option(&name, value.as_deref()).await?;
-                    display::show_set_success(&name, value.as_deref())?;
+                    match game.set_option(&name, value.as_deref()).await {
+                        Ok(()) => display::show_set_success(&name, value.as_deref())?,
+                        Err(e) => display::show_error(&e.to_string())?,
+                    }
                 } else {
                     display::show_error("没有游戏进行中")?;
                 }
             },
+            Command::Setup(base) => {
+                let base: SetupBase = base.unwrap_or(if game_manager.is_some() { SetupBase::Current } else { SetupBase::Empty });
+                let mut state: GameState = match base {
+                    SetupBase::Empty => GameState::empty(),
+                    SetupBase::Start => GameState::new(),
+                    SetupBase::Current => match &game_manager {
+                        Some(game) => game.view_state().clone(),
+                        None => {
+                            display::show_error("没有游戏进行中，无法从当前局面摆棋")?;
+                            continue;
+                        }
+                    },
+                };
+                state.clear_history();
+                display::render_setup(&state)?;
+                setup_state = Some(state);
+            },
+            Command::Put { piece, pos } => {
+                if let Some(state) = &mut setup_state {
+                    let result: Result<()> = state.put_piece(piece, pos);
+                    display::render_setup(state)?;
+                    if let Err(e) = result {
+                        display::show_error(&e.to_string())?;
+                    }
+                } else {
+                    display::show_error("请先使用 'setup' 命令进入摆棋模式")?;
+                }
+            },
+            Command::ClearSquare(pos) => {
+                if let Some(state) = &mut setup_state {
+                    if state.clear_square(pos).is_none() {
+                        display::show_error(&format!("{}处没有棋子", pos))?;
+                    }
+                    display::render_setup(state)?;
+                } else {
+                    display::show_error("请先使用 'setup' 命令进入摆棋模式")?;
+                }
+            },
+            Command::SetSide(color) => {
+                if let Some(state) = &mut setup_state {
+                    state.current_player = color;
+                    display::render_setup(state)?;
+                } else {
+                    display::show_error("请先使用 'setup' 命令进入摆棋模式")?;
+                }
+            },
+            Command::SetupDone { engine_type, mode } => {
+                let Some(state) = &setup_state else {
+                    display::show_error("请先使用 'setup' 命令进入摆棋模式")?;
+                    continue;
+                };
+                if let Err(e) = state.validate_setup() {
+                    display::render_setup(state)?;
+                    display::show_error(&format!("局面不合法: {}", e))?;
+                    continue;
+                }
+                match handle_setup_done(&engine_manager, engine_type, mode, state).await {
+                    Ok(game) => {
+                        if let Some(mut old_game) = game_manager.replace(game) {
+                            let _ = old_game.quit().await;
+                        }
+                        setup_state = None;
+                        display::render_view(game_manager.as_ref())?;
+                    }
+                    Err(e) => display::show_error(&e.to_string())?,
+                }
+            },
+            Command::SetupCancel => {
+                if setup_state.take().is_some() {
+                    display::render_view(game_manager.as_ref())?;
+                    display::show_message("已退出摆棋模式")?;
+                } else {
+                    display::show_error("当前不在摆棋模式")?;
+                }
+            },
+            Command::Perft(depth) | Command::Divide(depth) => {
+                // 优先统计摆棋局面，其次对局中正在查看的局面，否则使用初始局面
+                let state: GameState = match (&setup_state, &game_manager) {
+                    (Some(state), _) => state.clone(),
+                    (None, Some(game)) => game.view_state().clone(),
+                    (None, None) => GameState::new(),
+                };
+                display::show_message(&format!("正在统计深度 {} 的节点数...", depth))?;
+                let start: Instant = Instant::now();
+                if matches!(cmd, Command::Perft(_)) {
+                    match perft(&state, depth) {
+                        Ok(nodes) => display::show_perft(depth, nodes, start.elapsed())?,
+                        Err(e) => display::show_error(&e.to_string())?,
+                    }
+                } else {
+                    match divide(&state, depth) {
+                        Ok(results) => display::show_divide(&state, depth, &results, start.elapsed())?,
+                        Err(e) => display::show_error(&e.to_string())?,
+                    }
+                }
+            },
             Command::ListEngines => { 
                 let engines: Vec<String> = engine_manager.list_engines();
                 display::show_engines(&engines)?;
             },
+            Command::ListHandicaps => {
+                display::show_handicaps()?;
+            },
             Command::Reverse => {
                 if let Some(game) = &mut game_manager {
                     game.state.flipped = !game.state.flipped;
+                    if let Some((_, state)) = &mut game.review {
+                        state.flipped = game.state.flipped;
+                    }
                     display::render_view(game_manager.as_ref())?;
                 } else {
                     display::show_error("没有游戏进行中")?;
@@ -123,9 +1229,21 @@ pub async fn run_interactive_loop() -> Result<()> {
             Command::Error(msg) => display::show_error(&msg)?,
         }
 
+        // 每条命令处理后自动保存，训练、解题和猜着的对局不保存
+        if let Some(game) = &game_manager
+            && !trainer.as_ref().is_some_and(|active| active.owns(game))
+            && !guess.as_ref().is_some_and(|active| active.owns(game))
+            && !puzzles.as_ref().is_some_and(|active| active.presents(game))
+            && let Err(e) = game.autosave()
+        {
+            log_warn!(e);
+        }
+
         // 命令处理后，重置输入提示符和重绘棋盘
-        if let Some(game) = &mut game_manager {
-            display::render_board(&game.state)?;
+        if let Some(state) = &setup_state {
+            display::render_board(state)?;
+        } else if let Some(game) = &mut game_manager {
+            display::render_board(game.view_state())?;
         }
         display::reset_input_prompt()?;
     }
@@ -145,6 +1263,8 @@ async fn handle_new_game(
     engine_type: EngineType,
     player_color: PlayerColor,
     fen: Option<String>,
+    handicap: Option<&'static Handicap>,
+    variant: Variant,
 ) -> Result<GameManager> {
     // 创建引擎实例
     let mut engine: Box<dyn EngineProtocol> = engine_manager.create_engine_instance(&engine_type).await?;
@@ -153,14 +1273,171 @@ async fn handle_new_game(
     engine.init().await?;
     
     // 创建游戏管理器
-    let mut game: GameManager = GameManager::new(engine);
+    let mut game: GameManager = GameManager::new(engine, engine_type, &engine_manager.display_name(&engine_type), &engine_manager.player_name);
+    game.book = engine_manager.load_book(&engine_type)?;
     
     // 开始新游戏
-    game.start_new_game(player_color, fen).await?;
+    game.start_new_game(player_color, fen, handicap, variant).await?;
     
     Ok(game)
 }
 
+/// 对局没有引擎时加载皮卡鱼，用于分析、提示等临时需要引擎的命令
+async fn ensure_engine(game: &mut GameManager, engine_manager: &EngineManager) -> Result<()> {
+    if game.engine.is_some() {
+        return Ok(());
+    }
+    let engine_type: EngineType = EngineType::Pikafish;
+    let mut engine: Box<dyn EngineProtocol> = engine_manager.create_engine_instance(&engine_type).await?;
+    engine.init().await?;
+    game.set_engine(engine, engine_type, &engine_manager.display_name(&engine_type)).await
+}
+
+/// 从棋谱文件载入第 index 局，返回游戏管理器、棋谱和文件中的总局数
+/// 支持 PGN、DhtmlXQ（UTF-8 或 GBK 编码）和 XQF 格式
+/// 首次使用时打开棋谱库，之后沿用已读入的索引
+fn open_database(database: &mut Option<Database>) -> Result<&mut Database> {
+    if database.is_none() {
+        *database = Some(Database::open()?);
+    }
+    Ok(database.as_mut().unwrap())
+}
+
+fn handle_load(path: &str, index: usize, player_name: &str) -> Result<(GameManager, GameRecord, usize)> {
+    let mut records: Vec<GameRecord> = read_game_file(path)?;
+    let total: usize = records.len();
+    if index > total {
+        return Err(anyhow!("文件中只有{}局棋谱", total));
+    }
+    let record: GameRecord = records.swap_remove(index - 1);
+    let game: GameManager = GameManager::from_record(&record, player_name)?;
+    Ok((game, record, total))
+}
+
+/// 统计棋谱文件和自对弈的对局生成开局库并写入 output
+/// 返回开局库、统计的对局数、跳过的对局数和无法读取的文件
+fn handle_book_build(output: &str, inputs: &[String], records: Vec<GameRecord>, options: BookOptions) -> Result<(Book, usize, usize, Vec<String>)> {
+    let mut builder: BookBuilder = BookBuilder::new(options);
+    let mut failed: usize = 0;
+    let mut unreadable: Vec<String> = Vec::new();
+    let mut add = |record: &GameRecord| {
+        if let Err(e) = builder.add_game(record) {
+            log_warn!(e);
+            failed += 1;
+        }
+    };
+    records.iter().for_each(&mut add);
+    for input in inputs {
+        match read_game_file(input) {
+            Ok(records) => records.iter().for_each(&mut add),
+            Err(e) => {
+                log_warn!(e);
+                unreadable.push(input.clone());
+            }
+        }
+    }
+    let games: usize = builder.games;
+    if games == 0 {
+        return Err(anyhow!("没有可用于生成开局库的对局"));
+    }
+    let book: Book = builder.build();
+    book.save(Path::new(output))?;
+    Ok((book, games, failed, unreadable))
+}
+
+/// 启动引擎自对弈 count 局
+async fn self_play_games(engine_manager: &EngineManager, count: usize, max_ply: usize, think_time: usize) -> Result<Vec<GameRecord>> {
+    let mut engine: Box<dyn EngineProtocol> = engine_manager.create_engine_instance(&EngineType::Pikafish).await?;
+    let result: Result<Vec<GameRecord>> = async {
+        engine.init().await?;
+        engine.set_option("MultiPV", Some(&SELF_PLAY_LINES.to_string())).await?;
+        let mut records: Vec<GameRecord> = Vec::with_capacity(count);
+        for _ in 0..count {
+            records.push(self_play_game(&mut engine, max_ply, think_time).await?);
+        }
+        Ok(records)
+    }.await;
+    let _ = engine.quit().await;
+    result
+}
+
+/// 在正在查看的局面上修改开局谱
+fn handle_repertoire_edit(book: &mut Book, state: &GameState, edit: RepEdit) -> Result<()> {
+    let key: u64 = state.key();
+    // 序号指向已有条目，否则按记谱解析
+    let resolve = |text: &str| -> Result<Move> {
+        match text.parse::<usize>() {
+            Ok(n) => book.probe(key).get(n.wrapping_sub(1)).map(|e| e.mv).ok_or_else(|| anyhow!("此局面没有第{}个着法", n)),
+            Err(_) => state.parse_notation(text),
+        }
+    };
+    match edit {
+        RepEdit::Show => Ok(()),
+        RepEdit::Add { mv, weight } => {
+            let mv: Move = state.parse_notation(&mv)?;
+            book.insert(state, mv, weight);
+            Ok(())
+        },
+        RepEdit::Remove(mv) => {
+            let mv: Move = resolve(&mv)?;
+            book.remove(key, mv)
+        },
+        RepEdit::Weight { mv, weight } => {
+            let mv: Move = resolve(&mv)?;
+            book.update(key, mv, |entry| entry.weight = weight)
+        },
+        RepEdit::Comment { mv, text } => {
+            let mv: Move = resolve(&mv)?;
+            book.update(key, mv, |entry| entry.comment = text)
+        },
+        RepEdit::Mark { mv, mark } => {
+            let mv: Move = resolve(&mv)?;
+            book.update(key, mv, |entry| entry.mark = mark)
+        },
+    }
+}
+
+/// 训练结果和下次复习时间
+fn training_summary(trainer: &Trainer) -> String {
+    let next: String = trainer.next_due().map_or("无".to_string(), format_time);
+    format!("本次答对{}次，答错{}次，下次复习: {}", trainer.correct, trainer.wrong, next)
+}
+
+/// 从起始局面逐步回放当前对局
+async fn replay_game(game: &GameManager, delay: u64) -> Result<()> {
+    let mut state: GameState = FenProcessor::parse_fen(&game.start_fen)?;
+    state.flipped = game.state.flipped;
+    display::render_board(&state)?;
+    for mv in &game.state.moves {
+        sleep(Duration::from_millis(delay)).await;
+        state.apply_move(&mv.to_string())?;
+        display::render_board(&state)?;
+    }
+    display::show_message(&format!("回放完成，共{}步", game.state.moves.len()))
+}
+
+/// 处理摆棋完成命令，从摆出的局面开始对弈或分析
+async fn handle_setup_done(
+    engine_manager: &EngineManager,
+    engine_type: EngineType,
+    mode: SetupMode,
+    state: &GameState,
+) -> Result<GameManager> {
+    let fen: String = state.to_fen();
+    match mode {
+        SetupMode::Play(player_color) => {
+            handle_new_game(engine_manager, engine_type, player_color, Some(fen), None, state.variant).await
+        }
+        SetupMode::Analyze => {
+            let mut game: GameManager = handle_new_game(engine_manager, engine_type, state.current_player, Some(fen), None, state.variant).await?;
+            display::render_view(Some(&game))?;
+            display::show_message("引擎正在分析...")?;
+            game.analyze().await?;
+            Ok(game)
+        }
+    }
+}
+
 /// 主循环
 pub async fn run() -> Result<()> {
     match run_interactive_loop().await {
@@ -1,21 +1,36 @@
 use crate::utils::*;
 use crate::engine::{EngineType, EngineProtocol, UciEngine};
+use crate::game::{Book, MoveFormat};
+use std::sync::Arc;
 
 /// 引擎配置
 #[derive(Debug, Clone)]
 pub struct EngineConfig {
+    /// 引擎显示名称
+    pub name: Option<String>,
     /// 引擎可执行文件路径
     pub path: String,
     /// 引擎默认选项
     pub options: HashMap<String, Option<String>>,
+    /// 开局库文件路径
+    pub book: Option<String>,
 }
 
 /// 引擎管理器
 pub struct EngineManager {
     /// 引擎配置
     pub engines: HashMap<EngineType, EngineConfig>,
+    /// 玩家名称，来自配置文件的 [user] 表
+    pub player_name: String,
+    /// 玩家偏好的记谱格式，用于显示提示
+    pub notation: MoveFormat,
+    /// 提示和威胁分析时引擎的思考时间（毫秒）
+    pub hint_time: usize,
 }
 
+/// 未配置时提示的思考时间（毫秒）
+const DEFAULT_HINT_TIME: usize = 1000;
+
 impl EngineManager {
     /// 创建新的引擎管理器
     pub fn new() -> Result<Self> {
@@ -36,14 +51,31 @@ impl EngineManager {
         
         // 创建引擎映射
         let mut engines: HashMap<EngineType, EngineConfig> = HashMap::new();
+        let mut player_name: String = String::new();
+        let mut notation: MoveFormat = MoveFormat::Chinese;
+        let mut hint_time: usize = DEFAULT_HINT_TIME;
         for (key, value) in config.as_table().unwrap() {
+            if key == "user" {
+                player_name = value.get("name").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+                if let Some(format) = value.get("notation").and_then(|v| v.as_str()) {
+                    notation = MoveFormat::from_str(format)?;
+                }
+                if let Some(time) = value.get("hint_time").and_then(|v| v.as_integer()) {
+                    hint_time = usize::try_from(time).ok().filter(|&t| t > 0)
+                        .ok_or_else(|| anyhow!("hint_time 必须是正整数（毫秒）"))?;
+                }
+                continue;
+            }
             engines.insert(EngineType::from_str(key)?, EngineConfig::try_from(value.clone())?);
         }
 
         log_info!(engines);
 
         Ok(Self {
-            engines
+            engines,
+            player_name,
+            notation,
+            hint_time,
         })
     }
     
@@ -56,12 +88,12 @@ impl EngineManager {
         }
         
         // 2. 可执行文件所在目录
-        if let Ok(exe_path) = current_exe() {
-            if let Some(exe_dir) = exe_path.parent() {
-                let exe_config: PathBuf = exe_dir.join("engines.toml");
-                if exe_config.exists() {
-                    return Ok(exe_config);
-                }
+        if let Ok(exe_path) = current_exe()
+            && let Some(exe_dir) = exe_path.parent()
+        {
+            let exe_config: PathBuf = exe_dir.join("engines.toml");
+            if exe_config.exists() {
+                return Ok(exe_config);
             }
         }
         
@@ -107,6 +139,27 @@ impl EngineManager {
             .ok_or_else(|| anyhow!("未找到引擎 '{:?}' 的配置", engine_type))
     }
     
+    /// 引擎显示名称，未配置时使用引擎类型名
+    pub fn display_name(&self, engine_type: &EngineType) -> String {
+        self.engines.get(engine_type)
+            .and_then(|c| c.name.clone())
+            .unwrap_or_else(|| engine_type.to_string())
+    }
+
+    /// 配置文件中引擎选项的默认值，选项名不区分大小写，未配置时返回 None
+    pub fn default_option(&self, engine_type: &EngineType, name: &str) -> Option<String> {
+        self.engines.get(engine_type)?.options.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .and_then(|(_, value)| value.clone())
+    }
+
+    /// 读取引擎配置的开局库，未配置时返回 None
+    pub fn load_book(&self, engine_type: &EngineType) -> Result<Option<Arc<Book>>> {
+        let Some(path) = &self.get_config(engine_type)?.book else { return Ok(None) };
+        let path: String = Self::resolve_path(path)?;
+        Ok(Some(Arc::new(Book::load(Path::new(&path))?)))
+    }
+
     /// 创建引擎协议实例
     pub async fn create_engine_instance(&self, engine_type: &EngineType) -> Result<Box<dyn EngineProtocol>> {
         let config: &EngineConfig = self.get_config(engine_type)?;
@@ -160,6 +213,10 @@ impl TryFrom<toml::Value> for EngineConfig {
         let table: &toml::map::Map<String, toml::Value> = value.as_table()
             .ok_or_else(|| anyhow!("引擎配置应为表结构"))?;
         
+        let name: Option<String> = table.get("name")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
         let path: String = table.get("path")
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow!("引擎配置缺少 'path' 字段"))?
@@ -180,6 +237,10 @@ impl TryFrom<toml::Value> for EngineConfig {
             }
         }
         
-        Ok(EngineConfig { path, options })
+        let book: Option<String> = table.get("book")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string());
+
+        Ok(EngineConfig { name, path, options, book })
     }
 }
\ No newline at end of file
@@ -1,4 +1,4 @@
-use crate::{game::FenProcessor, utils::*};
+use crate::{game::{FenProcessor, Variant}, utils::*};
 
 /// 引擎协议抽象
 #[async_trait]
@@ -11,39 +11,49 @@ pub trait EngineProtocol: Send + Sync {
     
     /// 开始思考
     async fn go(&mut self, think_time: Option<usize>) -> Result<EngineGoResult>;
+
+    /// 搜索到指定深度
+    async fn go_depth(&mut self, depth: usize) -> Result<EngineGoResult>;
     
     /// 停止思考
     async fn stop(&mut self) -> Result<()>;
 
     /// 设置引擎选项
     async fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<()>;
+
+    /// 设置棋类变体
+    async fn set_variant(&mut self, variant: Variant) -> Result<()>;
     
     /// 退出引擎
     async fn quit(&mut self) -> Result<()>;
 }
 
+/// 杀棋折算的分数，步数越少绝对值越大
+pub const MATE_SCORE: isize = 30000;
+
+/// 折算分数对应的杀棋步数，正数表示几步胜，负数表示几步负，不是杀棋时为 None
+pub fn mate_in(score: isize) -> Option<isize> {
+    match score {
+        _ if score.abs() <= MATE_SCORE / 2 => None,
+        _ if score > 0 => Some(MATE_SCORE - score),
+        _ => Some(-MATE_SCORE - score),
+    }
+}
+
 /// 引擎思考信息
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct EngineThinkingInfo {
     pub depth: usize,
     pub score: Option<isize>,
+    /// 杀棋步数，正数表示行棋方几步胜，负数表示几步负
+    pub mate: Option<isize>,
+    /// 多主变搜索时的主变序号，从1开始
+    pub multipv: Option<usize>,
     pub nps: Option<usize>,
     pub time: Option<usize>,
     pub pv: Option<Vec<String>>,
 }
 
-impl Default for EngineThinkingInfo {
-    fn default() -> Self {
-        Self {
-            depth: 0,
-            score: None,
-            nps: None,
-            time: None,
-            pv: None,
-        }
-    }
-}
-
 impl FromStr for EngineThinkingInfo {
     type Err = anyhow::Error;
     
@@ -54,6 +64,8 @@ impl FromStr for EngineThinkingInfo {
         
         let mut depth: Option<usize> = None;
         let mut score: Option<isize> = None;
+        let mut mate: Option<isize> = None;
+        let mut multipv: Option<usize> = None;
         let mut nps: Option<usize> = None;
         let mut time: Option<usize> = None;
         let mut pv: Option<Vec<String>> = None;
@@ -72,6 +84,14 @@ impl FromStr for EngineThinkingInfo {
                     score = Some(tokens[i + 2].parse().context("解析得分失败")?);
                     i += 3;
                 }
+                "score" if i + 2 < tokens.len() && tokens[i + 1] == "mate" => {
+                    mate = Some(tokens[i + 2].parse().context("解析杀棋步数失败")?);
+                    i += 3;
+                }
+                "multipv" if i + 1 < tokens.len() => {
+                    multipv = Some(tokens[i + 1].parse().context("解析主变序号失败")?);
+                    i += 2;
+                }
                 "nps" if i + 1 < tokens.len() => {
                     nps = Some(tokens[i + 1].parse().context("解析节点每秒失败")?);
                     i += 2;
@@ -96,6 +116,8 @@ impl FromStr for EngineThinkingInfo {
             .map(|d| Self {
                 depth: d,
                 score,
+                mate,
+                multipv,
                 nps,
                 time,
                 pv,
@@ -104,6 +126,17 @@ impl FromStr for EngineThinkingInfo {
     }
 }
 
+impl EngineThinkingInfo {
+    /// 行棋方视角的分数，杀棋折算为接近 MATE_SCORE 的分数
+    pub fn centipawns(&self) -> Option<isize> {
+        match self.mate {
+            Some(mate) if mate > 0 => Some(MATE_SCORE - mate),
+            Some(mate) => Some(-MATE_SCORE - mate),
+            None => self.score,
+        }
+    }
+}
+
 /// 引擎思考结果
 #[derive(Debug, Clone)]
 pub struct EngineGoResult {
@@ -113,6 +146,13 @@ pub struct EngineGoResult {
     pub infos: Vec<EngineThinkingInfo>,
 }
 
+impl EngineGoResult {
+    /// 第一主变的思考信息，多主变搜索时其余主变不代表最佳着法
+    pub fn main_infos(&self) -> impl DoubleEndedIterator<Item = &EngineThinkingInfo> {
+        self.infos.iter().filter(|info| info.multipv.unwrap_or(1) == 1)
+    }
+}
+
 /// 支持的引擎
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum EngineType {
@@ -130,10 +170,10 @@ impl FromStr for EngineType {
     }
 }
 
-impl ToString for EngineType {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for EngineType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            EngineType::Pikafish => "pikafish".to_string(),
+            EngineType::Pikafish => write!(f, "pikafish"),
         }
     }
 }
@@ -171,6 +211,40 @@ impl UciEngine {
         })
     }
 
+    /// 发送 go 命令并读取思考信息直到 bestmove
+    async fn search(&mut self, command: &str) -> Result<EngineGoResult> {
+        self.send_command(command).await?;
+
+        // 读取响应直到找到 bestmove
+        let mut infos: Vec<EngineThinkingInfo> = Vec::new();
+        let mut best_move: Option<String> = None;
+        while best_move.is_none() {
+            let response: String = self.read_response().await?;
+            
+            if response.starts_with("bestmove") {
+                let parts: Vec<&str> = response.split_whitespace().collect();
+                if parts.len() > 1 {
+                    best_move = Some(parts[1].to_string());
+                }
+            }
+            // 解析并记录思考信息
+            else if response.starts_with("info") {
+                match EngineThinkingInfo::from_str(&response) {
+                    Ok(info) => {
+                        log_info!(info);
+                        infos.push(info);
+                    },
+                    Err(e) => {
+                        log_error!(format!("解析思考信息失败: {}", e))
+                    },
+                }
+            }
+        }
+
+        let best_move: String = best_move.ok_or_else(|| anyhow!("引擎未返回最佳着法"))?;
+        Ok(EngineGoResult { best_move, infos })
+    }
+
     /// 发送命令到引擎
     async fn send_command(&mut self, command: &str) -> Result<()> {
         let stdin: &mut ChildStdin = self
@@ -246,37 +320,11 @@ impl EngineProtocol for UciEngine {
             Some(time) => format!("go movetime {}", time),
             None => "go".to_string(),
         };
-        
-        self.send_command(&command).await?;
-        
-        // 读取响应直到找到 bestmove
-        let mut infos: Vec<EngineThinkingInfo> = Vec::new();
-        let mut best_move: Option<String> = None;
-        while best_move.is_none() {
-            let response: String = self.read_response().await?;
-            
-            if response.starts_with("bestmove") {
-                let parts: Vec<&str> = response.split_whitespace().collect();
-                if parts.len() > 1 {
-                    best_move = Some(parts[1].to_string());
-                }
-            }
-            // 解析并记录思考信息
-            else if response.starts_with("info") {
-                match EngineThinkingInfo::from_str(&response) {
-                    Ok(info) => {
-                        log_info!(info);
-                        infos.push(info);
-                    },
-                    Err(e) => {
-                        log_error!(format!("解析思考信息失败: {}", e))
-                    },
-                }
-            }
-        }
+        self.search(&command).await
+    }
 
-        let best_move: String = best_move.ok_or_else(|| anyhow!("引擎未返回最佳着法"))?;
-        Ok(EngineGoResult { best_move, infos })
+    async fn go_depth(&mut self, depth: usize) -> Result<EngineGoResult> {
+        self.search(&format!("go depth {}", depth)).await
     }
 
     async fn stop(&mut self) -> Result<()> {
@@ -292,6 +340,14 @@ impl EngineProtocol for UciEngine {
         self.send_command(&command).await
     }
 
+    async fn set_variant(&mut self, variant: Variant) -> Result<()> {
+        // 标准象棋无需设置，避免不支持变体的引擎报错
+        if variant == Variant::Standard {
+            return Ok(());
+        }
+        self.set_option("UCI_Variant", Some(variant.uci_name())).await
+    }
+
     async fn quit(&mut self) -> Result<()> {
         self.send_command("quit").await?;
         
@@ -304,3 +360,22 @@ impl EngineProtocol for UciEngine {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_infos_skip_other_lines() {
+        let infos: Vec<EngineThinkingInfo> = [
+            "info depth 10 score cp 30 pv h2e2 h9g7",
+            "info depth 12 multipv 1 score cp 40 pv h2e2 h9g7",
+            "info depth 12 multipv 2 score cp 10 pv b2e2 b9c7",
+            "info depth 12 multipv 3 score mate -3 pv a3a4",
+        ].iter().map(|line| EngineThinkingInfo::from_str(line).unwrap()).collect();
+        let result: EngineGoResult = EngineGoResult { best_move: "h2e2".to_string(), infos };
+        let scores: Vec<Option<isize>> = result.main_infos().map(|info| info.centipawns()).collect();
+        assert_eq!(scores, [Some(30), Some(40)]);
+        assert_eq!(result.main_infos().next_back().unwrap().pv.as_deref(), Some(&["h2e2".to_string(), "h9g7".to_string()][..]));
+    }
+}
@@ -0,0 +1,252 @@
+use crate::utils::*;
+use crate::engine::{EngineGoResult, EngineProtocol, EngineThinkingInfo, MATE_SCORE};
+use crate::game::{FenProcessor, GameManager, GameState, Glyph, Move, MoveNode, PlayerColor, Variant, ROOT};
+
+/// 计算平均损失时单步损失的上限，避免杀棋分数主导
+const MAX_LOSS: isize = 1000;
+/// 变着中保存的引擎主变步数
+const BEST_LINE_LENGTH: usize = 6;
+
+/// 每个局面的分析限制
+#[derive(Debug, Clone, Copy)]
+pub enum AnalysisLimit {
+    /// 每个局面思考的毫秒数
+    Time(usize),
+    /// 每个局面搜索的深度
+    Depth(usize),
+}
+
+impl Default for AnalysisLimit {
+    fn default() -> Self {
+        AnalysisLimit::Time(1000)
+    }
+}
+
+/// 着法分类
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MoveClass {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+impl MoveClass {
+    /// 按分数损失分类，与引擎最佳着法相同时为最佳
+    fn classify(loss: isize, is_best: bool) -> Self {
+        match loss {
+            _ if is_best => MoveClass::Best,
+            ..=50 => MoveClass::Good,
+            51..=100 => MoveClass::Inaccuracy,
+            101..=300 => MoveClass::Mistake,
+            _ => MoveClass::Blunder,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MoveClass::Best => "最佳",
+            MoveClass::Good => "好棋",
+            MoveClass::Inaccuracy => "缓着",
+            MoveClass::Mistake => "错着",
+            MoveClass::Blunder => "败着",
+        }
+    }
+
+    /// 标注到着法树的评注符号
+    pub fn glyph(&self) -> Option<Glyph> {
+        match self {
+            MoveClass::Best | MoveClass::Good => None,
+            MoveClass::Inaccuracy => Some(Glyph::Dubious),
+            MoveClass::Mistake => Some(Glyph::Mistake),
+            MoveClass::Blunder => Some(Glyph::Blunder),
+        }
+    }
+}
+
+/// 一步着法的分析结果，分数均为走子方视角
+#[derive(Debug, Clone)]
+pub struct MoveAnalysis {
+    /// 着法前局面的半回合数
+    pub ply: usize,
+    pub color: PlayerColor,
+    pub mv: Move,
+    pub name: String,
+    /// 引擎在着法前局面的最佳着法和主变
+    pub best_line: Vec<Move>,
+    pub best_name: String,
+    /// 着法前后的分数
+    pub before: isize,
+    pub after: isize,
+    pub loss: isize,
+    pub class: MoveClass,
+}
+
+impl MoveAnalysis {
+    /// 按胜率变化计算的单步准确率（0-100）
+    pub fn accuracy(&self) -> f64 {
+        let drop: f64 = (win_percent(self.before) - win_percent(self.after)).max(0.0);
+        (103.1668 * (-0.04354 * drop).exp() - 3.1669).clamp(0.0, 100.0)
+    }
+}
+
+/// 分数对应的胜率（百分比）
+fn win_percent(score: isize) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * score as f64).exp()) - 1.0)
+}
+
+/// 一方的分析汇总
+#[derive(Debug, Clone, Default)]
+pub struct SideSummary {
+    pub moves: usize,
+    /// 平均准确率
+    pub accuracy: f64,
+    /// 平均分数损失
+    pub average_loss: f64,
+    pub inaccuracies: usize,
+    pub mistakes: usize,
+    pub blunders: usize,
+}
+
+/// 整局分析结果
+#[derive(Debug, Clone, Default)]
+pub struct GameAnalysis {
+    pub moves: Vec<MoveAnalysis>,
+}
+
+impl GameAnalysis {
+    /// 指定一方的汇总
+    pub fn summary(&self, color: PlayerColor) -> SideSummary {
+        let moves: Vec<&MoveAnalysis> = self.moves.iter().filter(|m| m.color == color).collect();
+        if moves.is_empty() {
+            return SideSummary::default();
+        }
+        let count = |class: MoveClass| moves.iter().filter(|m| m.class == class).count();
+        SideSummary {
+            moves: moves.len(),
+            accuracy: moves.iter().map(|m| m.accuracy()).sum::<f64>() / moves.len() as f64,
+            average_loss: moves.iter().map(|m| m.loss.min(MAX_LOSS) as f64).sum::<f64>() / moves.len() as f64,
+            inaccuracies: count(MoveClass::Inaccuracy),
+            mistakes: count(MoveClass::Mistake),
+            blunders: count(MoveClass::Blunder),
+        }
+    }
+}
+
+/// 局面的评估：行棋方视角的分数和引擎主变，已分出胜负的局面没有主变
+struct Evaluation {
+    score: isize,
+    line: Vec<Move>,
+}
+
+/// 引擎评估局面
+async fn evaluate(engine: &mut Box<dyn EngineProtocol>, state: &GameState, limit: AnalysisLimit) -> Result<Evaluation> {
+    if state.is_game_over() {
+        return Ok(Evaluation { score: -MATE_SCORE, line: Vec::new() });
+    }
+    engine.set_position(&state.to_fen()).await?;
+    let result: EngineGoResult = match limit {
+        AnalysisLimit::Time(time) => engine.go(Some(time)).await?,
+        AnalysisLimit::Depth(depth) => engine.go_depth(depth).await?,
+    };
+    let info: Option<&EngineThinkingInfo> = result.main_infos().rev().find(|info| info.centipawns().is_some());
+    let score: isize = info.and_then(|info| info.centipawns()).ok_or_else(|| anyhow!("引擎没有给出评估"))?;
+    let mut line: Vec<Move> = vec![Move::from_str(&result.best_move)?];
+    if let Some(pv) = info.and_then(|info| info.pv.as_ref())
+        && pv.first() == Some(&result.best_move)
+    {
+        line = pv.iter().take(BEST_LINE_LENGTH).map(|mv| Move::from_str(mv)).collect::<Result<_>>()?;
+    }
+    Ok(Evaluation { score, line })
+}
+
+impl GameManager {
+    /// 引擎逐个分析当前路线的全部局面，为每步着法分类，并在着法树中标注评注符号、较好的着法和变着
+    /// 每分析完一个局面调用一次 progress(已完成数, 总数)
+    pub async fn analyze_game(&mut self, limit: AnalysisLimit, mut progress: impl FnMut(usize, usize) -> Result<()>) -> Result<GameAnalysis> {
+        if self.state.variant != Variant::Standard {
+            return Err(anyhow!("揭棋对局无法逐步分析"));
+        }
+        let moves: Vec<Move> = self.state.moves.clone();
+        if moves.is_empty() {
+            return Err(anyhow!("还没有着法可以分析"));
+        }
+        let engine: &mut Box<dyn EngineProtocol> = self.engine.as_mut().ok_or_else(|| anyhow!("当前对局没有加载引擎"))?;
+
+        let mut states: Vec<GameState> = vec![FenProcessor::parse_fen(&self.start_fen)?];
+        for mv in &moves {
+            let mut next: GameState = states.last().unwrap().clone();
+            next.apply_move(&mv.to_string())?;
+            states.push(next);
+        }
+        let mut evaluations: Vec<Evaluation> = Vec::with_capacity(states.len());
+        for (i, state) in states.iter().enumerate() {
+            evaluations.push(evaluate(engine, state, limit).await?);
+            progress(i + 1, states.len())?;
+        }
+        // 分析时引擎局面被改变，恢复为当前局面
+        let fen: String = self.state.to_fen();
+        engine.set_position(&fen).await?;
+
+        // 保存每个局面的分数供形势图使用
+        let nodes: Vec<usize> = std::iter::once(ROOT).chain(self.tree.path(self.node)).collect();
+        for ((&node, state), evaluation) in nodes.iter().zip(&states).zip(&evaluations) {
+            self.record_eval(node, state.current_player, evaluation.score);
+        }
+
+        let mut analysis: GameAnalysis = GameAnalysis::default();
+        for (ply, &mv) in moves.iter().enumerate() {
+            let state: &GameState = &states[ply];
+            let best_line: Vec<Move> = evaluations[ply].line.clone();
+            let before: isize = evaluations[ply].score;
+            let after: isize = -evaluations[ply + 1].score;
+            let is_best: bool = best_line.first() == Some(&mv);
+            let loss: isize = if is_best { 0 } else { (before - after).max(0) };
+            let best_name: String = match best_line.first() {
+                Some(best) => state.move_to_chinese(&best.to_string())?,
+                None => String::new(),
+            };
+            analysis.moves.push(MoveAnalysis {
+                ply,
+                color: state.current_player,
+                mv,
+                name: state.move_to_chinese(&mv.to_string())?,
+                best_line,
+                best_name,
+                before,
+                after,
+                loss,
+                class: MoveClass::classify(loss, is_best),
+            });
+        }
+        self.annotate(&analysis);
+        Ok(analysis)
+    }
+
+    /// 把缓着、错着和败着标注到当前路线上，引擎的较好着法作为变着加入
+    fn annotate(&mut self, analysis: &GameAnalysis) {
+        let path: Vec<usize> = self.tree.path(self.node);
+        for (result, &id) in analysis.moves.iter().zip(&path) {
+            let Some(glyph) = result.class.glyph() else { continue };
+            if result.best_line.is_empty() {
+                continue;
+            }
+            let node: &mut MoveNode = self.tree.node_mut(id);
+            node.glyph.get_or_insert(glyph);
+            let note: String = format!("{}，较好: {}（{:+}）", result.class.name(), result.best_name, result.before);
+            match &mut node.comment {
+                Some(comment) if comment.contains(&note) => {}
+                Some(comment) => {
+                    comment.push(' ');
+                    comment.push_str(&note);
+                }
+                None => node.comment = Some(note),
+            }
+            let mut parent: usize = self.tree.node(id).parent;
+            for &mv in &result.best_line {
+                parent = self.tree.add(parent, mv);
+            }
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use crate::utils::*;
+use crate::game::{Piece, PieceKind, PlayerColor, Position};
+
+/// 格子下标：行 * 9 + 列，0-89
+pub type Square = u8;
+
+/// 格子总数
+pub const SQUARE_COUNT: usize = 90;
+
+/// 每方最多的棋子数
+const MAX_PIECES: usize = 16;
+
+/// 空位标记
+const NO_SQUARE: Square = u8::MAX;
+
+/// Zobrist 棋子编号数：每方7种明子 + 每方1种暗子
+const ZOBRIST_PIECES: usize = 16;
+
+/// splitmix64 伪随机数，用于在编译期生成 Zobrist 键值
+const fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z: u64 = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// 棋子-格子 Zobrist 键值表
+const ZOBRIST: [[u64; SQUARE_COUNT]; ZOBRIST_PIECES] = {
+    let mut table: [[u64; SQUARE_COUNT]; ZOBRIST_PIECES] = [[0; SQUARE_COUNT]; ZOBRIST_PIECES];
+    let mut seed: u64 = 0x5851_F42D_4C95_7F2D;
+    let mut i: usize = 0;
+    while i < ZOBRIST_PIECES {
+        let mut j: usize = 0;
+        while j < SQUARE_COUNT {
+            table[i][j] = splitmix64(&mut seed);
+            j += 1;
+        }
+        i += 1;
+    }
+    table
+};
+
+/// 黑方行棋的 Zobrist 键值
+pub const ZOBRIST_SIDE: u64 = {
+    let mut seed: u64 = 0x2545_F491_4F6C_DD1D;
+    splitmix64(&mut seed)
+};
+
+impl PlayerColor {
+    /// 数组下标：红0黑1
+    pub fn index(&self) -> usize {
+        match self {
+            PlayerColor::Red => 0,
+            PlayerColor::Black => 1,
+        }
+    }
+}
+
+impl PieceKind {
+    /// 数组下标，顺序与显示用的棋子符号表一致
+    pub fn index(&self) -> usize {
+        match self {
+            PieceKind::General => 0,
+            PieceKind::Advisor => 1,
+            PieceKind::Elephant => 2,
+            PieceKind::Horse => 3,
+            PieceKind::Rook => 4,
+            PieceKind::Cannon => 5,
+            PieceKind::Pawn => 6,
+        }
+    }
+}
+
+impl Position {
+    /// 转换为格子下标
+    pub fn square(&self) -> Square {
+        (self.row * 9 + self.col) as Square
+    }
+
+    /// 由格子下标构造坐标
+    pub fn from_square(sq: Square) -> Self {
+        Position { row: sq as usize / 9, col: sq as usize % 9 }
+    }
+}
+
+/// 棋子的 Zobrist 编号
+fn zobrist_index(piece: Piece) -> usize {
+    if piece.hidden {
+        14 + piece.color.index()
+    } else {
+        piece.color.index() * 7 + piece.kind.index()
+    }
+}
+
+/// 紧凑棋盘：一维格子数组 + 双方棋子列表 + 增量 Zobrist 键值
+#[derive(Clone)]
+pub struct Board {
+    /// 格子内容，下标为 行 * 9 + 列
+    squares: [Option<Piece>; SQUARE_COUNT],
+    /// 双方棋子所在格子列表
+    lists: [[Square; MAX_PIECES]; 2],
+    /// 双方棋子数量
+    counts: [usize; 2],
+    /// 格子对应的棋子列表下标
+    slots: [u8; SQUARE_COUNT],
+    /// 双方将帅所在格子
+    generals: [Square; 2],
+    /// 棋子部分的 Zobrist 键值，不含行棋方
+    key: u64,
+}
+
+impl Board {
+    /// 创建空棋盘
+    pub fn empty() -> Self {
+        Self {
+            squares: [None; SQUARE_COUNT],
+            lists: [[NO_SQUARE; MAX_PIECES]; 2],
+            counts: [0; 2],
+            slots: [0; SQUARE_COUNT],
+            generals: [NO_SQUARE; 2],
+            key: 0,
+        }
+    }
+
+    /// 按行列获取棋子
+    #[inline]
+    pub fn at(&self, row: usize, col: usize) -> Option<Piece> {
+        self.squares[row * 9 + col]
+    }
+
+    /// 按格子下标获取棋子
+    #[inline]
+    pub fn get(&self, sq: Square) -> Option<Piece> {
+        self.squares[sq as usize]
+    }
+
+    /// 在指定格子放置棋子，覆盖原有棋子
+    pub fn put(&mut self, pos: Position, piece: Piece) -> Result<()> {
+        let sq: Square = pos.square();
+        // 替换己方棋子不增加数量；先检查再修改，出错时棋盘不变
+        let replaces_own: bool = self.get(sq).is_some_and(|old| old.color == piece.color);
+        if self.counts[piece.color.index()] >= MAX_PIECES && !replaces_own {
+            return Err(anyhow!("每方最多{}个棋子", MAX_PIECES));
+        }
+        self.remove(pos);
+        self.add_piece(sq, piece);
+        Ok(())
+    }
+
+    /// 移除指定格子上的棋子
+    pub fn remove(&mut self, pos: Position) -> Option<Piece> {
+        let sq: Square = pos.square();
+        let piece: Piece = self.get(sq)?;
+        self.remove_piece(sq, piece);
+        Some(piece)
+    }
+
+    /// 替换格子上的棋子（用于揭棋翻子），格子必须已有同色棋子
+    pub fn replace(&mut self, sq: Square, piece: Piece) {
+        let old: Piece = self.get(sq).expect("替换的格子上没有棋子");
+        self.key ^= ZOBRIST[zobrist_index(old)][sq as usize] ^ ZOBRIST[zobrist_index(piece)][sq as usize];
+        self.squares[sq as usize] = Some(piece);
+        if piece.kind == PieceKind::General {
+            self.generals[piece.color.index()] = sq;
+        }
+    }
+
+    /// 不做检查地走子，返回被吃掉的棋子
+    #[inline]
+    pub fn move_piece(&mut self, from: Square, to: Square) -> Option<Piece> {
+        let piece: Piece = self.get(from).expect("起始格子没有棋子");
+        let captured: Option<Piece> = self.get(to);
+        if let Some(captured_piece) = captured {
+            self.remove_piece(to, captured_piece);
+        }
+        self.relocate(from, to, piece);
+        captured
+    }
+
+    /// 撤销 move_piece
+    #[inline]
+    pub fn undo_move(&mut self, from: Square, to: Square, captured: Option<Piece>) {
+        let piece: Piece = self.get(to).expect("目标格子没有棋子");
+        self.relocate(to, from, piece);
+        if let Some(captured_piece) = captured {
+            self.add_piece(to, captured_piece);
+        }
+    }
+
+    /// 指定方全部棋子所在格子
+    #[inline]
+    pub fn pieces(&self, color: PlayerColor) -> &[Square] {
+        let idx: usize = color.index();
+        &self.lists[idx][..self.counts[idx]]
+    }
+
+    /// 指定方将帅所在格子
+    #[inline]
+    pub fn general_square(&self, color: PlayerColor) -> Option<Square> {
+        let sq: Square = self.generals[color.index()];
+        (sq != NO_SQUARE).then_some(sq)
+    }
+
+    /// 指定方将帅坐标
+    pub fn general(&self, color: PlayerColor) -> Option<Position> {
+        self.general_square(color).map(Position::from_square)
+    }
+
+    /// 棋子部分的 Zobrist 键值
+    #[inline]
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    /// 遍历棋盘上所有棋子
+    pub fn iter(&self) -> impl Iterator<Item = (Position, Piece)> + '_ {
+        self.squares.iter()
+            .enumerate()
+            .filter_map(|(sq, p)| p.map(|piece| (Position::from_square(sq as Square), piece)))
+    }
+
+    fn add_piece(&mut self, sq: Square, piece: Piece) {
+        let color: usize = piece.color.index();
+        let slot: usize = self.counts[color];
+        self.lists[color][slot] = sq;
+        self.slots[sq as usize] = slot as u8;
+        self.counts[color] += 1;
+        self.squares[sq as usize] = Some(piece);
+        self.key ^= ZOBRIST[zobrist_index(piece)][sq as usize];
+        if piece.kind == PieceKind::General && !piece.hidden {
+            self.generals[color] = sq;
+        }
+    }
+
+    fn remove_piece(&mut self, sq: Square, piece: Piece) {
+        let color: usize = piece.color.index();
+        let slot: usize = self.slots[sq as usize] as usize;
+        let last: usize = self.counts[color] - 1;
+        let last_sq: Square = self.lists[color][last];
+        self.lists[color][slot] = last_sq;
+        self.slots[last_sq as usize] = slot as u8;
+        self.lists[color][last] = NO_SQUARE;
+        self.counts[color] = last;
+        self.squares[sq as usize] = None;
+        self.key ^= ZOBRIST[zobrist_index(piece)][sq as usize];
+        // 摆棋时一方可能有多个将帅，移除记录的那个后改记剩下的
+        if piece.kind == PieceKind::General && self.generals[color] == sq {
+            self.generals[color] = self.lists[color][..last].iter()
+                .copied()
+                .find(|&s| matches!(self.squares[s as usize], Some(p) if p.kind == PieceKind::General && !p.hidden))
+                .unwrap_or(NO_SQUARE);
+        }
+    }
+
+    fn relocate(&mut self, from: Square, to: Square, piece: Piece) {
+        let color: usize = piece.color.index();
+        let slot: u8 = self.slots[from as usize];
+        self.lists[color][slot as usize] = to;
+        self.slots[to as usize] = slot;
+        self.squares[from as usize] = None;
+        self.squares[to as usize] = Some(piece);
+        let piece_keys: &[u64; SQUARE_COUNT] = &ZOBRIST[zobrist_index(piece)];
+        self.key ^= piece_keys[from as usize] ^ piece_keys[to as usize];
+        if piece.kind == PieceKind::General && !piece.hidden {
+            self.generals[color] = to;
+        }
+    }
+}
+
+impl std::ops::Index<Position> for Board {
+    type Output = Option<Piece>;
+
+    fn index(&self, pos: Position) -> &Self::Output {
+        &self.squares[pos.row * 9 + pos.col]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(s: &str) -> Position {
+        Position::from_str(s).unwrap()
+    }
+
+    fn piece(color: PlayerColor, kind: PieceKind) -> Piece {
+        Piece { color, kind, hidden: false }
+    }
+
+    #[test]
+    fn removing_a_duplicate_general_keeps_the_other() {
+        let general: Piece = piece(PlayerColor::Red, PieceKind::General);
+        let mut board: Board = Board::empty();
+        board.put(pos("e0"), general).unwrap();
+        board.put(pos("d0"), general).unwrap();
+        assert_eq!(board.general(PlayerColor::Red), Some(pos("d0")));
+        board.remove(pos("d0"));
+        assert_eq!(board.general(PlayerColor::Red), Some(pos("e0")));
+        board.remove(pos("e0"));
+        assert_eq!(board.general(PlayerColor::Red), None);
+    }
+
+    #[test]
+    fn full_side_rejects_new_pieces_without_changing_the_board() {
+        let pawn: Piece = piece(PlayerColor::Red, PieceKind::Pawn);
+        let mut board: Board = Board::empty();
+        for sq in 0..MAX_PIECES as Square {
+            board.put(Position::from_square(sq), pawn).unwrap();
+        }
+        board.put(pos("a9"), piece(PlayerColor::Black, PieceKind::Rook)).unwrap();
+        let key: u64 = board.key();
+
+        // 空格和对方棋子所在格都不能再放
+        assert!(board.put(pos("e5"), pawn).is_err());
+        assert!(board.put(pos("a9"), pawn).is_err());
+        assert_eq!(board[pos("a9")].map(|p| (p.color, p.kind)), Some((PlayerColor::Black, PieceKind::Rook)));
+        assert_eq!(board.key(), key);
+
+        // 替换己方棋子不增加数量
+        let rook: Piece = piece(PlayerColor::Red, PieceKind::Rook);
+        board.put(Position::from_square(0), rook).unwrap();
+        assert_eq!(board.get(0).map(|p| p.kind), Some(PieceKind::Rook));
+        assert_eq!(board.pieces(PlayerColor::Red).len(), MAX_PIECES);
+    }
+}
@@ -0,0 +1,444 @@
+use crate::utils::*;
+use crate::engine::{EngineGoResult, EngineProtocol};
+use crate::game::{GameRecord, GameResult, GameState, Move, PlayerColor, RecordMove, Variant, SQUARE_COUNT, START_FEN};
+
+/// 开局库文件头
+const MAGIC: &[u8; 8] = b"XQBOOK1\0";
+/// 每个条目的字节数：键值 8、起止格子各 1、权重 2、对局数 4
+const ENTRY_SIZE: usize = 16;
+/// 自对弈时每步搜索的主变数
+pub const SELF_PLAY_LINES: usize = 4;
+/// 自对弈时在与最佳着法分差不超过此值（厘兵）的着法中随机选择，使各局走出不同的开局
+const SELF_PLAY_MARGIN: isize = 30;
+/// 自对弈走满半回合数仍未分出胜负时，按引擎评分判定胜负的分数线（厘兵）
+const SELF_PLAY_ADJUDICATION: isize = 150;
+
+/// 开局谱中着法的标记
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveMark {
+    /// 主着，引擎走子和训练时优先选择
+    Main,
+    /// 避免的着法，从不选择
+    Avoid,
+}
+
+impl MoveMark {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MoveMark::Main => "main",
+            MoveMark::Avoid => "avoid",
+        }
+    }
+
+    /// 中文描述
+    pub fn name(&self) -> &'static str {
+        match self {
+            MoveMark::Main => "主着",
+            MoveMark::Avoid => "避免",
+        }
+    }
+}
+
+impl FromStr for MoveMark {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "main" | "主着" => Ok(MoveMark::Main),
+            "avoid" | "避免" => Ok(MoveMark::Avoid),
+            _ => Err(anyhow!("无效的着法标记: {}，使用 main、avoid 或 none", s)),
+        }
+    }
+}
+
+/// 开局库条目
+#[derive(Debug, Clone)]
+pub struct BookEntry {
+    /// 局面 Zobrist 键值
+    pub key: u64,
+    pub mv: Move,
+    /// 选择权重，越大越常用
+    pub weight: u16,
+    /// 统计到的对局数
+    pub games: u32,
+    /// 标记和注释，只保存在开局谱文件中
+    pub mark: Option<MoveMark>,
+    pub comment: Option<String>,
+}
+
+impl BookEntry {
+    pub fn new(key: u64, mv: Move, weight: u16) -> Self {
+        Self { key, mv, weight, games: 0, mark: None, comment: None }
+    }
+}
+
+/// 开局库，条目按键值排序，同一局面按权重从高到低
+/// 二进制文件只保存权重和对局数；扩展名为 .toml 的开局谱文件还保存标记、注释和局面 FEN
+#[derive(Debug, Clone, Default)]
+pub struct Book {
+    pub(crate) entries: Vec<BookEntry>,
+    /// 编辑时记录的局面 FEN，用于开局谱文件
+    pub(crate) fens: HashMap<u64, String>,
+}
+
+impl Book {
+    /// 读取开局库或开局谱文件，按文件头区分
+    pub fn load(path: &Path) -> Result<Self> {
+        let data: Vec<u8> = std::fs::read(path).with_context(|| format!("无法读取开局库 {}", path.display()))?;
+        let Some(body) = data.strip_prefix(MAGIC.as_slice()) else {
+            let text: String = String::from_utf8(data).map_err(|_| anyhow!("{} 不是开局库文件", path.display()))?;
+            return Self::from_repertoire(&text).with_context(|| format!("{} 不是开局库文件", path.display()));
+        };
+        if !body.len().is_multiple_of(ENTRY_SIZE) {
+            return Err(anyhow!("开局库 {} 已损坏", path.display()));
+        }
+        if body.chunks_exact(ENTRY_SIZE).any(|chunk| usize::from(chunk[8]) >= SQUARE_COUNT || usize::from(chunk[9]) >= SQUARE_COUNT) {
+            return Err(anyhow!("开局库 {} 已损坏", path.display()));
+        }
+        let mut entries: Vec<BookEntry> = body.chunks_exact(ENTRY_SIZE)
+            .map(|chunk| BookEntry {
+                key: u64::from_le_bytes(chunk[0..8].try_into().unwrap()),
+                mv: Move { from: chunk[8], to: chunk[9] },
+                weight: u16::from_le_bytes([chunk[10], chunk[11]]),
+                games: u32::from_le_bytes(chunk[12..16].try_into().unwrap()),
+                mark: None,
+                comment: None,
+            })
+            .collect();
+        entries.sort_by_key(|e| (e.key, std::cmp::Reverse(e.weight)));
+        Ok(Self { entries, fens: HashMap::new() })
+    }
+
+    /// 写入文件，扩展名为 .toml 时写为开局谱，否则写为二进制开局库
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("toml")) {
+            return write_atomic(path, self.to_repertoire());
+        }
+        let mut data: Vec<u8> = Vec::with_capacity(MAGIC.len() + self.entries.len() * ENTRY_SIZE);
+        data.extend_from_slice(MAGIC);
+        for entry in &self.entries {
+            data.extend_from_slice(&entry.key.to_le_bytes());
+            data.push(entry.mv.from);
+            data.push(entry.mv.to);
+            // 避免的着法写为零权重，不会被选中
+            let weight: u16 = if entry.mark == Some(MoveMark::Avoid) { 0 } else { entry.weight };
+            data.extend_from_slice(&weight.to_le_bytes());
+            data.extend_from_slice(&entry.games.to_le_bytes());
+        }
+        write_atomic(path, data)
+    }
+
+    /// 条目数
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 局面的全部条目，权重高的在前
+    pub fn probe(&self, key: u64) -> &[BookEntry] {
+        let start: usize = self.entries.partition_point(|e| e.key < key);
+        let end: usize = self.entries.partition_point(|e| e.key <= key);
+        &self.entries[start..end]
+    }
+
+    /// 为当前局面按权重随机选一步合法的库着，库中没有时返回 None
+    /// 有主着时只在主着中选择，避免的着法从不选择
+    pub fn choose(&self, state: &GameState) -> Option<Move> {
+        if state.variant != Variant::Standard {
+            return None;
+        }
+        // 键值冲突时库着可能不合法，先过滤
+        let legal: Vec<Move> = state.legal_moves();
+        let mut candidates: Vec<&BookEntry> = self.probe(state.key()).iter()
+            .filter(|e| legal.contains(&e.mv) && e.mark != Some(MoveMark::Avoid))
+            .collect();
+        if candidates.iter().any(|e| e.mark == Some(MoveMark::Main)) {
+            candidates.retain(|e| e.mark == Some(MoveMark::Main));
+        }
+        let weight = |e: &BookEntry| if e.mark == Some(MoveMark::Main) { e.weight.max(1) as u32 } else { e.weight as u32 };
+        let total: u32 = candidates.iter().map(|e| weight(e)).sum();
+        if total == 0 {
+            return None;
+        }
+        let mut pick: u32 = rand::rng().random_range(0..total);
+        for entry in candidates {
+            if pick < weight(entry) {
+                return Some(entry.mv);
+            }
+            pick -= weight(entry);
+        }
+        None
+    }
+
+    fn sort(&mut self) {
+        self.entries.sort_by_key(|e| (e.key, std::cmp::Reverse(e.weight)));
+    }
+
+    /// 在局面中添加着法，已有时更新权重
+    pub fn insert(&mut self, state: &GameState, mv: Move, weight: u16) {
+        let key: u64 = state.key();
+        self.fens.entry(key).or_insert_with(|| state.to_fen());
+        match self.entries.iter_mut().find(|e| e.key == key && e.mv == mv) {
+            Some(entry) => entry.weight = weight,
+            None => self.entries.push(BookEntry::new(key, mv, weight)),
+        }
+        self.sort();
+    }
+
+    /// 删除局面中的着法
+    pub fn remove(&mut self, key: u64, mv: Move) -> Result<()> {
+        let index: usize = self.entries.iter()
+            .position(|e| e.key == key && e.mv == mv)
+            .ok_or_else(|| anyhow!("开局库中此局面没有着法 {}", mv))?;
+        self.entries.remove(index);
+        Ok(())
+    }
+
+    /// 修改局面中的着法条目
+    pub fn update(&mut self, key: u64, mv: Move, f: impl FnOnce(&mut BookEntry)) -> Result<()> {
+        let entry: &mut BookEntry = self.entries.iter_mut()
+            .find(|e| e.key == key && e.mv == mv)
+            .ok_or_else(|| anyhow!("开局库中此局面没有着法 {}", mv))?;
+        f(entry);
+        self.sort();
+        Ok(())
+    }
+}
+
+/// 开局库生成条件
+#[derive(Debug, Clone, Copy)]
+pub struct BookOptions {
+    /// 着法至少出现的对局数
+    pub min_games: u32,
+    /// 走子方得分率下限（百分比）
+    pub min_score: f64,
+    /// 只收录前若干半回合
+    pub max_ply: usize,
+}
+
+impl Default for BookOptions {
+    fn default() -> Self {
+        Self { min_games: 1, min_score: 0.0, max_ply: 30 }
+    }
+}
+
+/// 某一局面下某一着法的统计
+#[derive(Debug, Clone, Copy, Default)]
+struct MoveStats {
+    games: u32,
+    wins: u32,
+    draws: u32,
+}
+
+/// 开局库生成器：逐局统计主线着法，最后按条件筛选并计算权重
+pub struct BookBuilder {
+    options: BookOptions,
+    stats: HashMap<(u64, Move), MoveStats>,
+    /// 已统计的对局数
+    pub games: usize,
+}
+
+impl BookBuilder {
+    pub fn new(options: BookOptions) -> Self {
+        Self { options, stats: HashMap::new(), games: 0 }
+    }
+
+    /// 统计一局的主线，未知结果按和棋计；揭棋对局无法重现，返回错误
+    pub fn add_game(&mut self, record: &GameRecord) -> Result<()> {
+        let mut state: GameState = record.initial_state()?;
+        if state.variant == Variant::Jieqi {
+            return Err(anyhow!("揭棋对局不能用于生成开局库"));
+        }
+        let winner: Option<PlayerColor> = match record.result() {
+            GameResult::RedWin => Some(PlayerColor::Red),
+            GameResult::BlackWin => Some(PlayerColor::Black),
+            GameResult::Draw | GameResult::Unknown => None,
+        };
+        // 先完整回放，避免无效棋谱统计一半
+        let mut positions: Vec<(u64, Move, PlayerColor)> = Vec::new();
+        for (i, record_move) in record.moves.iter().take(self.options.max_ply).enumerate() {
+            positions.push((state.key(), record_move.mv, state.current_player));
+            state.play_move(record_move.mv)
+                .with_context(|| format!("第{}步着法无效", i + 1))?;
+        }
+        for (key, mv, mover) in positions {
+            let stats: &mut MoveStats = self.stats.entry((key, mv)).or_default();
+            stats.games += 1;
+            match winner {
+                Some(color) if color == mover => stats.wins += 1,
+                Some(_) => {}
+                None => stats.draws += 1,
+            }
+        }
+        self.games += 1;
+        Ok(())
+    }
+
+    /// 按条件筛选，权重为胜局数的两倍加和局数
+    pub fn build(self) -> Book {
+        let options: BookOptions = self.options;
+        let mut entries: Vec<BookEntry> = self.stats.into_iter()
+            .filter(|(_, stats)| stats.games >= options.min_games)
+            .filter(|(_, stats)| {
+                let score: f64 = (stats.wins as f64 + stats.draws as f64 / 2.0) * 100.0 / stats.games as f64;
+                score >= options.min_score
+            })
+            .map(|((key, mv), stats)| BookEntry {
+                games: stats.games,
+                ..BookEntry::new(key, mv, (stats.wins * 2 + stats.draws).min(u16::MAX as u32) as u16)
+            })
+            .filter(|entry| entry.weight > 0)
+            .collect();
+        entries.sort_by_key(|e| (e.key, std::cmp::Reverse(e.weight)));
+        Book { entries, fens: HashMap::new() }
+    }
+}
+
+/// 引擎从初始局面自对弈一局，走满 max_ply 个半回合或一方无着可走为止
+/// 引擎须已将 MultiPV 设为 SELF_PLAY_LINES；未分胜负时按最后一步的评分判定结果
+pub async fn self_play_game(engine: &mut Box<dyn EngineProtocol>, max_ply: usize, think_time: usize) -> Result<GameRecord> {
+    let mut state: GameState = GameState::new();
+    let mut record: GameRecord = GameRecord::new(START_FEN);
+    // 红方视角的最后评分
+    let mut score: Option<isize> = None;
+    let mut result: GameResult = GameResult::Unknown;
+    while record.moves.len() < max_ply {
+        if state.is_game_over() {
+            result = match state.current_player {
+                PlayerColor::Red => GameResult::BlackWin,
+                PlayerColor::Black => GameResult::RedWin,
+            };
+            break;
+        }
+        engine.set_position(&state.to_fen()).await?;
+        let go: EngineGoResult = engine.go(Some(think_time)).await?;
+
+        // 每个主变取最后一条带分数的信息，第一主变为最佳着法
+        let mut lines: Vec<Option<(Move, isize)>> = vec![None; SELF_PLAY_LINES];
+        for info in &go.infos {
+            if let Some(slot) = lines.get_mut(info.multipv.unwrap_or(1).wrapping_sub(1))
+                && let Some(first) = info.pv.as_ref().and_then(|pv| pv.first())
+                && let Some(centipawns) = info.centipawns()
+            {
+                *slot = Some((Move::from_str(first)?, centipawns));
+            }
+        }
+        let candidates: Vec<(Move, isize)> = match lines[0] {
+            Some((_, best)) => lines.iter().flatten().copied().filter(|&(_, s)| s >= best - SELF_PLAY_MARGIN).collect(),
+            None => Vec::new(),
+        };
+        let (mv, mover_score) = match candidates.len() {
+            0 => (Move::from_str(&go.best_move)?, None),
+            n => {
+                let (mv, s) = candidates[rand::rng().random_range(0..n)];
+                (mv, Some(s))
+            }
+        };
+        score = mover_score.map(|s| if state.current_player == PlayerColor::Red { s } else { -s });
+        state.play_move(mv).with_context(|| format!("引擎给出无效着法 {}", mv))?;
+        record.moves.push(RecordMove::new(mv));
+    }
+    if result == GameResult::Unknown {
+        result = match score {
+            Some(s) if s >= SELF_PLAY_ADJUDICATION => GameResult::RedWin,
+            Some(s) if s <= -SELF_PLAY_ADJUDICATION => GameResult::BlackWin,
+            Some(_) => GameResult::Draw,
+            None => GameResult::Unknown,
+        };
+    }
+    record.tags.push(("Result".to_string(), result.as_str().to_string()));
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn book_file(name: &str, from: u8, to: u8) -> PathBuf {
+        let mut data: Vec<u8> = MAGIC.to_vec();
+        data.extend_from_slice(&1u64.to_le_bytes());
+        data.extend_from_slice(&[from, to]);
+        data.extend_from_slice(&10u16.to_le_bytes());
+        data.extend_from_slice(&3u32.to_le_bytes());
+        let path: PathBuf = std::env::temp_dir().join(format!("chess-cli-{}-{}.bin", name, std::process::id()));
+        std::fs::write(&path, data).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_rejects_bad_squares() {
+        let valid: PathBuf = book_file("book-valid", 0, 89);
+        let book: Result<Book> = Book::load(&valid);
+        std::fs::remove_file(&valid).unwrap();
+        assert_eq!(book.unwrap().len(), 1);
+
+        let bad: PathBuf = book_file("book-bad", 0, 90);
+        let book: Result<Book> = Book::load(&bad);
+        std::fs::remove_file(&bad).unwrap();
+        assert!(book.is_err());
+    }
+
+    fn game(result: &str, moves: &[&str]) -> GameRecord {
+        let mut record: GameRecord = GameRecord::new(START_FEN);
+        record.tags.push(("Result".to_string(), result.to_string()));
+        record.moves = moves.iter().map(|mv| RecordMove::new(Move::from_str(mv).unwrap())).collect();
+        record
+    }
+
+    fn build(options: BookOptions) -> Book {
+        let mut builder: BookBuilder = BookBuilder::new(options);
+        builder.add_game(&game("1-0", &["h2e2", "h9g7"])).unwrap();
+        builder.add_game(&game("0-1", &["h2e2", "b9c7"])).unwrap();
+        builder.add_game(&game("1/2-1/2", &["b2e2"])).unwrap();
+        assert!(builder.add_game(&game("1-0", &["e0e2"])).is_err());
+        assert_eq!(builder.games, 3);
+        builder.build()
+    }
+
+    /// 全部条目的着法、权重和对局数
+    fn summary(book: &Book) -> Vec<(String, u16, u32)> {
+        let mut entries: Vec<(String, u16, u32)> = book.entries.iter().map(|e| (e.mv.to_string(), e.weight, e.games)).collect();
+        entries.sort();
+        entries
+    }
+
+    #[test]
+    fn builder_weights() {
+        // 权重为走子方胜局数的两倍加和局数，零权重的着法不收录
+        let book: Book = build(BookOptions::default());
+        assert_eq!(summary(&book), [("b2e2".to_string(), 1, 1), ("b9c7".to_string(), 2, 1), ("h2e2".to_string(), 2, 2)]);
+        let start: Vec<Move> = book.probe(GameState::new().key()).iter().map(|e| e.mv).collect();
+        assert_eq!(start, [Move::from_str("h2e2").unwrap(), Move::from_str("b2e2").unwrap()]);
+    }
+
+    #[test]
+    fn builder_filters() {
+        let min_games: Book = build(BookOptions { min_games: 2, ..BookOptions::default() });
+        assert_eq!(summary(&min_games), [("h2e2".to_string(), 2, 2)]);
+        let min_score: Book = build(BookOptions { min_score: 60.0, ..BookOptions::default() });
+        assert_eq!(summary(&min_score), [("b9c7".to_string(), 2, 1)]);
+        let max_ply: Book = build(BookOptions { max_ply: 1, ..BookOptions::default() });
+        assert_eq!(summary(&max_ply), [("b2e2".to_string(), 1, 1), ("h2e2".to_string(), 2, 2)]);
+    }
+
+    #[test]
+    fn choose_respects_marks() {
+        let state: GameState = GameState::new();
+        let key: u64 = state.key();
+        let entry = |mv: &str, weight: u16, mark: Option<MoveMark>| BookEntry { mark, ..BookEntry::new(key, Move::from_str(mv).unwrap(), weight) };
+
+        // 有主着时只选主着，即使权重为零
+        let main: Book = Book { entries: vec![entry("h2e2", 100, None), entry("b2e2", 0, Some(MoveMark::Main))], fens: HashMap::new() };
+        // 避免的着法从不选择
+        let avoid: Book = Book { entries: vec![entry("h2e2", 100, Some(MoveMark::Avoid)), entry("b2e2", 1, None)], fens: HashMap::new() };
+        for _ in 0..50 {
+            assert_eq!(main.choose(&state), Some(Move::from_str("b2e2").unwrap()));
+            assert_eq!(avoid.choose(&state), Some(Move::from_str("b2e2").unwrap()));
+        }
+        let only_avoid: Book = Book { entries: vec![entry("h2e2", 100, Some(MoveMark::Avoid))], fens: HashMap::new() };
+        assert_eq!(only_avoid.choose(&state), None);
+    }
+}
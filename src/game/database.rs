@@ -0,0 +1,360 @@
+use crate::utils::*;
+use crate::game::{classify, parse_dhtmlxq, parse_pgn, parse_xqf, GameRecord, GameResult, GameState, Move, MoveFormat, Variant};
+use std::io::{Read, Seek, SeekFrom};
+
+/// 棋谱正文文件名，全部对局依次追加
+const GAMES_FILE: &str = "games.pgn";
+/// 索引文件名，每局一行
+const INDEX_FILE: &str = "index.txt";
+/// 描述开局的标签
+const OPENING_TAGS: [&str; 3] = ["ECCO", "Opening", "Variation"];
+
+/// 读取棋谱文件中的全部对局
+/// 支持 PGN、DhtmlXQ（UTF-8 或 GBK 编码）和 XQF 格式
+pub fn read_game_file(path: &str) -> Result<Vec<GameRecord>> {
+    let data: Vec<u8> = std::fs::read(path).with_context(|| format!("无法读取文件 {}", path))?;
+    if data.starts_with(b"XQ") {
+        return Ok(vec![parse_xqf(&data)?]);
+    }
+    let text: String = match String::from_utf8(data) {
+        Ok(text) => text,
+        Err(e) => encoding_rs::GBK.decode(e.as_bytes()).0.into_owned(),
+    };
+    if text.contains("[DhtmlXQ") {
+        Ok(vec![parse_dhtmlxq(&text)?])
+    } else {
+        parse_pgn(&text)
+    }
+}
+
+/// 棋谱库中一局的索引
+#[derive(Debug, Clone)]
+pub struct GameEntry {
+    /// 棋谱正文在文件中的位置和长度
+    offset: u64,
+    length: u64,
+    pub red: String,
+    pub black: String,
+    pub date: String,
+    pub event: String,
+    pub result: GameResult,
+    /// 双方等级分
+    pub red_elo: Option<u32>,
+    pub black_elo: Option<u32>,
+    /// ECCO 编号和开局名称
+    pub opening: String,
+    /// 主线着法
+    pub moves: Vec<Move>,
+    /// 主线每个局面的 Zobrist 键值，第一个为起始局面
+    pub keys: Vec<u64>,
+}
+
+/// 索引字段中去掉分隔符
+fn clean(s: &str) -> String {
+    s.replace(['\t', '\n', '\r'], " ")
+}
+
+impl GameEntry {
+    /// 由棋谱建立索引，揭棋对局无法重现局面
+    fn from_record(record: &GameRecord) -> Result<Self> {
+        let mut state: GameState = record.initial_state()?;
+        if state.variant == Variant::Jieqi {
+            return Err(anyhow!("揭棋对局无法建立局面索引"));
+        }
+        let mut keys: Vec<u64> = vec![state.key()];
+        for (i, record_move) in record.moves.iter().enumerate() {
+            state.play_move(record_move.mv)
+                .with_context(|| format!("第{}步着法无效", i + 1))?;
+            keys.push(state.key());
+        }
+        let tag = |key: &str| clean(record.tag(key).unwrap_or_default());
+        let mut opening: Vec<String> = OPENING_TAGS.iter()
+            .map(|key| tag(key))
+            .filter(|value| !value.is_empty())
+            .collect();
+        // 没有开局标签时按内置开局表分类，只收录能确定编号的
+        if opening.is_empty()
+            && keys.first() == Some(&GameState::new().key())
+            && let Some(classified) = classify(keys.iter().copied())
+            && classified.exact
+        {
+            opening.push(classified.to_string());
+        }
+        Ok(Self {
+            offset: 0,
+            length: 0,
+            red: tag("Red"),
+            black: tag("Black"),
+            date: tag("Date"),
+            event: tag("Event"),
+            result: record.result(),
+            red_elo: record.tag("RedElo").and_then(|elo| elo.trim().parse().ok()),
+            black_elo: record.tag("BlackElo").and_then(|elo| elo.trim().parse().ok()),
+            opening: opening.join(" "),
+            moves: record.moves.iter().map(|m| m.mv).collect(),
+            keys,
+        })
+    }
+
+    fn to_line(&self) -> String {
+        let moves: Vec<String> = self.moves.iter().map(|mv| mv.to_string()).collect();
+        let keys: Vec<String> = self.keys.iter().map(|key| format!("{:016x}", key)).collect();
+        let elo = |elo: Option<u32>| elo.map_or_else(String::new, |elo| elo.to_string());
+        [
+            self.offset.to_string(),
+            self.length.to_string(),
+            self.red.clone(),
+            self.black.clone(),
+            self.date.clone(),
+            self.event.clone(),
+            self.result.as_str().to_string(),
+            self.opening.clone(),
+            moves.join(" "),
+            keys.join(" "),
+            elo(self.red_elo),
+            elo(self.black_elo),
+        ].join("\t")
+    }
+
+    fn from_line(line: &str) -> Result<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let [offset, length, red, black, date, event, result, opening, moves, keys, red_elo, black_elo] = fields.as_slice() else {
+            return Err(anyhow!("索引格式错误"));
+        };
+        let elo = |elo: &str| elo.parse().ok();
+        Ok(Self {
+            offset: offset.parse()?,
+            length: length.parse()?,
+            red: red.to_string(),
+            black: black.to_string(),
+            date: date.to_string(),
+            event: event.to_string(),
+            result: GameResult::from_str(result)?,
+            red_elo: elo(red_elo),
+            black_elo: elo(black_elo),
+            opening: opening.to_string(),
+            moves: moves.split_whitespace().map(Move::from_str).collect::<Result<_>>()?,
+            keys: keys.split_whitespace()
+                .map(|key| u64::from_str_radix(key, 16).map_err(|_| anyhow!("无效的局面键值: {}", key)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// 是否与另一局为同一对局
+    fn same_game(&self, other: &GameEntry) -> bool {
+        self.red == other.red && self.black == other.black && self.date == other.date
+            && self.keys.first() == other.keys.first() && self.moves == other.moves
+    }
+}
+
+/// 对局查询条件，未设置的条件不限制；文本条件不区分大小写，按包含匹配
+#[derive(Debug, Clone, Default)]
+pub struct GameQuery {
+    /// 红方或黑方
+    pub player: Option<String>,
+    pub red: Option<String>,
+    pub black: Option<String>,
+    pub event: Option<String>,
+    pub result: Option<GameResult>,
+    /// 日期前缀，例如 2023 或 2023.05
+    pub date: Option<String>,
+    /// 开局：ECCO 编号或名称，或从起始局面开始的坐标着法序列
+    pub opening: Option<String>,
+}
+
+impl GameQuery {
+    /// 由 "条件 值" 成对的参数构建，例如 player 许银川 result 1-0
+    pub fn parse(args: &[&str]) -> Result<Self> {
+        let mut query: GameQuery = Self::default();
+        let mut args: std::slice::Iter<'_, &str> = args.iter();
+        while let Some(&key) = args.next() {
+            let key: String = key.to_lowercase();
+            // 开局着法序列占用其余全部参数
+            if key == "opening" {
+                let rest: Vec<&str> = args.by_ref().copied().collect();
+                if rest.is_empty() {
+                    return Err(anyhow!("opening 条件缺少取值"));
+                }
+                query.opening = Some(rest.join(" "));
+                break;
+            }
+            let value: String = args.next().ok_or_else(|| anyhow!("{} 条件缺少取值", key))?.to_string();
+            match key.as_str() {
+                "player" => query.player = Some(value),
+                "red" => query.red = Some(value),
+                "black" => query.black = Some(value),
+                "event" => query.event = Some(value),
+                "result" => query.result = Some(GameResult::from_str(&value)?),
+                "date" => query.date = Some(value),
+                _ => return Err(anyhow!("未知的查询条件: {}，可用 player red black event result date opening", key)),
+            }
+        }
+        Ok(query)
+    }
+
+    fn matches(&self, entry: &GameEntry) -> bool {
+        let contains = |text: &str, pattern: &Option<String>| {
+            pattern.as_ref().is_none_or(|p| text.to_lowercase().contains(&p.to_lowercase()))
+        };
+        let player: bool = self.player.as_ref().is_none_or(|_| contains(&entry.red, &self.player) || contains(&entry.black, &self.player));
+        player
+            && contains(&entry.red, &self.red)
+            && contains(&entry.black, &self.black)
+            && contains(&entry.event, &self.event)
+            && self.result.is_none_or(|result| entry.result == result)
+            && self.date.as_ref().is_none_or(|date| entry.date.starts_with(date.as_str()))
+            && self.opening.as_ref().is_none_or(|opening| Self::matches_opening(entry, opening))
+    }
+
+    /// 全部为坐标着法时按着法前缀匹配，否则匹配开局标签
+    fn matches_opening(entry: &GameEntry, opening: &str) -> bool {
+        let moves: Result<Vec<Move>> = opening.split_whitespace().map(Move::from_str).collect();
+        match moves {
+            Ok(moves) => entry.moves.starts_with(&moves),
+            Err(_) => entry.opening.to_lowercase().contains(&opening.to_lowercase()),
+        }
+    }
+}
+
+/// 查询命中的对局，ply 为匹配局面所在的半回合数
+#[derive(Debug, Clone, Copy)]
+pub struct GameHit {
+    pub id: usize,
+    pub ply: usize,
+}
+
+/// 导入结果统计
+#[derive(Debug, Clone, Default)]
+pub struct ImportSummary {
+    pub added: usize,
+    /// 库中已有的重复对局
+    pub duplicates: usize,
+    /// 无法解析或无法重现的对局
+    pub failed: usize,
+}
+
+/// 本地棋谱库，保存在用户数据目录中；只追加，不删除
+pub struct Database {
+    dir: PathBuf,
+    entries: Vec<GameEntry>,
+    /// 局面键值到 (对局编号, 半回合数) 的索引
+    positions: HashMap<u64, Vec<(usize, usize)>>,
+}
+
+impl Database {
+    /// 打开默认位置的棋谱库，不存在时创建空库
+    pub fn open() -> Result<Self> {
+        let dir: PathBuf = data_dir("database")?;
+
+        let mut database: Database = Self { dir, entries: Vec::new(), positions: HashMap::new() };
+        let index: PathBuf = database.dir.join(INDEX_FILE);
+        if index.exists() {
+            let text: String = read_to_string(&index).with_context(|| format!("无法读取 {}", index.display()))?;
+            for (i, line) in text.lines().enumerate().filter(|(_, line)| !line.is_empty()) {
+                let entry: GameEntry = GameEntry::from_line(line).with_context(|| format!("索引第{}行损坏", i + 1))?;
+                database.insert(entry);
+            }
+        }
+        Ok(database)
+    }
+
+    fn insert(&mut self, entry: GameEntry) {
+        let id: usize = self.entries.len();
+        for (ply, &key) in entry.keys.iter().enumerate() {
+            self.positions.entry(key).or_default().push((id, ply));
+        }
+        self.entries.push(entry);
+    }
+
+    /// 库中对局数
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// 读取对局索引
+    pub fn entry(&self, id: usize) -> &GameEntry {
+        &self.entries[id]
+    }
+
+    /// 导入棋谱文件中的全部对局，跳过重复和无效的对局
+    pub fn import(&mut self, path: &str) -> Result<ImportSummary> {
+        let records: Vec<GameRecord> = read_game_file(path)?;
+        let mut summary: ImportSummary = ImportSummary::default();
+        let mut games: std::fs::File = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(GAMES_FILE))?;
+        let mut index: std::fs::File = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.dir.join(INDEX_FILE))?;
+        let mut offset: u64 = games.metadata()?.len();
+
+        for record in &records {
+            let (mut entry, pgn) = match GameEntry::from_record(record).and_then(|entry| Ok((entry, record.to_pgn(MoveFormat::Iccs)?))) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    log_warn!(e);
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+            if self.duplicate(&entry) {
+                summary.duplicates += 1;
+                continue;
+            }
+            let text: String = format!("{}\n\n", pgn.trim_end());
+            entry.offset = offset;
+            entry.length = text.len() as u64;
+            games.write_all(text.as_bytes())?;
+            writeln!(index, "{}", entry.to_line())?;
+            offset += entry.length;
+            self.insert(entry);
+            summary.added += 1;
+        }
+        Ok(summary)
+    }
+
+    /// 库中是否已有同一对局
+    fn duplicate(&self, entry: &GameEntry) -> bool {
+        let Some(&start) = entry.keys.first() else { return false };
+        self.positions.get(&start).is_some_and(|hits| {
+            hits.iter().any(|&(id, ply)| ply == 0 && self.entries[id].same_game(entry))
+        })
+    }
+
+    /// 按条件查找对局
+    pub fn find(&self, query: &GameQuery) -> Vec<GameHit> {
+        self.entries.iter()
+            .enumerate()
+            .filter(|(_, entry)| query.matches(entry))
+            .map(|(id, _)| GameHit { id, ply: 0 })
+            .collect()
+    }
+
+    /// 查找出现过该局面的对局，不论着法次序；每局取第一次出现的位置
+    pub fn find_position(&self, key: u64) -> Vec<GameHit> {
+        let mut hits: Vec<GameHit> = Vec::new();
+        for &(id, ply) in self.positions.get(&key).map_or(&[][..], |hits| hits.as_slice()) {
+            if hits.last().is_none_or(|hit| hit.id != id) {
+                hits.push(GameHit { id, ply });
+            }
+        }
+        hits
+    }
+
+    /// 读取完整棋谱
+    pub fn load(&self, id: usize) -> Result<GameRecord> {
+        let entry: &GameEntry = self.entries.get(id).ok_or_else(|| anyhow!("棋谱库中没有第{}局", id + 1))?;
+        let mut file: std::fs::File = std::fs::File::open(self.dir.join(GAMES_FILE))?;
+        file.seek(SeekFrom::Start(entry.offset))?;
+        let mut text: String = String::new();
+        file.take(entry.length).read_to_string(&mut text)?;
+        parse_pgn(&text)?.into_iter().next().ok_or_else(|| anyhow!("棋谱库第{}局已损坏", id + 1))
+    }
+}
+
@@ -0,0 +1,372 @@
+use crate::utils::*;
+use crate::game::{
+    Board, GENERATED_TAGS, GameRecord, GameResult, GameState, Move, Piece, PieceKind, PlayerColor, Position, RecordMove, Variant,
+};
+
+/// binit 中32个棋子的顺序：红方车马相仕帅仕相马车炮炮兵兵兵兵兵，黑方相同
+const PIECE_ORDER: [PieceKind; 16] = [
+    PieceKind::Rook, PieceKind::Horse, PieceKind::Elephant, PieceKind::Advisor,
+    PieceKind::General, PieceKind::Advisor, PieceKind::Elephant, PieceKind::Horse,
+    PieceKind::Rook, PieceKind::Cannon, PieceKind::Cannon, PieceKind::Pawn,
+    PieceKind::Pawn, PieceKind::Pawn, PieceKind::Pawn, PieceKind::Pawn,
+];
+
+/// 不在棋盘上的棋子坐标
+const OFF_BOARD: &str = "99";
+
+/// 文本字段与PGN标签的对应关系
+const TEXT_TAGS: [(&str, &str); 7] = [
+    ("title", "Title"),
+    ("event", "Event"),
+    ("round", "Round"),
+    ("date", "Date"),
+    ("place", "Site"),
+    ("red", "Red"),
+    ("black", "Black"),
+];
+
+/// 没有对应字段的其他标签，每行一个 "标签=值"
+const EXTRA_TAGS_FIELD: &str = "tags";
+
+/// DhtmlXQ 坐标 (x, y)：x为列（0-8从左到右），y为行（0为黑方底线）
+fn parse_square(x: u8, y: u8) -> Result<Position> {
+    if !(b'0'..=b'8').contains(&x) || !y.is_ascii_digit() {
+        return Err(anyhow!("DhtmlXQ坐标无效: {}{}", x as char, y as char));
+    }
+    Ok(Position { row: 9 - (y - b'0') as usize, col: (x - b'0') as usize })
+}
+
+fn format_square(pos: Position) -> String {
+    format!("{}{}", pos.col, 9 - pos.row)
+}
+
+/// 解析四位数字一组的着法串
+fn parse_move_list(text: &str) -> Result<Vec<Move>> {
+    let bytes: &[u8] = text.trim().as_bytes();
+    if !bytes.len().is_multiple_of(4) {
+        return Err(anyhow!("着法串长度应为4的倍数: {}", text));
+    }
+    bytes.chunks(4)
+        .map(|c| Ok(Move::new(parse_square(c[0], c[1])?, parse_square(c[2], c[3])?)))
+        .collect()
+}
+
+fn format_move(mv: Move) -> String {
+    format!("{}{}", format_square(mv.source()), format_square(mv.target()))
+}
+
+/// 注释中的 "||" 表示换行
+fn decode_comment(text: &str) -> Option<String> {
+    Some(text.trim().replace("||", "\n")).filter(|s| !s.is_empty())
+}
+
+/// 方括号换成全角，避免注释中出现字段结束标记
+fn encode_text(text: &str) -> String {
+    text.replace('[', "［").replace(']', "］")
+}
+
+fn encode_comment(text: &str) -> String {
+    encode_text(text).replace('\n', "||")
+}
+
+/// 提取全部 [DhtmlXQ_键]值[/DhtmlXQ_键] 字段
+fn parse_fields(text: &str) -> HashMap<String, String> {
+    let mut fields: HashMap<String, String> = HashMap::new();
+    let mut rest: &str = text;
+    while let Some(start) = rest.find("[DhtmlXQ_") {
+        let after: &str = &rest[start + "[DhtmlXQ_".len()..];
+        let Some(close) = after.find(']') else { break };
+        let key: &str = &after[..close];
+        let body: &str = &after[close + 1..];
+        let end_tag: String = format!("[/DhtmlXQ_{}]", key);
+        match body.find(&end_tag) {
+            Some(end) => {
+                fields.insert(key.to_lowercase(), body[..end].to_string());
+                rest = &body[end + end_tag.len()..];
+            }
+            None => rest = body,
+        }
+    }
+    fields
+}
+
+/// 变着分支：父分支编号、起始半回合（从1开始）和着法
+struct Branch {
+    parent: usize,
+    ply: usize,
+    moves: Vec<Move>,
+}
+
+/// 导入时的分支树
+struct BranchSet {
+    branches: HashMap<usize, Branch>,
+    fields: HashMap<String, String>,
+}
+
+impl BranchSet {
+    fn comment(&self, branch: usize, ply: usize) -> Option<String> {
+        let key: String = if branch == 0 { format!("comment{}", ply) } else { format!("comment{}_{}", branch, ply) };
+        self.fields.get(&key).and_then(|c| decode_comment(c))
+    }
+
+    /// 按分支构建着法序列，子分支挂在其替代的着法上
+    fn build_line(&self, id: usize, state: &GameState) -> Result<Vec<RecordMove>> {
+        let branch: &Branch = &self.branches[&id];
+        let mut children: Vec<(usize, &Branch)> = self.branches.iter()
+            .filter(|&(&child, b)| child != id && b.parent == id)
+            .map(|(&child, b)| (child, b))
+            .collect();
+        children.sort_by_key(|(child, _)| *child);
+
+        let mut state: GameState = state.snapshot();
+        let mut line: Vec<RecordMove> = Vec::with_capacity(branch.moves.len());
+        for (i, &mv) in branch.moves.iter().enumerate() {
+            let ply: usize = branch.ply + i;
+            let mut record_move: RecordMove = RecordMove::new(mv);
+            record_move.comment = self.comment(id, ply);
+            for &(child, child_branch) in &children {
+                if child_branch.ply == ply {
+                    record_move.variations.push(self.build_line(child, &state)?);
+                }
+            }
+            state.play_move(mv)
+                .with_context(|| format!("第{}步着法无效", ply))?;
+            line.push(record_move);
+        }
+        Ok(line)
+    }
+}
+
+/// 解析 DhtmlXQ 棋谱块
+pub fn parse_dhtmlxq(text: &str) -> Result<GameRecord> {
+    let fields: HashMap<String, String> = parse_fields(text);
+    if fields.is_empty() {
+        return Err(anyhow!("没有找到 DhtmlXQ 棋谱字段"));
+    }
+
+    // 变着分支，键 move_父分支_起始步_分支编号
+    let mut branches: HashMap<usize, Branch> = HashMap::new();
+    let main: Vec<Move> = fields.get("movelist").map(|m| parse_move_list(m)).transpose()?.unwrap_or_default();
+    branches.insert(0, Branch { parent: 0, ply: 1, moves: main });
+    for (key, value) in &fields {
+        let Some(spec) = key.strip_prefix("move_") else { continue };
+        let numbers: Vec<usize> = spec.split('_')
+            .map(|n| n.parse().map_err(|_| anyhow!("变着字段无效: {}", key)))
+            .collect::<Result<_>>()?;
+        let [parent, ply, id] = numbers[..] else {
+            return Err(anyhow!("变着字段无效: {}", key));
+        };
+        branches.insert(id, Branch { parent, ply, moves: parse_move_list(value)? });
+    }
+    for (id, branch) in &branches {
+        if *id != 0 && !branches.contains_key(&branch.parent) {
+            return Err(anyhow!("变着{}的父分支{}不存在", id, branch.parent));
+        }
+    }
+
+    // 初始局面，缺省为标准开局
+    let board: Board = match fields.get("binit") {
+        Some(binit) => {
+            let binit: &str = binit.trim();
+            if binit.len() != 64 || !binit.is_ascii() {
+                return Err(anyhow!("binit 应为64位数字"));
+            }
+            let mut board: Board = Board::empty();
+            for (i, c) in binit.as_bytes().chunks(2).enumerate() {
+                if c == OFF_BOARD.as_bytes() {
+                    continue;
+                }
+                let color: PlayerColor = if i < 16 { PlayerColor::Red } else { PlayerColor::Black };
+                board.put(parse_square(c[0], c[1])?, Piece { color, kind: PIECE_ORDER[i % 16], hidden: false })?;
+            }
+            board
+        }
+        None => GameState::new().board,
+    };
+
+    // 首步着法的棋子决定先走方
+    let first_mover: PlayerColor = branches[&0].moves.first()
+        .and_then(|mv| board.get(mv.from))
+        .map_or(PlayerColor::Red, |p| p.color);
+    let state: GameState = GameState::from_board(board, first_mover);
+    state.validate_setup().context("DhtmlXQ初始局面不合法")?;
+
+    let set: BranchSet = BranchSet { branches, fields };
+    let mut record: GameRecord = GameRecord::new(&state.to_fen());
+    for (field, tag) in TEXT_TAGS {
+        if let Some(value) = set.fields.get(field).map(|v| v.trim()).filter(|v| !v.is_empty()) {
+            record.set_tag(tag, value);
+        }
+    }
+    if let Some(extra) = set.fields.get(EXTRA_TAGS_FIELD).and_then(|e| decode_comment(e)) {
+        for (key, value) in extra.lines().filter_map(|line| line.split_once('=')) {
+            record.set_tag(key.trim(), value.trim());
+        }
+    }
+    let result: GameResult = set.fields.get("result")
+        .and_then(|r| GameResult::from_str(r.trim()).ok())
+        .unwrap_or_default();
+    record.set_tag("Result", result.as_str());
+    record.comment = set.comment(0, 0);
+    record.moves = set.build_line(0, &state)?;
+    Ok(record)
+}
+
+/// 导出时按深度优先顺序给变着编号
+struct BranchWriter {
+    out: String,
+    next_id: usize,
+}
+
+impl BranchWriter {
+    fn write_line(&mut self, id: usize, moves: &[RecordMove], start_ply: usize) {
+        for (i, record_move) in moves.iter().enumerate() {
+            let ply: usize = start_ply + i;
+            if let Some(comment) = &record_move.comment {
+                let key: String = if id == 0 { format!("comment{}", ply) } else { format!("comment{}_{}", id, ply) };
+                self.out.push_str(&format!("[DhtmlXQ_{0}]{1}[/DhtmlXQ_{0}]\n", key, encode_comment(comment)));
+            }
+            for variation in &record_move.variations {
+                self.next_id += 1;
+                let child: usize = self.next_id;
+                let key: String = format!("move_{}_{}_{}", id, ply, child);
+                let list: String = variation.iter().map(|m| format_move(m.mv)).collect();
+                self.out.push_str(&format!("[DhtmlXQ_{0}]{1}[/DhtmlXQ_{0}]\n", key, list));
+                self.write_line(child, variation, ply);
+            }
+        }
+    }
+}
+
+impl GameRecord {
+    /// 导出为 DhtmlXQ 棋谱块
+    pub fn to_dhtmlxq(&self) -> Result<String> {
+        let state: GameState = self.initial_state()?;
+        if state.variant == Variant::Jieqi {
+            return Err(anyhow!("DhtmlXQ格式不支持揭棋"));
+        }
+        // 校验全部着法
+        self.validate()?;
+
+        // 按棋子种类依次填入32个位置
+        let mut slots: [String; 32] = std::array::from_fn(|_| OFF_BOARD.to_string());
+        for (pos, piece) in state.board.iter() {
+            let base: usize = if piece.color == PlayerColor::Red { 0 } else { 16 };
+            let slot: Option<usize> = (0..16)
+                .find(|&i| PIECE_ORDER[i] == piece.kind && slots[base + i] == OFF_BOARD);
+            let slot: usize = slot.ok_or_else(|| anyhow!("棋子数量超出DhtmlXQ格式限制"))?;
+            slots[base + slot] = format_square(pos);
+        }
+
+        let mut writer: BranchWriter = BranchWriter { out: String::from("[DhtmlXQ]\n"), next_id: 0 };
+        for (field, tag) in TEXT_TAGS {
+            if let Some(value) = self.tag(tag) {
+                writer.out.push_str(&format!("[DhtmlXQ_{0}]{1}[/DhtmlXQ_{0}]\n", field, encode_text(value)));
+            }
+        }
+        let extra: Vec<String> = self.tags.iter()
+            .filter(|(key, _)| {
+                !key.eq_ignore_ascii_case("Result")
+                    && !GENERATED_TAGS.iter().any(|t| t.eq_ignore_ascii_case(key))
+                    && !TEXT_TAGS.iter().any(|(_, tag)| tag.eq_ignore_ascii_case(key))
+            })
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        if !extra.is_empty() {
+            writer.out.push_str(&format!("[DhtmlXQ_{0}]{1}[/DhtmlXQ_{0}]\n", EXTRA_TAGS_FIELD, encode_comment(&extra.join("\n"))));
+        }
+        writer.out.push_str(&format!("[DhtmlXQ_result]{}[/DhtmlXQ_result]\n", self.result().name()));
+        writer.out.push_str(&format!("[DhtmlXQ_binit]{}[/DhtmlXQ_binit]\n", slots.concat()));
+        let main: String = self.moves.iter().map(|m| format_move(m.mv)).collect();
+        writer.out.push_str(&format!("[DhtmlXQ_movelist]{}[/DhtmlXQ_movelist]\n", main));
+        if let Some(comment) = &self.comment {
+            writer.out.push_str(&format!("[DhtmlXQ_comment0]{}[/DhtmlXQ_comment0]\n", encode_comment(comment)));
+        }
+        writer.write_line(0, &self.moves, 1);
+        writer.out.push_str("[/DhtmlXQ]\n");
+        Ok(writer.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{parse_pgn, START_FEN};
+
+    const GAME: &str = "[Event \"Cup\"]\n[Red \"A\"]\n[Result \"0-1\"]\n\n{开局}\n\
+        1. h2e2 {中炮} (1. b2e2 {过宫炮} h9g7 (1... b9c7)) h9g7 2. h0g2 *\n";
+
+    /// 着法树的简要形式，便于比较
+    fn summary(moves: &[RecordMove]) -> String {
+        moves.iter()
+            .map(|m| {
+                let variations: Vec<String> = m.variations.iter().map(|v| format!("({})", summary(v))).collect();
+                format!("{}{{{}}}{}", m.mv, m.comment.as_deref().unwrap_or(""), variations.concat())
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut record: GameRecord = parse_pgn(GAME).unwrap().remove(0);
+        record.moves[1].comment = Some("两行\n注释".to_string());
+        let text: String = record.to_dhtmlxq().unwrap();
+        let parsed: GameRecord = parse_dhtmlxq(&text).unwrap();
+        assert_eq!(parsed.start_fen, START_FEN);
+        assert_eq!(summary(&parsed.moves), summary(&record.moves), "{}", text);
+        assert_eq!(parsed.comment.as_deref(), Some("开局"));
+        assert_eq!(parsed.tag("Event"), Some("Cup"));
+        assert_eq!(parsed.tag("Red"), Some("A"));
+        assert_eq!(parsed.result(), GameResult::BlackWin);
+    }
+
+    #[test]
+    fn round_trip_from_position() {
+        let fen: &str = "4k4/9/9/9/9/9/9/9/4R4/3K5 b";
+        let text: String = format!("[FEN \"{}\"]\n\n1... e9f9 2. e1f1 *\n", fen);
+        let record: GameRecord = parse_pgn(&text).unwrap().remove(0);
+        let parsed: GameRecord = parse_dhtmlxq(&record.to_dhtmlxq().unwrap()).unwrap();
+        assert_eq!(parsed.start_fen, fen);
+        assert_eq!(summary(&parsed.moves), summary(&record.moves));
+    }
+
+    #[test]
+    fn metadata_round_trip() {
+        let mut record: GameRecord = parse_pgn(GAME).unwrap().remove(0);
+        record.set_tag("TimeControl", "40/5400");
+        record.set_tag("RedElo", "2500");
+        record.set_tag("Site", "北京");
+        let parsed: GameRecord = parse_dhtmlxq(&record.to_dhtmlxq().unwrap()).unwrap();
+        for key in ["Event", "Site", "Red", "TimeControl", "RedElo", "Result"] {
+            assert_eq!(parsed.tag(key), record.tag(key), "{}", key);
+        }
+    }
+
+    #[test]
+    fn brackets_in_comments_do_not_end_the_field() {
+        let mut record: GameRecord = parse_pgn(GAME).unwrap().remove(0);
+        record.moves[0].comment = Some("a [/DhtmlXQ_comment1] b".to_string());
+        record.set_tag("Event", "[/DhtmlXQ_event]x");
+        let parsed: GameRecord = parse_dhtmlxq(&record.to_dhtmlxq().unwrap()).unwrap();
+        assert_eq!(parsed.moves[0].comment.as_deref(), Some("a ［/DhtmlXQ_comment1］ b"));
+        assert_eq!(parsed.tag("Event"), Some("［/DhtmlXQ_event］x"));
+        assert_eq!(summary(&parsed.moves[1..]), summary(&record.moves[1..]));
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        for text in [
+            "",
+            "no fields here",
+            "[DhtmlXQ_movelist]7242797[/DhtmlXQ_movelist]",
+            "[DhtmlXQ_movelist]9242[/DhtmlXQ_movelist]",
+            "[DhtmlXQ_movelist]4047[/DhtmlXQ_movelist]",
+            "[DhtmlXQ_movelist]7242[/DhtmlXQ_movelist][DhtmlXQ_move_3_1_4]1242[/DhtmlXQ_move_3_1_4]",
+            "[DhtmlXQ_movelist]7242[/DhtmlXQ_movelist][DhtmlXQ_move_0_x_1]1242[/DhtmlXQ_move_0_x_1]",
+            "[DhtmlXQ_binit]0010[/DhtmlXQ_binit]",
+        ] {
+            assert!(parse_dhtmlxq(text).is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::utils::*;
+use crate::game::{GameState, Move};
+use std::sync::OnceLock;
+
+/// ECCO 开局
+#[derive(Debug)]
+pub struct Opening {
+    /// ECCO 编号，A00-E99
+    pub code: &'static str,
+    pub name: &'static str,
+    /// 从标准开局局面走出该开局的坐标着法
+    moves: &'static str,
+    /// 该编号不再细分，经过此局面的对局都属于该编号
+    leaf: bool,
+}
+
+impl std::fmt::Display for Opening {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.code, self.name)
+    }
+}
+
+impl Opening {
+    /// 开局着法的半回合数，用于在多个匹配中选取最深的一个
+    fn depth(&self) -> usize {
+        self.moves.split_whitespace().count()
+    }
+}
+
+/// 内置开局表，收录各开局体系及其主要分支，更细的编号未收录；左右对称的走法自动收录
+const OPENINGS: [Opening; 53] = [
+    Opening { code: "A01", name: "上仕局", moves: "f0e1", leaf: true },
+    Opening { code: "A02", name: "边马局", moves: "h0i2", leaf: true },
+    Opening { code: "A03", name: "边炮局", moves: "h2i2", leaf: true },
+    Opening { code: "A04", name: "巡河炮局", moves: "h2h4", leaf: true },
+    Opening { code: "A05", name: "过河炮局", moves: "h2h6", leaf: true },
+    Opening { code: "A06", name: "兵底炮局", moves: "h2g2", leaf: true },
+    Opening { code: "A08", name: "边兵局", moves: "i3i4", leaf: true },
+    Opening { code: "A10", name: "飞相局", moves: "g0e2", leaf: false },
+    Opening { code: "A13", name: "飞相对进左马", moves: "g0e2 h9g7", leaf: false },
+    Opening { code: "A20", name: "飞相对左士角炮", moves: "g0e2 h7f7", leaf: false },
+    Opening { code: "A27", name: "飞相对左过宫炮", moves: "g0e2 h7d7", leaf: false },
+    Opening { code: "A28", name: "飞相对右过宫炮", moves: "g0e2 b7f7", leaf: false },
+    Opening { code: "A29", name: "飞相对右中炮", moves: "g0e2 b7e7", leaf: false },
+    Opening { code: "A30", name: "飞相对左中炮", moves: "g0e2 h7e7", leaf: false },
+    Opening { code: "A36", name: "飞相对进7卒", moves: "g0e2 g6g5", leaf: false },
+    Opening { code: "A38", name: "飞相对进3卒", moves: "g0e2 c6c5", leaf: false },
+    Opening { code: "A40", name: "起马局", moves: "h0g2", leaf: false },
+    Opening { code: "A41", name: "起马对进7卒", moves: "h0g2 g6g5", leaf: false },
+    Opening { code: "A50", name: "仕角炮局", moves: "h2f2", leaf: false },
+    Opening { code: "A52", name: "仕角炮对右中炮", moves: "h2f2 b7e7", leaf: false },
+    Opening { code: "A60", name: "过宫炮局", moves: "h2d2", leaf: false },
+    Opening { code: "A61", name: "过宫炮对进左马", moves: "h2d2 h9g7", leaf: false },
+    Opening { code: "B00", name: "中炮局", moves: "h2e2", leaf: false },
+    Opening { code: "B05", name: "中炮对进左马", moves: "h2e2 h9g7", leaf: false },
+    Opening { code: "B10", name: "中炮对单提马", moves: "h2e2 b9a7 h0g2 h9g7", leaf: false },
+    Opening { code: "B20", name: "中炮对左三步虎", moves: "h2e2 h9g7 h0g2 h7i7", leaf: false },
+    Opening { code: "B30", name: "中炮对反宫马", moves: "h2e2 b9c7 h0g2 h7f7 i0h0 h9g7", leaf: false },
+    Opening { code: "C00", name: "中炮对屏风马", moves: "h2e2 h9g7 h0g2 b9c7", leaf: false },
+    Opening { code: "C01", name: "中炮七路马对屏风马", moves: "h2e2 h9g7 h0g2 b9c7 b0c2", leaf: false },
+    Opening { code: "C10", name: "中炮右横车对屏风马", moves: "h2e2 h9g7 h0g2 b9c7 i0i1", leaf: false },
+    Opening { code: "C15", name: "中炮巡河炮对屏风马", moves: "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 b2b4", leaf: false },
+    Opening { code: "C17", name: "中炮巡河车对屏风马", moves: "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 h0h4", leaf: false },
+    Opening { code: "C20", name: "中炮过河车对屏风马", moves: "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 h0h6", leaf: false },
+    Opening { code: "C25", name: "中炮过河车互进七兵对屏风马", moves: "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 c3c4 g6g5 h0h6", leaf: false },
+    Opening { code: "C30", name: "中炮过河车互进七兵对屏风马平炮兑车", moves: "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 c3c4 g6g5 h0h6 h7i7", leaf: false },
+    Opening { code: "C35", name: "中炮过河车互进七兵对屏风马左马盘河", moves: "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 c3c4 g6g5 h0h6 g7f5", leaf: false },
+    Opening { code: "C45", name: "中炮进七兵对屏风马", moves: "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 c3c4", leaf: false },
+    Opening { code: "C50", name: "五六炮对屏风马", moves: "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 b2d2", leaf: false },
+    Opening { code: "C60", name: "五七炮对屏风马", moves: "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 b2c2", leaf: false },
+    Opening { code: "D00", name: "顺炮局", moves: "h2e2 h7e7", leaf: false },
+    Opening { code: "D10", name: "顺炮直车对缓开车", moves: "h2e2 h7e7 h0g2 h9g7 i0h0", leaf: false },
+    Opening { code: "D20", name: "顺炮直车对横车", moves: "h2e2 h7e7 h0g2 h9g7 i0h0 i9i8", leaf: false },
+    Opening { code: "D30", name: "顺炮横车对直车", moves: "h2e2 h7e7 h0g2 h9g7 i0i1 i9h9", leaf: false },
+    Opening { code: "D50", name: "中炮对列炮", moves: "h2e2 b7e7", leaf: false },
+    Opening { code: "E00", name: "仙人指路", moves: "c3c4", leaf: false },
+    Opening { code: "E01", name: "仙人指路对中炮", moves: "c3c4 h7e7", leaf: false },
+    Opening { code: "E01", name: "仙人指路对中炮", moves: "c3c4 b7e7", leaf: false },
+    Opening { code: "E05", name: "仙人指路对飞象", moves: "c3c4 c9e7", leaf: false },
+    Opening { code: "E05", name: "仙人指路对飞象", moves: "c3c4 g9e7", leaf: false },
+    Opening { code: "E10", name: "仙人指路对卒底炮", moves: "c3c4 b7c7", leaf: false },
+    Opening { code: "E20", name: "仙人指路转左中炮对卒底炮", moves: "c3c4 b7c7 b2e2", leaf: false },
+    Opening { code: "E30", name: "仙人指路转右中炮对卒底炮", moves: "c3c4 b7c7 h2e2", leaf: false },
+    Opening { code: "E40", name: "对兵局", moves: "c3c4 c6c5", leaf: false },
+];
+
+/// 着法左右镜像，列 a-i 对调
+fn mirror(mv: &str) -> String {
+    mv.chars()
+        .map(|c| if c.is_ascii_lowercase() { (b'a' + b'i' - c as u8) as char } else { c })
+        .collect()
+}
+
+/// 开局局面键值到开局的索引，首次使用时建立
+fn table() -> &'static HashMap<u64, &'static Opening> {
+    static TABLE: OnceLock<HashMap<u64, &'static Opening>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let mut table: HashMap<u64, &'static Opening> = HashMap::new();
+        for opening in &OPENINGS {
+            for mirrored in [false, true] {
+                let mut state: GameState = GameState::new();
+                for mv in opening.moves.split_whitespace() {
+                    let mv: String = if mirrored { mirror(mv) } else { mv.to_string() };
+                    state.play_move(Move::from_str(&mv).expect("内置开局表着法无效")).expect("内置开局表着法无效");
+                }
+                table.entry(state.key()).or_insert(opening);
+            }
+        }
+        table
+    })
+}
+
+/// 开局分类结果
+#[derive(Debug, Clone, Copy)]
+pub struct Classification {
+    pub opening: &'static Opening,
+    /// 开局表是否确切覆盖该对局：编号不再细分，或对局停在该开局局面；否则只知道所属体系
+    pub exact: bool,
+}
+
+impl std::fmt::Display for Classification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.exact {
+            write!(f, "{}", self.opening)
+        } else {
+            write!(f, "{}体系", self.opening.name)
+        }
+    }
+}
+
+/// 按对局经过的局面键值判断开局，不论着法次序；取经过的最深的开局局面
+/// 只适用于从标准开局局面开始的对局
+pub fn classify(keys: impl IntoIterator<Item = u64>) -> Option<Classification> {
+    let table: &HashMap<u64, &'static Opening> = table();
+    let keys: Vec<u64> = keys.into_iter().collect();
+    let (key, opening) = keys.iter()
+        .filter_map(|key| table.get(key).map(|&opening| (*key, opening)))
+        .max_by_key(|(_, opening)| opening.depth())?;
+    let exact: bool = opening.leaf || keys.last() == Some(&key);
+    Some(Classification { opening, exact })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 从初始局面走出各步后经过的全部局面键值
+    fn classify_moves(moves: &str) -> Option<Classification> {
+        let mut state: GameState = GameState::new();
+        let mut keys: Vec<u64> = vec![state.key()];
+        for mv in moves.split_whitespace() {
+            state.play_move(Move::from_str(mv).unwrap()).unwrap();
+            keys.push(state.key());
+        }
+        classify(keys)
+    }
+
+    fn code(moves: &str) -> Option<(&'static str, bool)> {
+        classify_moves(moves).map(|c| (c.opening.code, c.exact))
+    }
+
+    #[test]
+    fn table_is_valid() {
+        assert_eq!(table().len(), OPENINGS.len() * 2 - OPENINGS.iter().filter(|o| o.moves.split_whitespace().all(|mv| mirror(mv) == mv)).count());
+        for opening in &OPENINGS {
+            let bytes: &[u8] = opening.code.as_bytes();
+            assert!(bytes.len() == 3 && (b'A'..=b'E').contains(&bytes[0]) && bytes[1..].iter().all(u8::is_ascii_digit), "{}", opening);
+        }
+    }
+
+    #[test]
+    fn classifies_c45() {
+        let moves: &str = "h2e2 h9g7 h0g2 i9h9 i0h0 b9c7 c3c4";
+        let classification: Classification = classify_moves(moves).unwrap();
+        assert_eq!(classification.to_string(), "C45 中炮进七兵对屏风马");
+        // 走出开局表以外的着法后只知道所属体系
+        assert_eq!(code(&format!("{} g6g5 b0c2", moves)), Some(("C45", false)));
+        assert_eq!(code("h2e2 h9g7"), Some(("B05", true)));
+        assert_eq!(code(""), None);
+    }
+
+    #[test]
+    fn classifies_transpositions() {
+        // 先挺七兵后架中炮，与 C45 的着法次序不同
+        assert_eq!(code("c3c4 h9g7 h2e2 b9c7 h0g2 i9h9 i0h0"), Some(("C45", true)));
+        assert_eq!(code("c3c4 b7c7 h2e2"), Some(("E30", true)));
+        assert_eq!(code("h2e2 b7c7 c3c4"), Some(("E30", true)));
+    }
+
+    #[test]
+    fn classifies_mirrored_openings() {
+        assert_eq!(code("b2e2 b9c7 b0c2 a9b9 a0b0 h9g7 g3g4"), Some(("C45", true)));
+        assert_eq!(code("g3g4"), Some(("E00", true)));
+        assert_eq!(code("d0e1 a6a5"), Some(("A01", true)));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::utils::*;
+use crate::game::{GameManager, GameResult, PlayerColor};
+
+/// 实用残局
+#[derive(Debug)]
+pub struct Endgame {
+    pub name: &'static str,
+    pub fen: &'static str,
+    /// 理论结果
+    pub result: GameResult,
+    /// 玩家执子方：胜局执优势方，和局执防守方
+    pub player: PlayerColor,
+    /// 限着回合数：胜局须在限着内取胜，和局须守满限着
+    pub move_limit: usize,
+}
+
+/// 内置残局库，均为红方先走
+pub const ENDGAMES: [Endgame; 13] = [
+    Endgame { name: "单车胜单将", fen: "4k4/9/9/9/R8/9/9/9/9/3K5 w", result: GameResult::RedWin, player: PlayerColor::Red, move_limit: 15 },
+    Endgame { name: "单车胜双士", fen: "3aka3/9/9/9/9/7R1/9/9/9/3K5 w", result: GameResult::RedWin, player: PlayerColor::Red, move_limit: 20 },
+    Endgame { name: "单车胜双象", fen: "2b1k1b2/9/9/9/9/7R1/9/9/9/3K5 w", result: GameResult::RedWin, player: PlayerColor::Red, move_limit: 20 },
+    Endgame { name: "单车胜单马", fen: "4k4/9/4n4/9/9/R8/9/9/9/3K5 w", result: GameResult::RedWin, player: PlayerColor::Red, move_limit: 30 },
+    Endgame { name: "单马胜单士", fen: "4k4/4a4/9/9/9/2N6/9/9/9/3K5 w", result: GameResult::RedWin, player: PlayerColor::Red, move_limit: 30 },
+    Endgame { name: "高兵胜单士", fen: "3k5/4a4/9/5P3/9/9/9/9/9/4K4 w", result: GameResult::RedWin, player: PlayerColor::Red, move_limit: 20 },
+    Endgame { name: "马炮胜士象全", fen: "2bakab2/9/9/9/9/2N6/9/7C1/9/3K5 w", result: GameResult::RedWin, player: PlayerColor::Red, move_limit: 50 },
+    Endgame { name: "车马冷着", fen: "2N1kab2/4a4/b8/4R4/9/9/9/9/9/3K5 w", result: GameResult::RedWin, player: PlayerColor::Red, move_limit: 10 },
+    Endgame { name: "马双士守和单车", fen: "3ak4/4a4/4n4/9/9/7R1/9/9/9/3K5 w", result: GameResult::Draw, player: PlayerColor::Black, move_limit: 40 },
+    Endgame { name: "士象全守和单车", fen: "2bakab2/9/9/9/9/7R1/9/9/9/3K5 w", result: GameResult::Draw, player: PlayerColor::Black, move_limit: 40 },
+    Endgame { name: "炮双士守和单车", fen: "3aca3/4k4/9/9/9/7R1/9/9/9/3K5 w", result: GameResult::Draw, player: PlayerColor::Black, move_limit: 40 },
+    Endgame { name: "车兵对车士象全", fen: "2bakab2/9/7r1/4P4/9/R8/9/9/9/3K5 w", result: GameResult::Draw, player: PlayerColor::Black, move_limit: 40 },
+    Endgame { name: "马炮争雄", fen: "3ak4/4a4/2n6/9/6c2/9/2C6/4N4/4A4/4K4 w", result: GameResult::Draw, player: PlayerColor::Black, move_limit: 40 },
+];
+
+impl Endgame {
+    /// 按序号（从1开始）或名称查找
+    pub fn find(name: &str) -> Result<(usize, &'static Endgame)> {
+        if let Ok(n) = name.parse::<usize>() {
+            return ENDGAMES.get(n.wrapping_sub(1)).map(|e| (n - 1, e))
+                .ok_or_else(|| anyhow!("残局序号超出范围，共{}个", ENDGAMES.len()));
+        }
+        ENDGAMES.iter().enumerate()
+            .find(|(_, e)| e.name == name)
+            .ok_or_else(|| anyhow!("未知的残局: {}，使用 'endgame' 查看列表", name))
+    }
+}
+
+/// 残局练习的结论
+#[derive(Debug, Clone, Copy)]
+pub enum EndgameOutcome {
+    /// 达到理论结果，附玩家所走回合数
+    Achieved(usize),
+    /// 未达到，附原因
+    Failed(&'static str),
+}
+
+/// 一个残局的练习记录
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EndgameRecord {
+    pub attempts: usize,
+    pub successes: usize,
+    /// 胜局最少用的回合数
+    pub best: Option<usize>,
+}
+
+/// 练习记录文件
+fn records_path() -> Result<PathBuf> {
+    Ok(data_dir("")?.join("endgames.toml"))
+}
+
+/// 读取各残局的练习记录，按残局名称索引
+pub fn load_endgame_records() -> Result<HashMap<String, EndgameRecord>> {
+    let path: PathBuf = records_path()?;
+    if !path.exists() {
+        return Ok(HashMap::new());
+    }
+    let table: toml::Table = toml::from_str(&read_to_string(&path)?)?;
+    let mut records: HashMap<String, EndgameRecord> = HashMap::new();
+    for (name, value) in table.get("records").and_then(|v| v.as_table()).into_iter().flatten() {
+        let integer = |field: &str| value.get(field).and_then(|v| v.as_integer()).map(|n| n as usize);
+        records.insert(name.clone(), EndgameRecord {
+            attempts: integer("attempts").unwrap_or(0),
+            successes: integer("successes").unwrap_or(0),
+            best: integer("best"),
+        });
+    }
+    Ok(records)
+}
+
+fn save_endgame_records(records: &HashMap<String, EndgameRecord>) -> Result<()> {
+    let mut entries: toml::Table = toml::Table::new();
+    for (name, record) in records {
+        let mut entry: toml::Table = toml::Table::new();
+        entry.insert("attempts".to_string(), toml::Value::Integer(record.attempts as i64));
+        entry.insert("successes".to_string(), toml::Value::Integer(record.successes as i64));
+        if let Some(best) = record.best {
+            entry.insert("best".to_string(), toml::Value::Integer(best as i64));
+        }
+        entries.insert(name.clone(), toml::Value::Table(entry));
+    }
+    let mut table: toml::Table = toml::Table::new();
+    table.insert("records".to_string(), toml::Value::Table(entries));
+    write_atomic(&records_path()?, table.to_string())
+}
+
+/// 正在进行的残局练习
+pub struct EndgameAttempt {
+    pub endgame: &'static Endgame,
+    /// 练习所用对局的会话编号
+    session_id: u64,
+}
+
+impl EndgameAttempt {
+    pub fn new(endgame: &'static Endgame, game: &GameManager) -> Self {
+        Self { endgame, session_id: game.session_id }
+    }
+
+    /// game 是否为练习中的对局
+    pub fn owns(&self, game: &GameManager) -> bool {
+        game.session_id == self.session_id
+    }
+
+    /// 玩家已走的回合数
+    fn player_moves(&self, game: &GameManager) -> usize {
+        let plies: usize = game.ply_count();
+        // 残局均为红方先走
+        match self.endgame.player {
+            PlayerColor::Red => plies.div_ceil(2),
+            PlayerColor::Black => plies / 2,
+        }
+    }
+
+    /// 检查对局是否已有结论，None 表示继续
+    pub fn check(&self, game: &GameManager) -> Option<EndgameOutcome> {
+        let player: PlayerColor = self.endgame.player;
+        let moves: usize = self.player_moves(game);
+        if game.state.is_game_over() {
+            return Some(if game.state.current_player == player {
+                EndgameOutcome::Failed("被将死或困毙")
+            } else {
+                EndgameOutcome::Achieved(moves)
+            });
+        }
+        // 对方应着后再判断限着
+        if moves >= self.endgame.move_limit && game.state.current_player == player {
+            return Some(match self.endgame.result {
+                GameResult::Draw => EndgameOutcome::Achieved(moves),
+                _ => EndgameOutcome::Failed("限着内未能取胜"),
+            });
+        }
+        None
+    }
+
+    /// 记录结论并返回该残局更新后的练习记录
+    pub fn record(&self, outcome: EndgameOutcome) -> Result<EndgameRecord> {
+        let mut records: HashMap<String, EndgameRecord> = load_endgame_records()?;
+        let record: &mut EndgameRecord = records.entry(self.endgame.name.to_string()).or_default();
+        record.attempts += 1;
+        if let EndgameOutcome::Achieved(moves) = outcome {
+            record.successes += 1;
+            if self.endgame.result != GameResult::Draw {
+                record.best = Some(record.best.map_or(moves, |best| best.min(moves)));
+            }
+        }
+        let record: EndgameRecord = *record;
+        save_endgame_records(&records)?;
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{FenProcessor, GameRecord, Move, RecordMove};
+
+    static WIN: Endgame = Endgame { name: "测试胜局", fen: "", result: GameResult::RedWin, player: PlayerColor::Red, move_limit: 1 };
+    static DRAW: Endgame = Endgame { name: "测试和局", fen: "", result: GameResult::Draw, player: PlayerColor::Black, move_limit: 1 };
+
+    fn game(fen: &str, moves: &[&str]) -> GameManager {
+        let mut record: GameRecord = GameRecord::new(fen);
+        record.moves = moves.iter().map(|mv| RecordMove::new(Move::from_str(mv).unwrap())).collect();
+        GameManager::from_record(&record, "").unwrap()
+    }
+
+    #[test]
+    fn catalogue_positions_are_valid() {
+        for endgame in &ENDGAMES {
+            let state = FenProcessor::parse_fen(endgame.fen).unwrap_or_else(|e| panic!("{}: {}", endgame.name, e));
+            state.validate_setup().unwrap_or_else(|e| panic!("{}: {}", endgame.name, e));
+            assert!(!state.is_game_over(), "{}", endgame.name);
+        }
+    }
+
+    #[test]
+    fn check_win() {
+        let fen: &str = "3k5/R8/9/9/9/9/9/9/9/4K3R w";
+        let attempt: EndgameAttempt = EndgameAttempt::new(&WIN, &game(fen, &[]));
+        assert!(attempt.check(&game(fen, &[])).is_none());
+        assert!(matches!(attempt.check(&game(fen, &["i0i9"])), Some(EndgameOutcome::Achieved(1))));
+        // 限着内没有将死
+        assert!(matches!(attempt.check(&game(fen, &["e0f0", "d9e9"])), Some(EndgameOutcome::Failed(_))));
+    }
+
+    #[test]
+    fn check_draw() {
+        let fen: &str = ENDGAMES.iter().find(|e| e.name == "士象全守和单车").unwrap().fen;
+        let attempt: EndgameAttempt = EndgameAttempt::new(&DRAW, &game(fen, &[]));
+        // 玩家执黑，红方应着后才判断限着
+        assert!(attempt.check(&game(fen, &["h4h3", "e9e8"])).is_none());
+        assert!(matches!(attempt.check(&game(fen, &["h4h3", "e9e8", "h3h4"])), Some(EndgameOutcome::Achieved(1))));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::utils::*;
+use crate::game::{Database, GameEntry, GameResult, GameState, Move, PlayerColor};
+
+/// 开局浏览器的排序方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExploreSort {
+    /// 按对局数
+    #[default]
+    Games,
+    /// 按行棋方得分率
+    Score,
+    /// 按行棋方平均等级分
+    Rating,
+}
+
+impl FromStr for ExploreSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "games" | "count" => Ok(ExploreSort::Games),
+            "score" => Ok(ExploreSort::Score),
+            "rating" | "elo" => Ok(ExploreSort::Rating),
+            _ => Err(anyhow!("无效的排序方式: {}，使用 games、score 或 rating", s)),
+        }
+    }
+}
+
+/// 某一局面下的一个后续着法及其统计
+#[derive(Debug, Clone)]
+pub struct Continuation {
+    pub mv: Move,
+    pub games: usize,
+    pub red_wins: usize,
+    pub draws: usize,
+    pub black_wins: usize,
+    /// 走这步棋一方的等级分总和和有等级分的对局数
+    rating_sum: u64,
+    rated: usize,
+}
+
+impl Continuation {
+    fn new(mv: Move) -> Self {
+        Self { mv, games: 0, red_wins: 0, draws: 0, black_wins: 0, rating_sum: 0, rated: 0 }
+    }
+
+    /// 指定一方的得分率（百分比），没有已知结果的对局时为 None
+    pub fn score(&self, color: PlayerColor) -> Option<f64> {
+        let decided: usize = self.red_wins + self.draws + self.black_wins;
+        if decided == 0 {
+            return None;
+        }
+        let wins: usize = match color {
+            PlayerColor::Red => self.red_wins,
+            PlayerColor::Black => self.black_wins,
+        };
+        Some((wins as f64 + self.draws as f64 / 2.0) * 100.0 / decided as f64)
+    }
+
+    /// 走这步棋一方的平均等级分
+    pub fn average_rating(&self) -> Option<u32> {
+        (self.rated > 0).then(|| (self.rating_sum / self.rated as u64) as u32)
+    }
+}
+
+/// 汇总棋谱库中到达该局面的对局的后续着法，不论着法次序；按指定方式排序
+pub fn explore(database: &Database, state: &GameState, sort: ExploreSort) -> Vec<Continuation> {
+    let side: PlayerColor = state.current_player;
+    let mut continuations: Vec<Continuation> = Vec::new();
+    for hit in database.find_position(state.key()) {
+        let entry: &GameEntry = database.entry(hit.id);
+        let Some(&mv) = entry.moves.get(hit.ply) else { continue };
+        let index: usize = match continuations.iter().position(|c| c.mv == mv) {
+            Some(index) => index,
+            None => {
+                continuations.push(Continuation::new(mv));
+                continuations.len() - 1
+            }
+        };
+        let continuation: &mut Continuation = &mut continuations[index];
+        continuation.games += 1;
+        match entry.result {
+            GameResult::RedWin => continuation.red_wins += 1,
+            GameResult::BlackWin => continuation.black_wins += 1,
+            GameResult::Draw => continuation.draws += 1,
+            GameResult::Unknown => {}
+        }
+        let rating: Option<u32> = match side {
+            PlayerColor::Red => entry.red_elo,
+            PlayerColor::Black => entry.black_elo,
+        };
+        if let Some(rating) = rating {
+            continuation.rating_sum += rating as u64;
+            continuation.rated += 1;
+        }
+    }
+
+    // 同值时按对局数排序，没有数据的排在最后
+    match sort {
+        ExploreSort::Games => continuations.sort_by_key(|c| std::cmp::Reverse(c.games)),
+        ExploreSort::Score => continuations.sort_by(|a, b| {
+            b.score(side).unwrap_or(-1.0).total_cmp(&a.score(side).unwrap_or(-1.0)).then(b.games.cmp(&a.games))
+        }),
+        ExploreSort::Rating => continuations.sort_by_key(|c| std::cmp::Reverse((c.average_rating(), c.games))),
+    }
+    continuations
+}
+
@@ -1,5 +1,5 @@
 use crate::utils::*;
-use crate::game::{GameState, PlayerColor, Piece, PieceKind};
+use crate::game::{Board, GameState, PlayerColor, Piece, PieceKind, Position, disguise_kind};
 
 /// 处理FEN字符串的解析和生成
 pub struct FenProcessor;
@@ -14,7 +14,7 @@ impl FenProcessor {
         }
         
         let board_str: &str = parts[0];
-        let mut board: [[Option<Piece>; 9]; 10] = [[None; 9]; 10];
+        let mut board: Board = Board::empty();
         
         // 按行分割并反转顺序
         let mut rows: Vec<&str> = board_str.split('/').collect();
@@ -29,11 +29,27 @@ impl FenProcessor {
                 // 数字表示空格子数量
                 if let Some(digit) = c.to_digit(10) {
                     x += digit as usize;
-                } 
+                    if x > 9 {
+                        return Err(anyhow!("一行超过9个格子"));
+                    }
+                    continue;
+                }
+                if x > 8 {
+                    return Err(anyhow!("一行超过9个格子"));
+                }
+                // 揭棋暗子，按所在初始位置确定走法
+                if c == 'X' || c == 'x' {
+                    let color: PlayerColor = if c == 'X' { PlayerColor::Red } else { PlayerColor::Black };
+                    let pos: Position = Position { row: y, col: x };
+                    let kind: PieceKind = disguise_kind(color, pos)
+                        .ok_or_else(|| anyhow!("暗子只能位于初始位置: {}", pos))?;
+                    board.put(pos, Piece { color, kind, hidden: true })?;
+                    x += 1;
+                }
                 // 否则是棋子字符
                 else {
                     let piece: Piece = Self::char_to_piece(c)?;
-                    board[y][x] = Some(piece);
+                    board.put(Position { row: y, col: x }, piece)?;
                     x += 1;
                 }
             }
@@ -50,16 +66,11 @@ impl FenProcessor {
             _ => return Err(anyhow!("当前玩家必须是 'w' 或 'b'")),
         };
         
-        Ok(GameState {
-            board,
-            current_player,
-            history: Vec::new(),
-            flipped: false,
-        })
+        Ok(GameState::from_board(board, current_player))
     }
     
     /// 将字符转换为棋子
-    fn char_to_piece(c: char) -> Result<Piece> {
+    pub fn char_to_piece(c: char) -> Result<Piece> {
         let (color, kind) = match c {
             'K' => (PlayerColor::Red, PieceKind::General),
             'A' => (PlayerColor::Red, PieceKind::Advisor),
@@ -77,7 +88,7 @@ impl FenProcessor {
             'p' => (PlayerColor::Black, PieceKind::Pawn),
             _ => return Err(anyhow!("无效的棋子字符: {}", c)),
         };
-        Ok(Piece { color, kind })
+        Ok(Piece { color, kind, hidden: false })
     }
     
     /// 从游戏状态生成FEN字符串
@@ -87,14 +98,14 @@ impl FenProcessor {
         // 反转行顺序：从黑方顶部（第9行）到红方底部（第0行）
         for y in (0..10).rev() {
             let mut empty: usize = 0;
-            for piece in &state.board[y] {
-                if let Some(p) = piece {
+            for x in 0..9 {
+                if let Some(p) = state.board.at(y, x) {
                     // 如果有空位，先输出空位数字
                     if empty > 0 {
                         fen.push_str(&empty.to_string());
                         empty = 0;
                     }
-                    fen.push(Self::piece_to_char(*p));
+                    fen.push(Self::piece_to_char(p));
                 } else {
                     empty += 1;
                 }
@@ -122,7 +133,13 @@ impl FenProcessor {
     }
     
     /// 将棋子转换为字符
-    fn piece_to_char(piece: Piece) -> char {
+    pub fn piece_to_char(piece: Piece) -> char {
+        if piece.hidden {
+            return match piece.color {
+                PlayerColor::Red => 'X',
+                PlayerColor::Black => 'x',
+            };
+        }
         match (piece.color, piece.kind) {
             (PlayerColor::Red, PieceKind::General) => 'K',
             (PlayerColor::Red, PieceKind::Advisor) => 'A',
@@ -141,3 +158,28 @@ impl FenProcessor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{JIEQI_START_FEN, START_FEN};
+
+    #[test]
+    fn overlong_rows_are_rejected() {
+        for fen in [
+            "9/9/9/9/9/9/9999999999X/9/9/9 w",
+            "9/9/9/9/9/9/9R/9/9/9 w",
+            "9/9/9/9/9/9/46/9/9/9 w",
+            "4k4/9/9/9/9/9/9/9/9/3K1R3R w",
+        ] {
+            assert!(FenProcessor::parse_fen(fen).is_err(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for fen in [START_FEN, JIEQI_START_FEN, "4k4/9/9/9/9/9/9/9/9/3K5 w"] {
+            assert_eq!(FenProcessor::parse_fen(fen).unwrap().to_fen(), fen);
+        }
+    }
+}
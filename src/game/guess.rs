@@ -0,0 +1,165 @@
+use crate::utils::*;
+use crate::engine::{EngineGoResult, EngineProtocol, MATE_SCORE};
+use crate::game::{GameManager, GameRecord, GameState, Move, PlayerColor, RecordMove, Variant};
+
+/// 引擎评估每个着法的思考时间（毫秒）
+const EVAL_TIME: usize = 1000;
+/// 猜中一步的满分
+pub const FULL_POINTS: u32 = 5;
+
+/// 一步猜测的结果
+#[derive(Debug, Clone)]
+pub struct GuessResult {
+    /// 猜测前局面的半回合数
+    pub ply: usize,
+    pub guess: Move,
+    pub actual: Move,
+    /// 猜测着法比实战着法差的分数，猜中或不差于实战时为 0
+    pub loss: isize,
+    /// 两个着法的中文记谱
+    pub guess_name: String,
+    pub actual_name: String,
+}
+
+impl GuessResult {
+    pub fn is_exact(&self) -> bool {
+        self.guess == self.actual
+    }
+
+    /// 按分差计分：猜中或不差于实战得满分，分差越大得分越少
+    pub fn points(&self) -> u32 {
+        if self.is_exact() || self.loss == 0 {
+            return FULL_POINTS;
+        }
+        match self.loss {
+            ..=20 => 4,
+            21..=50 => 3,
+            51..=100 => 2,
+            101..=200 => 1,
+            _ => 0,
+        }
+    }
+}
+
+/// 猜着训练：逐步猜测名局中一方的着法，引擎评估猜测和实战着法的分差
+pub struct GuessSession {
+    record: GameRecord,
+    pub color: PlayerColor,
+    engine: Box<dyn EngineProtocol>,
+    /// 第一步猜测和当前局面的半回合数
+    first_ply: usize,
+    ply: usize,
+    pub results: Vec<GuessResult>,
+    /// 训练所用对局的会话编号
+    session_id: u64,
+}
+
+impl GuessSession {
+    /// 走到第一个轮到猜测方的局面，返回训练和对局；对局中没有该方的着法时返回错误
+    pub fn start(record: GameRecord, color: PlayerColor, engine: Box<dyn EngineProtocol>, player_name: &str) -> Result<(Self, GameManager)> {
+        let state: GameState = record.initial_state()?;
+        if state.variant != Variant::Standard {
+            return Err(anyhow!("只支持标准象棋对局"));
+        }
+        let ply: usize = (state.current_player != color) as usize;
+        if ply >= record.moves.len() {
+            return Err(anyhow!("棋谱中没有可猜的着法"));
+        }
+        let mut opening: GameRecord = GameRecord::new(&record.start_fen);
+        opening.tags = record.tags.clone();
+        opening.moves = record.moves[..ply].iter().map(|m| RecordMove::new(m.mv)).collect();
+        let mut game: GameManager = GameManager::from_record(&opening, player_name)?;
+        game.player_color = Some(color);
+        game.state.flipped = color == PlayerColor::Black;
+        let session: Self = Self { record, color, engine, first_ply: ply, ply, results: Vec::new(), session_id: game.session_id };
+        Ok((session, game))
+    }
+
+    /// game 是否为训练中的对局
+    pub fn owns(&self, game: &GameManager) -> bool {
+        game.session_id == self.session_id
+    }
+
+    /// 棋谱中猜测方的总步数
+    pub fn total(&self) -> usize {
+        (self.first_ply..self.record.moves.len()).step_by(2).count()
+    }
+
+    /// 是否已猜完
+    pub fn is_finished(&self) -> bool {
+        self.ply >= self.record.moves.len()
+    }
+
+    /// 评估猜测，走出实战着法和对方的下一步，返回本步结果
+    pub async fn answer(&mut self, game: &mut GameManager, move_str: &str) -> Result<GuessResult> {
+        if self.is_finished() {
+            return Err(anyhow!("棋谱已猜完"));
+        }
+        if game.review.is_some() {
+            return Err(anyhow!("请先回到最新局面再猜着"));
+        }
+        let state: GameState = game.state.clone();
+        let guess: Move = state.parse_notation(move_str)?;
+        let actual: Move = self.record.moves[self.ply].mv;
+        let loss: isize = if guess == actual {
+            0
+        } else {
+            let actual_score: isize = self.evaluate(&state, actual).await?;
+            let guess_score: isize = self.evaluate(&state, guess).await?;
+            (actual_score - guess_score).max(0)
+        };
+        let result: GuessResult = GuessResult {
+            ply: self.ply,
+            guess,
+            actual,
+            loss,
+            guess_name: state.move_to_chinese(&guess.to_string())?,
+            actual_name: state.move_to_chinese(&actual.to_string())?,
+        };
+        self.results.push(result.clone());
+
+        // 走出实战着法和对方的应着
+        for _ in 0..2 {
+            let Some(record_move) = self.record.moves.get(self.ply) else { break };
+            game.player_move(&record_move.mv.to_string()).await?;
+            self.ply += 1;
+        }
+        Ok(result)
+    }
+
+    /// 引擎评估走出 mv 后的局面，返回走子方视角的分数
+    async fn evaluate(&mut self, state: &GameState, mv: Move) -> Result<isize> {
+        let mut next: GameState = state.clone();
+        next.apply_move(&mv.to_string())?;
+        // 走后对方无着可走
+        if next.is_game_over() {
+            return Ok(MATE_SCORE);
+        }
+        self.engine.set_position(&next.to_fen()).await?;
+        let result: EngineGoResult = self.engine.go(Some(EVAL_TIME)).await?;
+        // 引擎给出的是对方视角的分数
+        let score: isize = result.main_infos().rev()
+            .find_map(|info| info.centipawns())
+            .ok_or_else(|| anyhow!("引擎没有给出评估"))?;
+        Ok(-score)
+    }
+
+    /// 得分率（百分比）
+    pub fn accuracy(&self) -> Option<f64> {
+        let points: u32 = self.results.iter().map(|r| r.points()).sum();
+        (!self.results.is_empty()).then(|| points as f64 * 100.0 / (self.results.len() as u32 * FULL_POINTS) as f64)
+    }
+
+    /// 分差最大的若干步，分差为 0 的不算
+    pub fn worst_misses(&self, count: usize) -> Vec<&GuessResult> {
+        let mut misses: Vec<&GuessResult> = self.results.iter().filter(|r| r.loss > 0).collect();
+        misses.sort_by_key(|r| std::cmp::Reverse(r.loss));
+        misses.truncate(count);
+        misses
+    }
+
+    /// 结束训练，关闭引擎
+    pub async fn close(&mut self) -> Result<()> {
+        self.engine.quit().await
+    }
+}
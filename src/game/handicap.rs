@@ -0,0 +1,103 @@
+use crate::utils::*;
+use crate::game::{GameState, PlayerColor, Position};
+
+/// 让子开局预设，让子方固定为红方且红方先走
+#[derive(Debug, Clone, Copy)]
+pub struct Handicap {
+    /// 预设名称
+    pub name: &'static str,
+    /// 英文别名，便于在不方便输入中文的终端中使用
+    pub alias: &'static str,
+    /// 需要从标准开局中移除的红方棋子所在格子
+    pub removed: &'static [&'static str],
+}
+
+/// 记录让子预设名称的棋谱标签
+pub const HANDICAP_TAG: &str = "Handicap";
+
+/// 让子预设目录
+pub const HANDICAPS: [Handicap; 13] = [
+    Handicap { name: "让左马", alias: "left-horse", removed: &["b0"] },
+    Handicap { name: "让右马", alias: "right-horse", removed: &["h0"] },
+    Handicap { name: "让双马", alias: "two-horses", removed: &["b0", "h0"] },
+    Handicap { name: "让左车", alias: "left-rook", removed: &["a0"] },
+    Handicap { name: "让右车", alias: "right-rook", removed: &["i0"] },
+    Handicap { name: "让双车", alias: "two-rooks", removed: &["a0", "i0"] },
+    Handicap { name: "让左炮", alias: "left-cannon", removed: &["b2"] },
+    Handicap { name: "让右炮", alias: "right-cannon", removed: &["h2"] },
+    Handicap { name: "让车马", alias: "rook-horse", removed: &["a0", "b0"] },
+    Handicap { name: "让双马双炮", alias: "horses-cannons", removed: &["b0", "h0", "b2", "h2"] },
+    Handicap { name: "让五兵", alias: "five-pawns", removed: &["a3", "c3", "e3", "g3", "i3"] },
+    Handicap { name: "让双车双马", alias: "rooks-horses", removed: &["a0", "i0", "b0", "h0"] },
+    Handicap { name: "让九子", alias: "nine-pieces", removed: &["a0", "i0", "b0", "h0", "b2", "h2", "a3", "e3", "i3"] },
+];
+
+impl Handicap {
+    /// 按名称或别名查找让子预设
+    pub fn find(name: &str) -> Result<&'static Handicap> {
+        HANDICAPS.iter()
+            .find(|h| h.name == name || h.alias.eq_ignore_ascii_case(name))
+            .ok_or_else(|| anyhow!("未知的让子预设: {}，使用 'handicaps' 查看可用预设", name))
+    }
+
+    /// 生成让子后的初始局面，让子格子必须是标准开局中的红方棋子
+    pub fn initial_state(&self) -> Result<GameState> {
+        let mut state: GameState = GameState::new();
+        for square in self.removed {
+            let pos: Position = Position::from_str(square)
+                .with_context(|| format!("让子预设{}的格子无效", self.name))?;
+            if !state.clear_square(pos).is_some_and(|piece| piece.color == PlayerColor::Red) {
+                return Err(anyhow!("让子预设{}的{}没有红方棋子", self.name, square));
+            }
+        }
+        Ok(state)
+    }
+
+    /// 让子后初始局面的FEN
+    pub fn fen(&self) -> Result<String> {
+        Ok(self.initial_state()?.to_fen())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameManager, GameRecord, MoveFormat, parse_pgn};
+
+    #[test]
+    fn presets_build_valid_positions() {
+        for (i, handicap) in HANDICAPS.iter().enumerate() {
+            let state: GameState = handicap.initial_state().unwrap_or_else(|e| panic!("{}: {}", handicap.name, e));
+            state.validate_setup().unwrap_or_else(|e| panic!("{}: {}", handicap.name, e));
+            assert_eq!(state.board.pieces(PlayerColor::Red).len(), 16 - handicap.removed.len(), "{}", handicap.name);
+            assert_eq!(state.current_player, PlayerColor::Red);
+            assert!(HANDICAPS[..i].iter().all(|h| h.name != handicap.name && h.alias != handicap.alias), "{}", handicap.name);
+            assert_eq!(Handicap::find(handicap.alias).unwrap().name, handicap.name);
+        }
+        assert!(Handicap::find("让将").is_err());
+    }
+
+    #[test]
+    fn bad_squares_are_errors() {
+        assert!(Handicap { name: "无效", alias: "bad", removed: &["j0"] }.initial_state().is_err());
+        assert!(Handicap { name: "空格", alias: "empty", removed: &["e5"] }.initial_state().is_err());
+        assert!(Handicap { name: "黑子", alias: "black", removed: &["a9"] }.initial_state().is_err());
+    }
+
+    #[test]
+    fn tag_survives_pgn_round_trip() {
+        let handicap: &Handicap = Handicap::find("two-horses").unwrap();
+        let mut record: GameRecord = GameRecord::new(&handicap.fen().unwrap());
+        record.set_tag(HANDICAP_TAG, handicap.name);
+        let pgn: String = record.to_pgn(MoveFormat::Iccs).unwrap();
+        let parsed: GameRecord = parse_pgn(&pgn).unwrap().remove(0);
+        let game: GameManager = GameManager::from_record(&parsed, "").unwrap();
+        assert!(game.handicap.is_some_and(|h| h.name == handicap.name));
+        assert_eq!(game.to_record().tag(HANDICAP_TAG), Some(handicap.name));
+
+        // 起始局面与预设不符时不恢复
+        let mut record: GameRecord = GameRecord::new(&Handicap::find("left-rook").unwrap().fen().unwrap());
+        record.set_tag(HANDICAP_TAG, handicap.name);
+        assert!(GameManager::from_record(&record, "").unwrap().handicap.is_none());
+    }
+}
@@ -0,0 +1,60 @@
+use crate::utils::*;
+use crate::engine::{EngineGoResult, EngineProtocol, EngineThinkingInfo};
+use crate::game::{GameManager, GameState, Move};
+
+/// 引擎建议的一个候选着法
+#[derive(Debug, Clone)]
+pub struct Hint {
+    pub mv: Move,
+    /// 走子方视角的分数，引擎没有给出时为 None
+    pub score: Option<isize>,
+    /// 候选着法开始的主变
+    pub line: Vec<Move>,
+}
+
+impl GameManager {
+    /// 引擎短时间搜索正在查看的局面，返回至多 count 个候选着法（不超过合法着法数），按引擎排序，并记录一次提示
+    /// default_multipv 为配置文件中的 MultiPV，对局中没有设置过时搜索后恢复为该值
+    pub async fn hint(&mut self, count: usize, think_time: usize, default_multipv: Option<&str>) -> Result<Vec<Hint>> {
+        let state: GameState = self.view_state().clone();
+        if state.is_game_over() {
+            return Err(anyhow!("对局已结束，没有可走的着法"));
+        }
+        let count: usize = count.min(state.legal_moves().len());
+        // 搜索后恢复为对局中设置的值，其次为配置的默认值
+        let multipv: String = self.options.iter()
+            .rfind(|(name, _)| name.eq_ignore_ascii_case("MultiPV"))
+            .and_then(|(_, value)| value.clone())
+            .or_else(|| default_multipv.map(str::to_string))
+            .unwrap_or_else(|| "1".to_string());
+        let engine: &mut Box<dyn EngineProtocol> = self.engine()?;
+        engine.set_option("MultiPV", Some(&count.to_string())).await?;
+        engine.set_position(&state.to_fen()).await?;
+        let result: Result<EngineGoResult> = engine.go(Some(think_time)).await;
+        engine.set_option("MultiPV", Some(&multipv)).await?;
+        let result: EngineGoResult = result?;
+
+        // 每个主变取最后一条带着法的信息
+        let mut lines: Vec<Option<&EngineThinkingInfo>> = vec![None; count];
+        for info in &result.infos {
+            let index: usize = info.multipv.unwrap_or(1);
+            if let Some(slot) = lines.get_mut(index.wrapping_sub(1))
+                && info.pv.as_ref().is_some_and(|pv| !pv.is_empty())
+            {
+                *slot = Some(info);
+            }
+        }
+        let mut hints: Vec<Hint> = Vec::new();
+        for info in lines.into_iter().flatten() {
+            let line: Vec<Move> = info.pv.iter().flatten().map(|mv| Move::from_str(mv)).collect::<Result<_>>()?;
+            hints.push(Hint { mv: line[0], score: info.centipawns(), line });
+        }
+        // 引擎没有输出主变时只给出最佳着法
+        if hints.is_empty() {
+            let mv: Move = Move::from_str(&result.best_move)?;
+            hints.push(Hint { mv, score: None, line: vec![mv] });
+        }
+        self.metadata.add_hint()?;
+        Ok(hints)
+    }
+}
@@ -0,0 +1,280 @@
+use crate::utils::*;
+use crate::game::{Board, FenProcessor, Piece, PieceKind, PlayerColor, Position};
+
+/// 揭棋初始局面，除将帅外全部为暗子
+pub const JIEQI_START_FEN: &str = "xxxxkxxxx/9/1x5x1/x1x1x1x1x/9/9/X1X1X1X1X/1X5X1/9/XXXXKXXXX w";
+
+/// 棋类变体
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Variant {
+    /// 标准象棋
+    #[default]
+    Standard,
+    /// 揭棋
+    Jieqi,
+}
+
+impl Variant {
+    /// 中文名称
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "象棋",
+            Variant::Jieqi => "揭棋",
+        }
+    }
+
+    /// 引擎 UCI_Variant 选项值
+    pub fn uci_name(&self) -> &'static str {
+        match self {
+            Variant::Standard => "xiangqi",
+            Variant::Jieqi => "jieqi",
+        }
+    }
+}
+
+impl FromStr for Variant {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "standard" | "xiangqi" | "象棋" => Ok(Variant::Standard),
+            "jieqi" | "揭棋" => Ok(Variant::Jieqi),
+            _ => Err(anyhow!("不支持的变体: {}，使用 standard 或 jieqi", s)),
+        }
+    }
+}
+
+/// 一方全部可翻开的棋子（将帅除外）
+const FULL_SET: [(PieceKind, usize); 6] = [
+    (PieceKind::Advisor, 2),
+    (PieceKind::Elephant, 2),
+    (PieceKind::Horse, 2),
+    (PieceKind::Rook, 2),
+    (PieceKind::Cannon, 2),
+    (PieceKind::Pawn, 5),
+];
+
+/// 暗子池，记录每方尚未翻开的棋子
+#[derive(Debug, Clone, Default)]
+pub struct HiddenPool {
+    red: Vec<PieceKind>,
+    black: Vec<PieceKind>,
+}
+
+impl HiddenPool {
+    /// 根据棋盘推算暗子池：全套棋子减去已翻开在棋盘上的棋子
+    /// 被吃掉的棋子无从得知，按兵、炮、车、马、象、士的顺序剔除多出的身份，
+    /// 使每方暗子数与棋盘上的暗子数相同；同一局面总是得到同一暗子池
+    pub fn from_board(board: &Board) -> Self {
+        let mut pool: HiddenPool = HiddenPool::default();
+        for color in [PlayerColor::Red, PlayerColor::Black] {
+            let mut kinds: Vec<PieceKind> = Vec::new();
+            for (kind, count) in FULL_SET {
+                let on_board: usize = board.iter()
+                    .filter(|(_, p)| p.color == color && p.kind == kind && !p.hidden)
+                    .count();
+                kinds.extend(std::iter::repeat_n(kind, count.saturating_sub(on_board)));
+            }
+            kinds.truncate(hidden_count(board, color));
+            *pool.side_mut(color) = kinds;
+        }
+        pool
+    }
+
+    /// 由保存的文本恢复暗子池，每方一个 FEN 棋子字母串；与棋盘上的暗子数不符时返回错误
+    pub fn parse(red: &str, black: &str, board: &Board) -> Result<Self> {
+        let mut pool: HiddenPool = HiddenPool::default();
+        for (color, text) in [(PlayerColor::Red, red), (PlayerColor::Black, black)] {
+            let mut kinds: Vec<PieceKind> = Vec::new();
+            for c in text.chars() {
+                let piece: Piece = FenProcessor::char_to_piece(c)?;
+                if piece.color != color || piece.kind == PieceKind::General {
+                    return Err(anyhow!("无效的暗子: {}", c));
+                }
+                kinds.push(piece.kind);
+            }
+            if kinds.len() != hidden_count(board, color) {
+                return Err(anyhow!("暗子池与棋盘上的暗子数不符"));
+            }
+            *pool.side_mut(color) = kinds;
+        }
+        Ok(pool)
+    }
+
+    /// 指定方暗子池的 FEN 棋子字母串
+    pub fn letters(&self, color: PlayerColor) -> String {
+        self.remaining(color).iter()
+            .map(|&kind| FenProcessor::piece_to_char(Piece { color, kind, hidden: false }))
+            .collect()
+    }
+
+    /// 指定方剩余的暗子
+    pub fn remaining(&self, color: PlayerColor) -> &[PieceKind] {
+        match color {
+            PlayerColor::Red => &self.red,
+            PlayerColor::Black => &self.black,
+        }
+    }
+
+    /// 随机抽出一枚暗子的真实身份，暗子池为空时返回 None
+    pub fn draw(&mut self, color: PlayerColor) -> Option<PieceKind> {
+        let side: &mut Vec<PieceKind> = self.side_mut(color);
+        if side.is_empty() {
+            return None;
+        }
+        let idx: usize = rand::rng().random_range(0..side.len());
+        Some(side.swap_remove(idx))
+    }
+
+    /// 将抽出的身份放回暗子池（用于撤销走子）
+    pub fn put_back(&mut self, color: PlayerColor, kind: PieceKind) {
+        self.side_mut(color).push(kind);
+    }
+
+    fn side_mut(&mut self, color: PlayerColor) -> &mut Vec<PieceKind> {
+        match color {
+            PlayerColor::Red => &mut self.red,
+            PlayerColor::Black => &mut self.black,
+        }
+    }
+}
+
+/// 棋盘上指定方的暗子数
+fn hidden_count(board: &Board, color: PlayerColor) -> usize {
+    board.iter().filter(|(_, p)| p.color == color && p.hidden).count()
+}
+
+/// 暗子按其所在初始位置的棋子走法行棋，返回该位置对应的棋子种类
+pub fn disguise_kind(color: PlayerColor, pos: Position) -> Option<PieceKind> {
+    // 统一转换为红方视角
+    let row: usize = match color {
+        PlayerColor::Red => pos.row,
+        PlayerColor::Black => 9 - pos.row,
+    };
+    match (row, pos.col) {
+        (0, 0) | (0, 8) => Some(PieceKind::Rook),
+        (0, 1) | (0, 7) => Some(PieceKind::Horse),
+        (0, 2) | (0, 6) => Some(PieceKind::Elephant),
+        (0, 3) | (0, 5) => Some(PieceKind::Advisor),
+        (2, 1) | (2, 7) => Some(PieceKind::Cannon),
+        (3, col) if col.is_multiple_of(2) => Some(PieceKind::Pawn),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameState, Move, Undo};
+
+    fn state(fen: &str) -> GameState {
+        FenProcessor::parse_fen(fen).unwrap()
+    }
+
+    fn pos(s: &str) -> Position {
+        Position::from_str(s).unwrap()
+    }
+
+    /// 暗子池按种类排序后的内容，便于比较
+    fn sorted(pool: &HiddenPool, color: PlayerColor) -> String {
+        let mut letters: Vec<char> = pool.letters(color).chars().collect();
+        letters.sort();
+        letters.into_iter().collect()
+    }
+
+    #[test]
+    fn disguise_kinds() {
+        assert_eq!(disguise_kind(PlayerColor::Red, pos("a0")), Some(PieceKind::Rook));
+        assert_eq!(disguise_kind(PlayerColor::Red, pos("h2")), Some(PieceKind::Cannon));
+        assert_eq!(disguise_kind(PlayerColor::Red, pos("e3")), Some(PieceKind::Pawn));
+        assert_eq!(disguise_kind(PlayerColor::Black, pos("b9")), Some(PieceKind::Horse));
+        assert_eq!(disguise_kind(PlayerColor::Black, pos("f9")), Some(PieceKind::Advisor));
+        assert_eq!(disguise_kind(PlayerColor::Black, pos("c6")), Some(PieceKind::Pawn));
+        // 将帅位置和非初始位置没有对应的暗子
+        assert_eq!(disguise_kind(PlayerColor::Red, pos("e0")), None);
+        assert_eq!(disguise_kind(PlayerColor::Red, pos("b3")), None);
+        assert_eq!(disguise_kind(PlayerColor::Black, pos("a0")), None);
+    }
+
+    #[test]
+    fn pool_from_board_is_deterministic() {
+        // 红方翻出一车，另有一子被吃；黑方全部暗子
+        let fen: &str = "xxxxkxxxx/9/1x5x1/x1x1x1x1x/9/9/X1X1X1X1X/1X5X1/9/RXXXKXXX1 w";
+        let first: GameState = state(fen);
+        assert_eq!(first.pool.remaining(PlayerColor::Red).len(), 13);
+        assert_eq!(first.pool.remaining(PlayerColor::Black).len(), 15);
+        assert_eq!(first.pool.remaining(PlayerColor::Red).iter().filter(|&&k| k == PieceKind::Rook).count(), 1);
+        for _ in 0..10 {
+            assert_eq!(state(fen).pool.letters(PlayerColor::Red), first.pool.letters(PlayerColor::Red));
+        }
+    }
+
+    #[test]
+    fn reveal_in_make_and_unmake() {
+        let mut game: GameState = GameState::new_jieqi();
+        let fen: String = game.to_fen();
+        let red: String = sorted(&game.pool, PlayerColor::Red);
+        let black: String = sorted(&game.pool, PlayerColor::Black);
+
+        // 暗炮隔暗炮吃暗马：走动的暗子翻开，被吃的暗子从对方暗子池抽出身份
+        let undo: Undo = game.make_move(Move::from_str("b2b9").unwrap());
+        assert!(undo.is_reveal() && undo.is_capture());
+        let revealed: Piece = game.board[pos("b9")].unwrap();
+        assert!(!revealed.hidden && revealed.color == PlayerColor::Red);
+        assert_eq!(game.pool.remaining(PlayerColor::Red).len(), 14);
+        assert_eq!(game.pool.remaining(PlayerColor::Black).len(), 14);
+
+        // 撤销后身份放回暗子池，棋盘恢复暗子
+        game.unmake_move(undo);
+        assert_eq!(game.to_fen(), fen);
+        assert_eq!(sorted(&game.pool, PlayerColor::Red), red);
+        assert_eq!(sorted(&game.pool, PlayerColor::Black), black);
+    }
+
+    #[test]
+    fn put_back_restores_pool() {
+        let mut pool: HiddenPool = GameState::new_jieqi().pool;
+        let before: String = sorted(&pool, PlayerColor::Black);
+        let kind: PieceKind = pool.draw(PlayerColor::Black).unwrap();
+        assert_eq!(pool.remaining(PlayerColor::Black).len(), 14);
+        pool.put_back(PlayerColor::Black, kind);
+        assert_eq!(sorted(&pool, PlayerColor::Black), before);
+        assert!(HiddenPool::default().draw(PlayerColor::Red).is_none());
+    }
+
+    #[test]
+    fn revealed_advisor_and_elephant_move_freely() {
+        // 留一枚暗子使局面为揭棋
+        let jieqi: GameState = state("4k4/9/9/9/9/9/2B6/9/5A3/X2K5 w");
+        let standard: GameState = state("4k4/9/9/9/9/9/2B6/9/5A3/3K5 w");
+        assert_eq!(jieqi.variant, Variant::Jieqi);
+        assert_eq!(standard.variant, Variant::Standard);
+
+        // 仕出九宫，相过河
+        for (from, to) in [("f1", "g2"), ("c3", "e5")] {
+            let mv: Move = Move::new(pos(from), pos(to));
+            assert!(jieqi.is_valid_move(pos(from), pos(to)).is_ok(), "{}{}", from, to);
+            assert!(jieqi.legal_moves().contains(&mv), "{}{}", from, to);
+            assert!(standard.is_valid_move(pos(from), pos(to)).is_err(), "{}{}", from, to);
+            assert!(!standard.legal_moves().contains(&mv), "{}{}", from, to);
+        }
+        // 斜走一步和田字的走法不变
+        assert!(jieqi.is_valid_move(pos("f1"), pos("f2")).is_err());
+    }
+
+    #[test]
+    fn hidden_fen_round_trip() {
+        let mut game: GameState = GameState::new_jieqi();
+        for mv in ["b2b9", "a6a5", "h0g2"] {
+            game.play_move(Move::from_str(mv).unwrap()).unwrap();
+        }
+        let fen: String = game.to_fen();
+        assert!(fen.contains('X') && fen.contains('x'));
+        let parsed: GameState = state(&fen);
+        assert_eq!(parsed.variant, Variant::Jieqi);
+        assert_eq!(parsed.to_fen(), fen);
+        for color in [PlayerColor::Red, PlayerColor::Black] {
+            assert_eq!(parsed.pool.remaining(color).len(), hidden_count(&parsed.board, color));
+        }
+    }
+}
@@ -1,44 +1,246 @@
 use crate::{
-    engine::protocol::{EngineThinkingInfo, EngineProtocol, EngineGoResult},
+    engine::protocol::{EngineThinkingInfo, EngineProtocol, EngineGoResult, EngineType},
     game::state::{GameState, PlayerColor},
+    game::movegen::Move,
     game::fen::FenProcessor,
+    game::handicap::{Handicap, HANDICAP_TAG},
+    game::jieqi::Variant,
+    game::pgn::{GameRecord, GameResult, START_FEN},
+    game::metadata::GameMetadata,
+    game::tree::{Glyph, MoveTree, ROOT},
+    game::ecco::{classify, Classification},
+    game::book::Book,
 };
+use std::sync::Arc;
 use crate::utils::*;
 
+/// 引擎单步最长思考时间（毫秒）
+const MAX_THINK_TIME: usize = 5000;
+
 /// 游戏管理器
 pub struct GameManager {
     /// 游戏状态
     pub state: GameState,
-    /// 引擎实例
-    pub engine: Box<dyn EngineProtocol>,
+    /// 引擎实例，载入棋谱后可以没有引擎
+    pub engine: Option<Box<dyn EngineProtocol>>,
+    /// 引擎类型，恢复对局时据此重新创建引擎
+    pub engine_type: Option<EngineType>,
+    /// 引擎名称
+    pub engine_name: String,
+    /// 对局中设置过的引擎选项
+    pub options: Vec<(String, Option<String>)>,
+    /// 玩家名称，来自配置文件
+    pub player_name: String,
+    /// 玩家执子颜色，None 表示双方均由玩家走子
+    pub player_color: Option<PlayerColor>,
     /// 思考信息
     pub think_info: Option<EngineThinkingInfo>,
+    /// 对局起始局面FEN
+    pub start_fen: String,
+    /// 让子预设
+    pub handicap: Option<&'static Handicap>,
+    /// 对局信息，载入棋谱时保留原有标签
+    pub metadata: GameMetadata,
+    /// 着法树，保存主线、变着、注释和评注符号
+    pub tree: MoveTree,
+    /// 当前走子路线末端在着法树中的节点，与 state 对应
+    pub node: usize,
+    /// 复盘浏览：查看的半回合数和该处局面，None 表示最新局面
+    pub review: Option<(usize, GameState)>,
+    /// 自动保存的会话编号
+    pub session_id: u64,
+    /// 引擎走子时优先使用的开局库
+    pub book: Option<Arc<Book>>,
 }
 
 impl GameManager {
     /// 创建新游戏管理器
-    pub fn new(engine: Box<dyn EngineProtocol>) -> Self {
+    pub fn new(engine: Box<dyn EngineProtocol>, engine_type: EngineType, engine_name: &str, player_name: &str) -> Self {
         Self {
             state: GameState::new(),
-            engine,
+            engine: Some(engine),
+            engine_type: Some(engine_type),
+            engine_name: engine_name.to_string(),
+            options: Vec::new(),
+            player_name: player_name.to_string(),
+            player_color: None,
+            think_info: None,
+            start_fen: START_FEN.to_string(),
+            handicap: None,
+            metadata: GameMetadata::default(),
+            tree: MoveTree::new(),
+            node: ROOT,
+            review: None,
+            session_id: new_session_id(),
+            book: None,
+        }
+    }
+
+    /// 由棋谱创建游戏管理器，局面走到棋谱末尾，不带引擎
+    pub fn from_record(record: &GameRecord, player_name: &str) -> Result<Self> {
+        let state: GameState = record.replay()?;
+        let tree: MoveTree = MoveTree::from_record(record);
+        let node: usize = tree.main_leaf(ROOT);
+        let metadata: GameMetadata = GameMetadata::from_tags(&record.tags);
+        // 让子标签与起始局面相符时恢复让子预设
+        let start: String = FenProcessor::parse_fen(&record.start_fen)?.to_fen();
+        let handicap: Option<&'static Handicap> = metadata.get(HANDICAP_TAG)
+            .and_then(|name| Handicap::find(name).ok())
+            .filter(|handicap| handicap.fen().is_ok_and(|fen| fen == start));
+        Ok(Self {
+            state,
+            engine: None,
+            engine_type: None,
+            engine_name: String::new(),
+            options: Vec::new(),
+            player_name: player_name.to_string(),
+            player_color: None,
             think_info: None,
+            start_fen: record.start_fen.clone(),
+            handicap,
+            metadata,
+            tree,
+            node,
+            review: None,
+            session_id: new_session_id(),
+            book: None,
+        })
+    }
+
+    /// 导出当前对局为棋谱记录
+    pub fn to_record(&self) -> GameRecord {
+        let mut metadata: GameMetadata = self.metadata.clone();
+        if metadata.date.is_empty() {
+            metadata.date = today();
+        }
+        // 棋谱已有开局标签时保留原值，开局表不能确定编号时不写入
+        if let Some(classification) = self.opening()
+            && classification.exact
+            && metadata.get("ECCO").is_none()
+        {
+            metadata.extra.push(("ECCO".to_string(), classification.opening.code.to_string()));
+            metadata.extra.push(("Opening".to_string(), classification.opening.name.to_string()));
+        }
+        let mut record: GameRecord = GameRecord::new(&self.start_fen);
+        record.tags = metadata.to_tags();
+        record.comment = self.tree.node(ROOT).comment.clone();
+        record.moves = self.tree.record_moves();
+        record
+    }
+
+    /// 按执子方填写双方名称
+    fn fill_players(&mut self, player_color: PlayerColor) {
+        let player: String = if self.player_name.is_empty() { "玩家".to_string() } else { self.player_name.clone() };
+        let (red, black) = match player_color {
+            PlayerColor::Red => (player, self.engine_name.clone()),
+            PlayerColor::Black => (self.engine_name.clone(), player),
+        };
+        self.metadata.red = red;
+        self.metadata.black = black;
+    }
+
+    /// 为当前对局加载引擎，玩家执指定颜色继续对弈
+    pub async fn attach_engine(&mut self, engine: Box<dyn EngineProtocol>, engine_type: EngineType, engine_name: &str, player_color: PlayerColor) -> Result<()> {
+        self.set_engine(engine, engine_type, engine_name).await?;
+        self.play_as(player_color).await
+    }
+
+    /// 玩家执指定颜色与已加载的引擎继续对弈，轮到引擎时引擎立即走子
+    pub async fn play_as(&mut self, player_color: PlayerColor) -> Result<()> {
+        self.player_color = Some(player_color);
+        // 载入的棋谱保留原有棋手
+        if self.metadata.red.is_empty() && self.metadata.black.is_empty() {
+            self.fill_players(player_color);
+        }
+        self.state.flipped = player_color == PlayerColor::Black;
+
+        let fen: String = self.state.to_fen();
+        self.engine()?.set_position(&fen).await?;
+
+        if self.is_engine_turn() {
+            self.engine_move().await?;
         }
+        Ok(())
+    }
+
+    /// 为当前对局加载引擎但不改变执子方，用于分析
+    pub async fn set_engine(&mut self, engine: Box<dyn EngineProtocol>, engine_type: EngineType, engine_name: &str) -> Result<()> {
+        if let Some(mut old) = self.engine.replace(engine) {
+            let _ = old.quit().await;
+        }
+        // 新实例使用配置文件中的默认选项
+        self.options.clear();
+        self.engine_type = Some(engine_type);
+        self.engine_name = engine_name.to_string();
+        let variant: Variant = self.state.variant;
+        let engine: &mut Box<dyn EngineProtocol> = self.engine()?;
+        engine.set_variant(variant).await?;
+        engine.set_option("Clear Hash", None).await?;
+        Ok(())
+    }
+
+    /// 设置引擎选项并记录，恢复对局时重新应用
+    pub async fn set_option(&mut self, name: &str, value: Option<&str>) -> Result<()> {
+        self.engine()?.set_option(name, value).await?;
+        self.options.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.options.push((name.to_string(), value.map(|v| v.to_string())));
+        Ok(())
+    }
+
+    /// 当前引擎实例
+    pub fn engine(&mut self) -> Result<&mut Box<dyn EngineProtocol>> {
+        self.engine.as_mut().ok_or_else(|| anyhow!("当前对局没有加载引擎"))
+    }
+
+    /// 是否轮到引擎走子
+    pub fn is_engine_turn(&self) -> bool {
+        self.engine.is_some() && self.player_color.is_some_and(|c| c != self.state.current_player)
     }
 
     /// 开始新游戏
-    pub async fn start_new_game(&mut self, player_color: PlayerColor, fen: Option<String>) -> Result<()> {
+    pub async fn start_new_game(
+        &mut self,
+        player_color: PlayerColor,
+        fen: Option<String>,
+        handicap: Option<&'static Handicap>,
+        variant: Variant,
+    ) -> Result<()> {
         // 重置游戏状态
-        self.state = if let Some(fen_str) = fen {
-            FenProcessor::parse_fen(&fen_str)?
-        } else {
-            GameState::new()
+        self.state = match (fen, handicap, variant) {
+            (Some(_), Some(_), _) => return Err(anyhow!("不能同时指定FEN和让子预设")),
+            (_, Some(_), Variant::Jieqi) => return Err(anyhow!("揭棋不支持让子预设")),
+            (Some(fen_str), None, _) => {
+                let mut state: GameState = FenProcessor::parse_fen(&fen_str)?;
+                if variant == Variant::Jieqi {
+                    state.variant = Variant::Jieqi;
+                }
+                state
+            }
+            (None, Some(h), Variant::Standard) => h.initial_state()?,
+            (None, None, Variant::Standard) => GameState::new(),
+            (None, None, Variant::Jieqi) => GameState::new_jieqi(),
         };
+        self.start_fen = self.state.to_fen();
+        self.handicap = handicap;
+        self.player_color = Some(player_color);
+        self.metadata = GameMetadata { date: today(), ..GameMetadata::default() };
+        if let Some(handicap) = handicap {
+            self.metadata.set(HANDICAP_TAG, handicap.name)?;
+        }
+        self.fill_players(player_color);
+        self.tree = MoveTree::new();
+        self.node = ROOT;
+        self.review = None;
         
         // 重置引擎状态
-        self.engine.set_option("Clear Hash", None).await?;
+        let variant: Variant = self.state.variant;
+        let fen: String = self.state.to_fen();
+        let engine: &mut Box<dyn EngineProtocol> = self.engine()?;
+        engine.set_variant(variant).await?;
+        engine.set_option("Clear Hash", None).await?;
         
         // 设置初始位置
-        self.engine.set_position(&self.state.to_fen()).await?;
+        engine.set_position(&fen).await?;
         
         // 如果目前局面引擎先走
         if player_color.opponent() == self.state.current_player {
@@ -49,37 +251,298 @@ impl GameManager {
         Ok(())
     }
     
-    /// 玩家走子
+    /// 总半回合数
+    pub fn ply_count(&self) -> usize {
+        self.state.moves.len()
+    }
+
+    /// 当前路线从起始局面起每个局面的红方视角分数，未评估的局面为 None
+    pub fn eval_line(&self) -> Vec<Option<isize>> {
+        std::iter::once(ROOT).chain(self.tree.path(self.node))
+            .map(|id| self.tree.node(id).eval)
+            .collect()
+    }
+
+    /// 记录 node 处局面的分数，score 为 side 视角，保存时转为红方视角
+    pub(crate) fn record_eval(&mut self, node: usize, side: PlayerColor, score: isize) {
+        let score: isize = match side {
+            PlayerColor::Red => score,
+            PlayerColor::Black => -score,
+        };
+        self.tree.node_mut(node).eval = Some(score);
+    }
+
+    /// 正在查看的半回合数
+    pub fn view_ply(&self) -> usize {
+        self.review.as_ref().map_or(self.ply_count(), |(ply, _)| *ply)
+    }
+
+    /// 正在查看的局面
+    pub fn view_state(&self) -> &GameState {
+        self.review.as_ref().map_or(&self.state, |(_, state)| state)
+    }
+
+    /// 沿主变走到底时当前路线的总半回合数
+    pub fn line_end(&self) -> usize {
+        self.tree.depth(self.tree.main_leaf(self.node))
+    }
+
+    /// 正在查看的着法节点，开局前为根节点
+    pub fn view_node(&self) -> usize {
+        match &self.review {
+            Some((0, _)) => ROOT,
+            Some((ply, _)) => self.tree.path(self.node)[ply - 1],
+            None => self.node,
+        }
+    }
+
+    /// 从起始局面重放到第 ply 个半回合后的局面，带走子历史
+    pub fn position_at(&self, ply: usize) -> Result<GameState> {
+        if self.state.variant == Variant::Jieqi {
+            return Err(anyhow!("揭棋翻子具有随机性，无法重现历史局面"));
+        }
+        if ply > self.ply_count() {
+            return Err(anyhow!("超出范围，本局共{}步", self.ply_count()));
+        }
+        let mut state: GameState = FenProcessor::parse_fen(&self.start_fen)?;
+        state.flipped = self.state.flipped;
+        for mv in &self.state.moves[..ply] {
+            state.apply_move(&mv.to_string())?;
+        }
+        Ok(state)
+    }
+
+    /// 跳转到第 ply 个半回合，超出当前路线时沿主变继续，到达末尾时退出复盘
+    pub fn goto(&mut self, ply: usize) -> Result<()> {
+        while ply > self.ply_count()
+            && let Some(&child) = self.tree.node(self.node).children.first()
+        {
+            let mv: Move = self.tree.node(child).mv.unwrap();
+            self.state.apply_move(&mv.to_string())?;
+            self.node = child;
+        }
+        if ply == self.ply_count() {
+            self.review = None;
+            return Ok(());
+        }
+        let state: GameState = self.position_at(ply)?;
+        self.review = Some((ply, state));
+        Ok(())
+    }
+
+    /// 把当前路线设为从起始局面到 node，并走到 node
+    fn set_line(&mut self, node: usize) -> Result<()> {
+        if self.state.variant == Variant::Jieqi {
+            return Err(anyhow!("揭棋翻子具有随机性，无法切换变着"));
+        }
+        let mut state: GameState = FenProcessor::parse_fen(&self.start_fen)?;
+        state.flipped = self.state.flipped;
+        for id in self.tree.path(node) {
+            state.apply_move(&self.tree.node(id).mv.unwrap().to_string())?;
+        }
+        self.state = state;
+        self.node = node;
+        self.review = None;
+        self.think_info = None;
+        Ok(())
+    }
+
+    /// 切换到着法树中的节点，路线沿主变延伸到底
+    fn select(&mut self, id: usize) -> Result<()> {
+        self.set_line(self.tree.main_leaf(id))?;
+        self.goto(self.tree.depth(id))
+    }
+
+    /// 按着法序列选定当前路线并查看第 ply 步，用于恢复对局；树中没有的着法被忽略
+    pub fn restore_line(&mut self, line: &[Move], ply: usize) -> Result<()> {
+        let mut node: usize = ROOT;
+        for &mv in line {
+            match self.tree.node(node).children.iter().find(|&&c| self.tree.node(c).mv == Some(mv)) {
+                Some(&child) => node = child,
+                None => break,
+            }
+        }
+        if node != self.node {
+            self.set_line(node)?;
+        }
+        self.goto(ply.min(self.ply_count()))
+    }
+
+    /// 从正在查看的局面继续走子，其后的着法保留在着法树中
+    pub fn branch(&mut self) {
+        let node: usize = self.view_node();
+        let Some((_, state)) = self.review.take() else { return };
+        self.state = state;
+        self.node = node;
+        self.think_info = None;
+    }
+
+    /// 正在查看的着法的全部候选着法，第一个为主变
+    pub fn alternatives(&self) -> Vec<usize> {
+        self.tree.alternatives(self.view_node()).to_vec()
+    }
+
+    /// 正在查看的着法的全部候选着法的中文表示
+    pub fn alternative_names(&self) -> Result<Vec<String>> {
+        let Some(ply) = self.view_ply().checked_sub(1) else { return Ok(Vec::new()) };
+        let state: GameState = self.position_at(ply)?;
+        self.alternatives().iter()
+            .map(|&id| state.move_to_chinese(&self.tree.node(id).mv.unwrap().to_string()))
+            .collect()
+    }
+
+    /// 切换到正在查看的着法的第 index 个候选着法（从1开始）
+    pub fn select_variation(&mut self, index: usize) -> Result<()> {
+        let alternatives: Vec<usize> = self.alternatives();
+        let id: usize = index.checked_sub(1)
+            .and_then(|i| alternatives.get(i).copied())
+            .ok_or_else(|| anyhow!("此处共有{}个候选着法", alternatives.len()))?;
+        self.select(id)
+    }
+
+    /// 把正在查看的着法所在变着提升为主线
+    pub fn promote(&mut self) -> Result<()> {
+        let node: usize = self.view_node();
+        if self.tree.is_main_line(node) {
+            return Err(anyhow!("当前着法已在主线上"));
+        }
+        self.tree.promote(node);
+        Ok(())
+    }
+
+    /// 删除正在查看的着法及其后续着法，回到上一步
+    pub fn delete_branch(&mut self) -> Result<()> {
+        let node: usize = self.view_node();
+        let parent: usize = self.tree.remove(node)?;
+        self.select(parent)
+    }
+
+    /// 设置正在查看的着法的注释，None 表示清除
+    pub fn set_comment(&mut self, comment: Option<String>) {
+        let node: usize = self.view_node();
+        self.tree.node_mut(node).comment = comment;
+    }
+
+    /// 设置正在查看的着法的评注符号，None 表示清除
+    pub fn set_glyph(&mut self, glyph: Option<Glyph>) -> Result<()> {
+        let node: usize = self.view_node();
+        if node == ROOT {
+            return Err(anyhow!("开局前没有着法可以评注"));
+        }
+        self.tree.node_mut(node).glyph = glyph;
+        Ok(())
+    }
+
+    /// 当前路线的 ECCO 开局分类，不是从标准开局局面开始时为 None
+    pub fn opening(&self) -> Option<Classification> {
+        if self.state.variant != Variant::Standard || !self.is_standard_start() {
+            return None;
+        }
+        classify(self.state.position_keys())
+    }
+
+    /// 是否从标准开局局面开始
+    pub fn is_standard_start(&self) -> bool {
+        match self.state.variant {
+            Variant::Standard => self.start_fen == GameState::new().to_fen(),
+            Variant::Jieqi => self.start_fen == GameState::new_jieqi().to_fen(),
+        }
+    }
+
+    /// 在当前路线末端走子并记入着法树，主线分出胜负时记录结果
+    fn push_move(&mut self, mv: Move) -> Result<()> {
+        self.state.apply_move(&mv.to_string())?;
+        self.node = self.tree.add(self.node, mv);
+        let result: GameResult = GameResult::from_state(&self.state);
+        if result != GameResult::Unknown && self.tree.is_main_line(self.node) {
+            self.metadata.result = result;
+        }
+        Ok(())
+    }
+
+    /// 玩家走子，支持坐标、WXF和中文着法；复盘时走出的不同着法作为变着保存
     pub async fn player_move(&mut self, move_str: &str) -> Result<()> {
-        self.state.apply_move(move_str)?;
-        self.engine.set_position(&self.state.to_fen()).await?;
+        let mv: Move = self.view_state().parse_notation(move_str)?;
+        self.branch();
+        self.push_move(mv)?;
+        let fen: String = self.state.to_fen();
+        if let Some(engine) = &mut self.engine {
+            engine.set_position(&fen).await?;
+        }
         Ok(())
     }
     
     /// 引擎思考并走子
     pub async fn engine_move(&mut self) -> Result<()> {
-        // 等待引擎走子
-        const MAX_THINK_TIME: usize = 5000;
-        let result: EngineGoResult = self.engine.go(Some(MAX_THINK_TIME)).await?;
+        // 开局库中有当前局面时直接走库着
+        if let Some(mv) = self.book.as_ref().and_then(|book| book.choose(&self.state)) {
+            self.think_info = None;
+            self.push_move(mv)?;
+            let fen: String = self.state.to_fen();
+            self.engine()?.set_position(&fen).await?;
+            return Ok(());
+        }
+
+        // 等待引擎走子，分析过历史局面后需重新设置局面
+        let fen: String = self.state.to_fen();
+        let engine: &mut Box<dyn EngineProtocol> = self.engine()?;
+        engine.set_position(&fen).await?;
+        let result: EngineGoResult = engine.go(Some(MAX_THINK_TIME)).await?;
 
         // 处理引擎走子和记录思考信息
-        if !result.infos.is_empty() {
-            let mut info: EngineThinkingInfo = result.infos.into_iter().last().unwrap();
+        let side: PlayerColor = self.state.current_player;
+        let score: Option<isize> = result.main_infos().rev().find_map(|info| info.centipawns());
+        if let Some(mut info) = result.main_infos().last().cloned() {
             if let Some(pv) = &info.pv {
                 info.pv = Some(self.state.pv_to_chinese(pv)?);
             }
             self.think_info = Some(info);
         }
         
-        self.state.apply_move(&result.best_move)?;
-        self.engine.set_position(&self.state.to_fen()).await?;
+        self.push_move(Move::from_str(&result.best_move)?)?;
+        // 引擎的分数即为其最佳着法后局面的估值
+        if let Some(score) = score {
+            self.record_eval(self.node, side, score);
+        }
+        let fen: String = self.state.to_fen();
+        self.engine()?.set_position(&fen).await?;
         
         Ok(())
     }
     
+    /// 引擎分析当前局面，只记录思考信息不走子
+    pub async fn analyze(&mut self) -> Result<()> {
+        let fen: String = self.view_state().to_fen();
+        let engine: &mut Box<dyn EngineProtocol> = self.engine()?;
+        engine.set_position(&fen).await?;
+        let result: EngineGoResult = engine.go(Some(MAX_THINK_TIME)).await?;
+
+        if let Some(score) = result.main_infos().rev().find_map(|info| info.centipawns()) {
+            let side: PlayerColor = self.view_state().current_player;
+            self.record_eval(self.view_node(), side, score);
+        }
+        if let Some(mut info) = result.main_infos().last().cloned() {
+            if let Some(pv) = &info.pv {
+                info.pv = Some(self.view_state().pv_to_chinese(pv)?);
+            }
+            self.think_info = Some(info);
+        }
+
+        Ok(())
+    }
+
     /// 退出游戏
     pub async fn quit(&mut self) -> Result<()> {
-        self.engine.quit().await?;
+        if let Some(engine) = &mut self.engine {
+            engine.quit().await?;
+        }
         Ok(())
     }
 }
+
+/// 以当前毫秒时间戳作为会话编号
+fn new_session_id() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
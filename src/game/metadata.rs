@@ -0,0 +1,148 @@
+use crate::utils::*;
+use crate::game::GameResult;
+
+/// 导出时由棋谱内容生成的标签，不作为对局信息保存
+pub const GENERATED_TAGS: [&str; 3] = ["Game", "FEN", "Format"];
+/// 记录提示次数的标签
+const HINTS_TAG: &str = "Hints";
+
+/// 对局信息：棋手、赛事、地点、日期、轮次、用时和结果
+#[derive(Debug, Clone, Default)]
+pub struct GameMetadata {
+    pub event: String,
+    pub site: String,
+    pub date: String,
+    pub round: String,
+    pub red: String,
+    pub black: String,
+    /// 用时规则
+    pub time_control: String,
+    pub result: GameResult,
+    /// 其他标签，保持原有顺序
+    pub extra: Vec<(String, String)>,
+}
+
+impl GameMetadata {
+    /// 标准字段的标签名和取值，按 PGN 惯例排序
+    fn fields(&self) -> [(&'static str, &String); 7] {
+        [
+            ("Event", &self.event),
+            ("Site", &self.site),
+            ("Date", &self.date),
+            ("Round", &self.round),
+            ("Red", &self.red),
+            ("Black", &self.black),
+            ("TimeControl", &self.time_control),
+        ]
+    }
+
+    fn field_mut(&mut self, key: &str) -> Option<&mut String> {
+        match key.to_lowercase().as_str() {
+            "event" => Some(&mut self.event),
+            "site" => Some(&mut self.site),
+            "date" => Some(&mut self.date),
+            "round" => Some(&mut self.round),
+            "red" => Some(&mut self.red),
+            "black" => Some(&mut self.black),
+            "timecontrol" | "time" => Some(&mut self.time_control),
+            _ => None,
+        }
+    }
+
+    /// 由棋谱标签构建，标签名不区分大小写
+    pub fn from_tags(tags: &[(String, String)]) -> Self {
+        let mut metadata: GameMetadata = Self::default();
+        for (key, value) in tags {
+            if GENERATED_TAGS.iter().any(|t| t.eq_ignore_ascii_case(key)) {
+                continue;
+            }
+            // 无法识别的结果按未结束处理
+            if metadata.set(key, value).is_err() {
+                metadata.result = GameResult::Unknown;
+            }
+        }
+        metadata
+    }
+
+    /// 导出为棋谱标签，空字段不输出，结果总是输出
+    pub fn to_tags(&self) -> Vec<(String, String)> {
+        let mut tags: Vec<(String, String)> = self.fields().into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .map(|(key, value)| (key.to_string(), value.clone()))
+            .collect();
+        tags.push(("Result".to_string(), self.result.as_str().to_string()));
+        tags.extend(self.extra.iter().cloned());
+        tags
+    }
+
+    /// 读取标签
+    pub fn get(&self, key: &str) -> Option<&str> {
+        if key.eq_ignore_ascii_case("result") {
+            return Some(self.result.as_str());
+        }
+        let value: Option<&str> = match self.fields().into_iter().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+            Some((_, value)) => Some(value.as_str()),
+            None => self.extra.iter().find(|(k, _)| k.eq_ignore_ascii_case(key)).map(|(_, v)| v.as_str()),
+        };
+        value.filter(|v| !v.is_empty())
+    }
+
+    /// 设置标签，值为空表示清除
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let value: &str = value.trim();
+        if key.eq_ignore_ascii_case("result") {
+            self.result = if value.is_empty() { GameResult::Unknown } else { GameResult::from_str(value)? };
+            return Ok(());
+        }
+        if GENERATED_TAGS.iter().any(|t| t.eq_ignore_ascii_case(key)) {
+            return Err(anyhow!("{} 标签由棋谱内容生成，不能修改", key));
+        }
+        if let Some(field) = self.field_mut(key) {
+            *field = value.to_string();
+            return Ok(());
+        }
+        match self.extra.iter().position(|(k, _)| k.eq_ignore_ascii_case(key)) {
+            Some(i) if value.is_empty() => {
+                self.extra.remove(i);
+            }
+            Some(i) => self.extra[i].1 = value.to_string(),
+            None if value.is_empty() => {}
+            None => self.extra.push((key.to_string(), value.to_string())),
+        }
+        Ok(())
+    }
+
+    /// 对局中使用提示的次数
+    pub fn hints(&self) -> usize {
+        self.get(HINTS_TAG).and_then(|v| v.parse().ok()).unwrap_or(0)
+    }
+
+    /// 提示次数加一
+    pub fn add_hint(&mut self) -> Result<()> {
+        let hints: usize = self.hints() + 1;
+        self.set(HINTS_TAG, &hints.to_string())
+    }
+
+    /// 一行摘要，用于信息面板
+    pub fn summary(&self) -> String {
+        let or_unknown = |s: &str| if s.is_empty() { "?".to_string() } else { s.to_string() };
+        let mut parts: Vec<String> = vec![format!("红方 {} vs 黑方 {}", or_unknown(&self.red), or_unknown(&self.black))];
+        if !self.event.is_empty() {
+            let round: String = if self.round.is_empty() { String::new() } else { format!(" 第{}轮", self.round) };
+            parts.push(format!("{}{}", self.event, round));
+        }
+        for value in [&self.site, &self.date, &self.time_control] {
+            if !value.is_empty() {
+                parts.push(value.clone());
+            }
+        }
+        if self.result != GameResult::Unknown {
+            parts.push(self.result.name().to_string());
+        }
+        if self.hints() > 0 {
+            parts.push(format!("提示{}次", self.hints()));
+        }
+        parts.join(" | ")
+    }
+}
+
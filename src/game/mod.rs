@@ -1,9 +1,59 @@
 //! 游戏核心模块，包括状态管理和FEN处理
 
+pub mod board;
 pub mod fen;
 pub mod state;
 pub mod manager;
+pub mod setup;
+pub mod handicap;
+pub mod jieqi;
+pub mod movegen;
+pub mod perft;
+pub mod notation;
+pub mod pgn;
+pub mod xqf;
+pub mod dhtmlxq;
+pub mod tree;
+pub mod metadata;
+pub mod session;
+pub mod database;
+pub mod explorer;
+pub mod ecco;
+pub mod book;
+pub mod repertoire;
+pub mod trainer;
+pub mod puzzle;
+pub mod endgame;
+pub mod guess;
+pub mod analysis;
+pub mod hint;
+pub mod threat;
 
+pub use board::*;
 pub use fen::*;
 pub use state::*;
 pub use manager::*;
+pub use setup::*;
+pub use handicap::*;
+pub use jieqi::*;
+pub use movegen::*;
+pub use perft::*;
+pub use notation::*;
+pub use pgn::*;
+pub use xqf::*;
+pub use dhtmlxq::*;
+pub use tree::*;
+pub use metadata::*;
+pub use session::*;
+pub use database::*;
+pub use explorer::*;
+pub use ecco::*;
+pub use book::*;
+pub use repertoire::*;
+pub use trainer::*;
+pub use puzzle::*;
+pub use endgame::*;
+pub use guess::*;
+pub use analysis::*;
+pub use hint::*;
+pub use threat::*;
@@ -0,0 +1,345 @@
+use crate::utils::*;
+use crate::game::{Board, GameState, Piece, PieceKind, PlayerColor, Position, Square, Variant};
+
+/// 着法，起止格子使用一维下标
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Move {
+    pub from: Square,
+    pub to: Square,
+}
+
+impl Move {
+    /// 由起止坐标构造着法
+    pub fn new(from: Position, to: Position) -> Self {
+        Self { from: from.square(), to: to.square() }
+    }
+
+    /// 起始坐标
+    pub fn source(&self) -> Position {
+        Position::from_square(self.from)
+    }
+
+    /// 目标坐标
+    pub fn target(&self) -> Position {
+        Position::from_square(self.to)
+    }
+}
+
+impl std::fmt::Display for Move {
+    /// 输出坐标格式着法，例如 "h2e2"
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.source(), self.target())
+    }
+}
+
+impl FromStr for Move {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.len() != 4 || !s.is_ascii() {
+            return Err(anyhow!("走法格式错误，应为4个字符"));
+        }
+        Ok(Move::new(Position::from_str(&s[0..2])?, Position::from_str(&s[2..4])?))
+    }
+}
+
+/// 马的走法偏移：(行偏移, 列偏移)
+const HORSE_OFFSETS: [(i8, i8); 8] = [(2, 1), (2, -1), (-2, 1), (-2, -1), (1, 2), (1, -2), (-1, 2), (-1, -2)];
+/// 斜向偏移
+const DIAGONAL_OFFSETS: [(i8, i8); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+/// 直线方向
+const ORTHOGONAL_OFFSETS: [(i8, i8); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+/// 按偏移计算目标格子，越界返回 None
+#[inline]
+fn offset(sq: Square, dr: i8, dc: i8) -> Option<Square> {
+    let row: i8 = (sq / 9) as i8 + dr;
+    let col: i8 = (sq % 9) as i8 + dc;
+    ((0..10).contains(&row) && (0..9).contains(&col)).then(|| (row * 9 + col) as Square)
+}
+
+/// 是否在指定方的九宫内
+#[inline]
+fn in_palace(sq: Square, color: PlayerColor) -> bool {
+    let row: u8 = sq / 9;
+    let col: u8 = sq % 9;
+    let row_ok: bool = match color {
+        PlayerColor::Red => row <= 2,
+        PlayerColor::Black => row >= 7,
+    };
+    row_ok && (3..=5).contains(&col)
+}
+
+/// 是否在指定方的河界以内（未过河）
+#[inline]
+fn on_own_side(sq: Square, color: PlayerColor) -> bool {
+    match color {
+        PlayerColor::Red => sq / 9 <= 4,
+        PlayerColor::Black => sq / 9 >= 5,
+    }
+}
+
+/// 兵的前进方向
+#[inline]
+fn forward(color: PlayerColor) -> i8 {
+    match color {
+        PlayerColor::Red => 1,
+        PlayerColor::Black => -1,
+    }
+}
+
+impl Board {
+    /// 生成指定方符合走法规则的着法，不检查走后是否被将军
+    pub fn pseudo_legal_moves(&self, color: PlayerColor, variant: Variant, moves: &mut Vec<Move>) {
+        for &from in self.pieces(color) {
+            let piece: Piece = self.get(from).unwrap();
+            // 揭棋中翻开的士象不受九宫和河界限制
+            let relaxed: bool = variant == Variant::Jieqi && !piece.hidden;
+            match piece.kind {
+                PieceKind::General => {
+                    for (dr, dc) in ORTHOGONAL_OFFSETS {
+                        if let Some(to) = offset(from, dr, dc)
+                            && in_palace(to, color)
+                        {
+                            self.push_if_target(color, from, to, moves);
+                        }
+                    }
+                }
+                PieceKind::Advisor => {
+                    for (dr, dc) in DIAGONAL_OFFSETS {
+                        if let Some(to) = offset(from, dr, dc)
+                            && (relaxed || in_palace(to, color))
+                        {
+                            self.push_if_target(color, from, to, moves);
+                        }
+                    }
+                }
+                PieceKind::Elephant => {
+                    for (dr, dc) in DIAGONAL_OFFSETS {
+                        if let Some(eye) = offset(from, dr, dc)
+                            && self.get(eye).is_none()
+                            && let Some(to) = offset(from, dr * 2, dc * 2)
+                            && (relaxed || on_own_side(to, color))
+                        {
+                            self.push_if_target(color, from, to, moves);
+                        }
+                    }
+                }
+                PieceKind::Horse => {
+                    for (dr, dc) in HORSE_OFFSETS {
+                        if let Some(to) = offset(from, dr, dc)
+                            && let Some(leg) = offset(from, dr / 2, dc / 2)
+                            && self.get(leg).is_none()
+                        {
+                            self.push_if_target(color, from, to, moves);
+                        }
+                    }
+                }
+                PieceKind::Rook => {
+                    for (dr, dc) in ORTHOGONAL_OFFSETS {
+                        let mut cur: Square = from;
+                        while let Some(to) = offset(cur, dr, dc) {
+                            match self.get(to) {
+                                None => moves.push(Move { from, to }),
+                                Some(target) => {
+                                    if target.color != color {
+                                        moves.push(Move { from, to });
+                                    }
+                                    break;
+                                }
+                            }
+                            cur = to;
+                        }
+                    }
+                }
+                PieceKind::Cannon => {
+                    for (dr, dc) in ORTHOGONAL_OFFSETS {
+                        let mut cur: Square = from;
+                        let mut screened: bool = false;
+                        while let Some(to) = offset(cur, dr, dc) {
+                            match (self.get(to), screened) {
+                                (None, false) => moves.push(Move { from, to }),
+                                (None, true) => {}
+                                (Some(_), false) => screened = true,
+                                (Some(target), true) => {
+                                    if target.color != color {
+                                        moves.push(Move { from, to });
+                                    }
+                                    break;
+                                }
+                            }
+                            cur = to;
+                        }
+                    }
+                }
+                PieceKind::Pawn => {
+                    if let Some(to) = offset(from, forward(color), 0) {
+                        self.push_if_target(color, from, to, moves);
+                    }
+                    if !on_own_side(from, color) {
+                        for dc in [-1, 1] {
+                            if let Some(to) = offset(from, 0, dc) {
+                                self.push_if_target(color, from, to, moves);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// 生成指定方的全部合法着法
+    pub fn legal_moves(&mut self, color: PlayerColor, variant: Variant) -> Vec<Move> {
+        let mut moves: Vec<Move> = Vec::with_capacity(64);
+        self.pseudo_legal_moves(color, variant, &mut moves);
+        moves.retain(|&mv| self.is_king_safe_after(color, mv));
+        moves
+    }
+
+    /// 走子后指定方将帅是否安全
+    #[inline]
+    pub fn is_king_safe_after(&mut self, color: PlayerColor, mv: Move) -> bool {
+        let captured: Option<Piece> = self.move_piece(mv.from, mv.to);
+        let safe: bool = !self.in_check(color);
+        self.undo_move(mv.from, mv.to, captured);
+        safe
+    }
+
+    /// 指定方是否正被将军（含将帅照面）
+    pub fn in_check(&self, color: PlayerColor) -> bool {
+        match self.general_square(color) {
+            Some(sq) => self.is_attacked(sq, color.opponent()),
+            None => false,
+        }
+    }
+
+    /// 将帅是否在同一列且中间无子
+    pub fn generals_facing(&self) -> bool {
+        let Some(red) = self.general_square(PlayerColor::Red) else { return false };
+        let mut cur: Square = red;
+        while let Some(next) = offset(cur, 1, 0) {
+            if let Some(piece) = self.get(next) {
+                return piece.kind == PieceKind::General && !piece.hidden && piece.color == PlayerColor::Black;
+            }
+            cur = next;
+        }
+        false
+    }
+
+    /// 格子是否被指定方攻击；对将帅格子而言，将帅照面也视为攻击
+    pub fn is_attacked(&self, sq: Square, by: PlayerColor) -> bool {
+        let is_attacker = |target: Square, kind: PieceKind| -> bool {
+            matches!(self.get(target), Some(p) if p.color == by && p.kind == kind)
+        };
+
+        // 车、炮、将帅照面
+        for (dr, dc) in ORTHOGONAL_OFFSETS {
+            let mut cur: Square = sq;
+            let mut screened: bool = false;
+            while let Some(next) = offset(cur, dr, dc) {
+                if let Some(piece) = self.get(next) {
+                    if piece.color == by {
+                        if !screened && (piece.kind == PieceKind::Rook || (piece.kind == PieceKind::General && dc == 0)) {
+                            return true;
+                        }
+                        if screened && piece.kind == PieceKind::Cannon {
+                            return true;
+                        }
+                    }
+                    if screened {
+                        break;
+                    }
+                    screened = true;
+                }
+                cur = next;
+            }
+        }
+
+        // 马：马腿位于目标格子的斜向相邻格
+        for (dr, dc) in HORSE_OFFSETS {
+            if let Some(from) = offset(sq, dr, dc)
+                && is_attacker(from, PieceKind::Horse)
+                && let Some(leg) = offset(sq, dr.signum(), dc.signum())
+                && self.get(leg).is_none()
+            {
+                return true;
+            }
+        }
+
+        // 兵：来自后方，过河后也可来自两侧
+        if let Some(from) = offset(sq, -forward(by), 0)
+            && is_attacker(from, PieceKind::Pawn)
+        {
+            return true;
+        }
+        for dc in [-1, 1] {
+            if let Some(from) = offset(sq, 0, dc)
+                && !on_own_side(from, by)
+                && is_attacker(from, PieceKind::Pawn)
+            {
+                return true;
+            }
+        }
+
+        // 士、象：标准象棋中无法攻击到对方九宫，揭棋中翻开后可以
+        for (dr, dc) in DIAGONAL_OFFSETS {
+            if let Some(from) = offset(sq, dr, dc)
+                && is_attacker(from, PieceKind::Advisor)
+            {
+                return true;
+            }
+            if let Some(eye) = offset(sq, dr, dc)
+                && self.get(eye).is_none()
+                && let Some(from) = offset(sq, dr * 2, dc * 2)
+                && is_attacker(from, PieceKind::Elephant)
+            {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    /// 目标格子为空或对方棋子时加入着法
+    #[inline]
+    fn push_if_target(&self, color: PlayerColor, from: Square, to: Square, moves: &mut Vec<Move>) {
+        match self.get(to) {
+            Some(target) if target.color == color => {}
+            _ => moves.push(Move { from, to }),
+        }
+    }
+}
+
+impl GameState {
+    /// 生成当前行棋方的全部合法着法
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut board: Board = self.board.clone();
+        board.legal_moves(self.current_player, self.variant)
+    }
+
+    /// 完整合法性检查：符合走法且走后己方将帅安全
+    pub fn is_legal_move(&self, from: Position, to: Position) -> Result<()> {
+        let mv: Move = Move::new(from, to);
+        if self.legal_moves().contains(&mv) {
+            return Ok(());
+        }
+        // 给出具体的违规原因
+        self.is_valid_move(from, to)?;
+        Err(anyhow!("走子后己方将帅被将军或照面"))
+    }
+
+    /// 指定方是否正被将军（含将帅照面）
+    pub fn is_in_check(&self, color: PlayerColor) -> bool {
+        self.board.in_check(color)
+    }
+
+    /// 当前行棋方是否已无合法着法（被将死或困毙）
+    pub fn is_game_over(&self) -> bool {
+        self.legal_moves().is_empty()
+    }
+
+    /// 将帅是否在同一列且中间无子
+    pub fn generals_facing(&self) -> bool {
+        self.board.generals_facing()
+    }
+}
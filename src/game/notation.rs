@@ -0,0 +1,303 @@
+use crate::utils::*;
+use crate::game::{GameState, Move, Piece, PieceKind, PlayerColor, Position};
+
+/// 着法记谱格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MoveFormat {
+    /// 坐标格式，例如 "H2-E2"
+    #[default]
+    Iccs,
+    /// 世界象棋联合会格式，例如 "C2.5"
+    Wxf,
+    /// 中文纵线格式，例如 "炮二平五"
+    Chinese,
+}
+
+impl MoveFormat {
+    /// PGN Format 标签值
+    pub fn name(&self) -> &'static str {
+        match self {
+            MoveFormat::Iccs => "ICCS",
+            MoveFormat::Wxf => "WXF",
+            MoveFormat::Chinese => "Chinese",
+        }
+    }
+}
+
+impl FromStr for MoveFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "iccs" => Ok(MoveFormat::Iccs),
+            "wxf" => Ok(MoveFormat::Wxf),
+            "chinese" | "中文" => Ok(MoveFormat::Chinese),
+            _ => Err(anyhow!("不支持的记谱格式: {}，使用 iccs、wxf 或 chinese", s)),
+        }
+    }
+}
+
+/// 同一纵线上有多个同类棋子时的位置，按前后顺序编号
+#[derive(Debug, Clone, Copy)]
+enum Locator {
+    /// 纵线号，1-9，从己方右侧数起
+    File(usize),
+    /// 同线棋子中的序号（0为最前）和总数
+    Tandem { index: usize, total: usize },
+}
+
+/// 纵线记谱的组成部分，中文与WXF格式共用
+#[derive(Debug, Clone, Copy)]
+struct Notation {
+    piece: Piece,
+    locator: Locator,
+    /// 进'+'、退'-'、平'.'
+    op: char,
+    /// 平和斜走时为目标纵线号，直进直退时为步数
+    dest: usize,
+}
+
+/// 己方视角的纵线号
+fn file_number(color: PlayerColor, col: usize) -> usize {
+    match color {
+        PlayerColor::Red => 9 - col,
+        PlayerColor::Black => col + 1,
+    }
+}
+
+/// 红方用中文数字，黑方用阿拉伯数字
+fn numeral(color: PlayerColor, n: usize) -> String {
+    const ZH_NUMERALS: [&str; 9] = ["一", "二", "三", "四", "五", "六", "七", "八", "九"];
+    match color {
+        PlayerColor::Red => ZH_NUMERALS[n - 1].to_string(),
+        PlayerColor::Black => n.to_string(),
+    }
+}
+
+/// WXF 棋子字母
+fn wxf_letter(kind: PieceKind) -> char {
+    match kind {
+        PieceKind::General => 'K',
+        PieceKind::Advisor => 'A',
+        PieceKind::Elephant => 'E',
+        PieceKind::Horse => 'H',
+        PieceKind::Rook => 'R',
+        PieceKind::Cannon => 'C',
+        PieceKind::Pawn => 'P',
+    }
+}
+
+/// 将中文或WXF着法归一化为可比较的形式：
+/// 棋子统一为WXF字母，数字统一为阿拉伯数字，进退平统一为 +-.，
+/// 前中后统一为 +.-，并移到棋子字母之后
+fn normalize(text: &str) -> String {
+    let mut chars: Vec<char> = text.chars()
+        .take_while(|&c| c != '(')
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            '帅' | '帥' | '将' | '將' | 'k' => 'K',
+            '仕' | '士' | 'a' => 'A',
+            '相' | '象' | 'e' | 'B' | 'b' => 'E',
+            '马' | '馬' | '傌' | 'h' | 'N' | 'n' => 'H',
+            '车' | '車' | '俥' | 'r' => 'R',
+            '炮' | '砲' | '包' | 'c' => 'C',
+            '兵' | '卒' | 'p' => 'P',
+            '进' | '進' | '前' => '+',
+            '退' | '后' | '後' => '-',
+            '平' | '中' | '=' => '.',
+            '一' | '１' => '1',
+            '二' | '２' => '2',
+            '三' | '３' => '3',
+            '四' | '４' => '4',
+            '五' | '５' => '5',
+            '六' | '６' => '6',
+            '七' | '７' => '7',
+            '八' | '８' => '8',
+            '九' | '９' => '9',
+            other => other,
+        })
+        .collect();
+    // "前炮平五" 与 "+C.5" 统一为 "C+.5"
+    if chars.len() >= 2 && !chars[0].is_ascii_uppercase() && chars[1].is_ascii_uppercase() {
+        chars.swap(0, 1);
+    }
+    chars.into_iter().collect()
+}
+
+impl GameState {
+    /// 计算着法的纵线记谱组成
+    fn describe(&self, mv: Move) -> Result<Notation> {
+        let from: Position = mv.source();
+        let to: Position = mv.target();
+        let piece: Piece = self.board.get(mv.from)
+            .ok_or_else(|| anyhow!("起始位置没有棋子"))?;
+        let color: PlayerColor = piece.color;
+
+        // 同一纵线上的同类棋子，按己方视角从前到后排列
+        let mut rows: Vec<usize> = (0..10)
+            .filter(|&row| matches!(self.board.at(row, from.col), Some(p) if p.color == color && p.kind == piece.kind))
+            .collect();
+        if color == PlayerColor::Red {
+            rows.reverse();
+        }
+        let locator: Locator = if rows.len() == 1 {
+            Locator::File(file_number(color, from.col))
+        } else {
+            let index: usize = rows.iter().position(|&r| r == from.row).unwrap();
+            Locator::Tandem { index, total: rows.len() }
+        };
+
+        let advancing: bool = match color {
+            PlayerColor::Red => to.row > from.row,
+            PlayerColor::Black => to.row < from.row,
+        };
+        let (op, dest) = if from.row == to.row {
+            ('.', file_number(color, to.col))
+        } else if from.col == to.col {
+            (if advancing { '+' } else { '-' }, from.row.abs_diff(to.row))
+        } else {
+            (if advancing { '+' } else { '-' }, file_number(color, to.col))
+        };
+
+        Ok(Notation { piece, locator, op, dest })
+    }
+
+    /// 将走法转换为中文表示
+    /// 例如: "h2e2" -> "炮二平五"
+    pub fn move_to_chinese(&self, move_str: &str) -> Result<String> {
+        let mv: Move = Move::from_str(move_str)?;
+        let notation: Notation = self.describe(mv)?;
+        let color: PlayerColor = notation.piece.color;
+        let name: &str = notation.piece.get_chinese_name();
+
+        let part1: String = match notation.locator {
+            Locator::File(file) => format!("{}{}", name, numeral(color, file)),
+            Locator::Tandem { index, total } => {
+                let prefix: &str = match total {
+                    2 => ["前", "后"][index],
+                    3 => ["前", "中", "后"][index],
+                    _ => ["一", "二", "三", "四", "五"][index],
+                };
+                format!("{}{}", prefix, name)
+            }
+        };
+        let op: &str = match notation.op {
+            '+' => "进",
+            '-' => "退",
+            _ => "平",
+        };
+        Ok(format!("{}{}{}", part1, op, numeral(color, notation.dest)))
+    }
+
+    /// 将走法转换为WXF表示
+    /// 例如: "h2e2" -> "C2.5"
+    pub fn move_to_wxf(&self, mv: Move) -> Result<String> {
+        let notation: Notation = self.describe(mv)?;
+        let letter: char = wxf_letter(notation.piece.kind);
+        let locator: String = match notation.locator {
+            Locator::File(file) => file.to_string(),
+            Locator::Tandem { index, total: 2 } => ["+", "-"][index].to_string(),
+            Locator::Tandem { index, total: 3 } => ["+", ".", "-"][index].to_string(),
+            Locator::Tandem { index, .. } => (index + 1).to_string(),
+        };
+        Ok(format!("{}{}{}{}", letter, locator, notation.op, notation.dest))
+    }
+
+    /// 按指定格式输出走法
+    pub fn format_move(&self, mv: Move, format: MoveFormat) -> Result<String> {
+        match format {
+            MoveFormat::Iccs => {
+                let s: String = mv.to_string().to_uppercase();
+                Ok(format!("{}-{}", &s[0..2], &s[2..4]))
+            }
+            MoveFormat::Wxf => self.move_to_wxf(mv),
+            MoveFormat::Chinese => self.move_to_chinese(&mv.to_string()),
+        }
+    }
+
+    /// 解析任意格式的着法（坐标、ICCS、WXF、中文），返回当前局面下的合法着法
+    pub fn parse_notation(&self, text: &str) -> Result<Move> {
+        let text: &str = text.trim();
+        let legal: Vec<Move> = self.legal_moves();
+
+        // 坐标格式：h2e2 或 H2-E2
+        let coord: String = text.replace('-', "").to_lowercase();
+        if let Ok(mv) = Move::from_str(&coord) {
+            return if legal.contains(&mv) {
+                Ok(mv)
+            } else {
+                Err(anyhow!("着法不合法: {}", text))
+            };
+        }
+
+        let target: String = normalize(text);
+        let matches: Vec<Move> = legal.into_iter()
+            .filter(|&mv| {
+                self.move_to_chinese(&mv.to_string()).is_ok_and(|s| normalize(&s) == target)
+                    || self.move_to_wxf(mv).is_ok_and(|s| normalize(&s) == target)
+            })
+            .collect();
+        match matches.as_slice() {
+            [mv] => Ok(*mv),
+            [] => Err(anyhow!("无法识别或不合法的着法: {}", text)),
+            _ => Err(anyhow!("着法有歧义: {}", text)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{FenProcessor, START_FEN};
+
+    fn mv(s: &str) -> Move {
+        Move::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn formats_known_moves() {
+        let mut state: GameState = FenProcessor::parse_fen(START_FEN).unwrap();
+        assert_eq!(state.format_move(mv("h2e2"), MoveFormat::Chinese).unwrap(), "炮二平五");
+        assert_eq!(state.format_move(mv("h2e2"), MoveFormat::Wxf).unwrap(), "C2.5");
+        assert_eq!(state.format_move(mv("h2e2"), MoveFormat::Iccs).unwrap(), "H2-E2");
+        state.apply_move("h2e2").unwrap();
+        assert_eq!(state.format_move(mv("b9c7"), MoveFormat::Chinese).unwrap(), "马2进3");
+        assert_eq!(state.format_move(mv("b9c7"), MoveFormat::Wxf).unwrap(), "H2+3");
+
+        let tandem: GameState = FenProcessor::parse_fen("4k4/9/9/9/9/4R4/9/9/4R4/3K5 w").unwrap();
+        assert_eq!(tandem.format_move(mv("e4e6"), MoveFormat::Chinese).unwrap(), "前车进二");
+        assert_eq!(tandem.format_move(mv("e1e3"), MoveFormat::Wxf).unwrap(), "R-+2");
+    }
+
+    #[test]
+    fn parses_every_format() {
+        let state: GameState = FenProcessor::parse_fen(START_FEN).unwrap();
+        for text in ["h2e2", "H2-E2", "炮二平五", "炮２平５", "砲二平五", "C2.5", "c2=5"] {
+            assert_eq!(state.parse_notation(text).unwrap(), mv("h2e2"), "{}", text);
+        }
+        let tandem: GameState = FenProcessor::parse_fen("4k4/9/9/9/9/4R4/9/9/4R4/3K5 w").unwrap();
+        assert_eq!(tandem.parse_notation("前车进二").unwrap(), mv("e4e6"));
+        assert_eq!(tandem.parse_notation("R+.4").unwrap(), mv("e4f4"));
+    }
+
+    #[test]
+    fn round_trip_all_legal_moves() {
+        for fen in [START_FEN, "4k4/9/9/9/9/4R4/9/9/4R4/3K5 w", "3akab2/9/4b4/p3p3p/2p3p2/9/P1P1P1P1P/4B4/9/2BAKA3 b"] {
+            let state: GameState = FenProcessor::parse_fen(fen).unwrap();
+            for legal in state.legal_moves() {
+                for format in [MoveFormat::Iccs, MoveFormat::Wxf, MoveFormat::Chinese] {
+                    let text: String = state.format_move(legal, format).unwrap();
+                    assert_eq!(state.parse_notation(&text).unwrap(), legal, "{} {}", fen, text);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_moves_are_rejected() {
+        let state: GameState = FenProcessor::parse_fen(START_FEN).unwrap();
+        for text in ["", "xyz", "炮十平五", "炮二进五", "C2.10", "e0e2", "h2", "前炮平五"] {
+            assert!(state.parse_notation(text).is_err(), "{}", text);
+        }
+    }
+}
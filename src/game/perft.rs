@@ -0,0 +1,138 @@
+use crate::utils::*;
+use crate::game::{Board, GameState, Move, Piece, PlayerColor, Variant};
+
+/// 交互命令允许的最大深度，更深的统计需要数分钟以上
+pub const MAX_PERFT_DEPTH: usize = 5;
+
+/// 统计指定深度的叶子节点数，用于验证着法生成的正确性
+pub fn perft(state: &GameState, depth: usize) -> Result<u64> {
+    check_variant(state)?;
+    let mut board: Board = state.board.clone();
+    Ok(perft_inner(&mut board, state.current_player, depth))
+}
+
+/// 按根节点着法分别统计叶子节点数
+pub fn divide(state: &GameState, depth: usize) -> Result<Vec<(Move, u64)>> {
+    check_variant(state)?;
+    if depth == 0 {
+        return Ok(Vec::new());
+    }
+    let mut board: Board = state.board.clone();
+    let color: PlayerColor = state.current_player;
+    let moves: Vec<Move> = board.legal_moves(color, Variant::Standard);
+    Ok(moves.into_iter()
+        .map(|mv| {
+            let captured: Option<Piece> = board.move_piece(mv.from, mv.to);
+            let nodes: u64 = perft_inner(&mut board, color.opponent(), depth - 1);
+            board.undo_move(mv.from, mv.to, captured);
+            (mv, nodes)
+        })
+        .collect())
+}
+
+/// 在棋盘上原地走子和撤销，避免复制局面
+fn perft_inner(board: &mut Board, color: PlayerColor, depth: usize) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves: Vec<Move> = board.legal_moves(color, Variant::Standard);
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes: u64 = 0;
+    for mv in moves {
+        let captured: Option<Piece> = board.move_piece(mv.from, mv.to);
+        nodes += perft_inner(board, color.opponent(), depth - 1);
+        board.undo_move(mv.from, mv.to, captured);
+    }
+    nodes
+}
+
+/// 揭棋翻子具有随机性，无法统计确定的节点数
+fn check_variant(state: &GameState) -> Result<()> {
+    if state.variant != Variant::Standard {
+        return Err(anyhow!("perft仅支持标准象棋局面"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::FenProcessor;
+
+    /// 公开的象棋 perft 测试局面及深度1-4的叶节点数，涵盖炮架、蹩马腿、将帅照面和闪将
+    const MIDDLEGAMES: [(&str, [u64; 4]); 7] = [
+        ("r1ba1a3/4kn3/2n1b4/pNp1p1p1p/4c4/6P2/P1P2R2P/1CcC5/9/2BAKAB2 w", [38, 1_128, 43_929, 1_339_047]),
+        ("5a3/3k5/3aR4/9/5r3/5n3/9/3A1A3/5K3/2BC2B2 w", [25, 424, 9_850, 202_884]),
+        ("CRN1k1b2/3ca4/4ba3/9/2nr5/9/9/4B4/4A4/4KA3 w", [28, 516, 14_808, 395_483]),
+        ("R1N1k1b2/9/3aba3/9/2nr5/2B6/9/4B4/4A4/4KA3 w", [21, 364, 7_626, 162_837]),
+        ("C1nNk4/9/9/9/9/9/n1pp5/B3C4/9/3A1K3 w", [28, 222, 6_241, 64_971]),
+        ("4ka3/4a4/9/9/4N4/p8/9/4C3c/7n1/2BK5 w", [23, 345, 8_124, 149_272]),
+        ("1C2ka3/9/C1Nab1n2/p3p3p/6p2/9/P3P3P/3AB4/3p2c2/c1BAK4 w", [30, 830, 22_787, 649_866]),
+    ];
+
+    fn perft_fen(fen: &str, depth: usize) -> u64 {
+        perft(&FenProcessor::parse_fen(fen).unwrap(), depth).unwrap()
+    }
+
+    #[test]
+    fn start_position() {
+        let state: GameState = GameState::new();
+        assert_eq!(perft(&state, 1).unwrap(), 44);
+        assert_eq!(perft(&state, 2).unwrap(), 1_920);
+        assert_eq!(perft(&state, 3).unwrap(), 79_666);
+    }
+
+    #[test]
+    #[ignore = "耗时较长，使用 cargo test --release -- --ignored 运行"]
+    fn start_position_deep() {
+        let state: GameState = GameState::new();
+        assert_eq!(perft(&state, 4).unwrap(), 3_290_240);
+        assert_eq!(perft(&state, 5).unwrap(), 133_312_995);
+    }
+
+    #[test]
+    fn middlegame_positions() {
+        for (fen, counts) in MIDDLEGAMES {
+            for (depth, &count) in counts.iter().enumerate().take(3) {
+                assert_eq!(perft_fen(fen, depth + 1), count, "{} 深度{}", fen, depth + 1);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "耗时较长，使用 cargo test --release -- --ignored 运行"]
+    fn middlegame_positions_deep() {
+        for (fen, counts) in MIDDLEGAMES {
+            assert_eq!(perft_fen(fen, 4), counts[3], "{} 深度4", fen);
+        }
+    }
+
+    #[test]
+    fn flying_general_is_illegal() {
+        // 帅走到e0会与将照面，只剩d1一步
+        assert_eq!(perft_fen("4k4/9/9/9/9/9/9/9/9/3K5 w", 1), 1);
+    }
+
+    #[test]
+    fn check_evasion() {
+        // 车将军：帅只能吃车或走到f0，d0与将照面
+        assert_eq!(perft_fen("3k5/9/9/9/9/9/9/9/4r4/4K4 w", 1), 2);
+    }
+
+    #[test]
+    fn checkmate_has_no_moves() {
+        // 双车错杀，黑方无着可走
+        let state: GameState = FenProcessor::parse_fen("R2k5/R8/9/9/9/9/9/9/9/4K4 b").unwrap();
+        assert!(state.is_in_check(state.current_player));
+        assert_eq!(perft(&state, 1).unwrap(), 0);
+    }
+
+    #[test]
+    fn divide_matches_perft() {
+        let state: GameState = GameState::new();
+        let total: u64 = divide(&state, 2).unwrap().iter().map(|(_, n)| n).sum();
+        assert_eq!(total, perft(&state, 2).unwrap());
+    }
+}
@@ -0,0 +1,668 @@
+use crate::utils::*;
+use crate::game::{FenProcessor, GameState, Glyph, Move, MoveFormat, PlayerColor};
+
+/// 标准开局局面FEN
+pub const START_FEN: &str = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w";
+
+/// PGN Game 标签值
+const GAME_TAG: &str = "Chinese Chess";
+
+/// 对局结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GameResult {
+    RedWin,
+    BlackWin,
+    Draw,
+    #[default]
+    Unknown,
+}
+
+impl GameResult {
+    /// PGN 结果记号
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            GameResult::RedWin => "1-0",
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Unknown => "*",
+        }
+    }
+
+    /// 中文描述
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameResult::RedWin => "红胜",
+            GameResult::BlackWin => "黑胜",
+            GameResult::Draw => "和棋",
+            GameResult::Unknown => "未结束",
+        }
+    }
+
+    /// 根据局面判断结果：无着可走的一方判负
+    pub fn from_state(state: &GameState) -> Self {
+        if !state.is_game_over() {
+            return GameResult::Unknown;
+        }
+        match state.current_player {
+            PlayerColor::Red => GameResult::BlackWin,
+            PlayerColor::Black => GameResult::RedWin,
+        }
+    }
+}
+
+impl FromStr for GameResult {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "1-0" => Ok(GameResult::RedWin),
+            "0-1" => Ok(GameResult::BlackWin),
+            "1/2-1/2" => Ok(GameResult::Draw),
+            "*" => Ok(GameResult::Unknown),
+            "红胜" => Ok(GameResult::RedWin),
+            "黑胜" => Ok(GameResult::BlackWin),
+            "和棋" => Ok(GameResult::Draw),
+            "未结束" => Ok(GameResult::Unknown),
+            _ => Err(anyhow!("无效的对局结果: {}", s)),
+        }
+    }
+}
+
+/// 棋谱中的一步
+#[derive(Debug, Clone)]
+pub struct RecordMove {
+    pub mv: Move,
+    /// 着法后的注释
+    pub comment: Option<String>,
+    /// 评注符号
+    pub glyph: Option<Glyph>,
+    /// 替代本步的变着，每个变着从本步位置开始
+    pub variations: Vec<Vec<RecordMove>>,
+}
+
+impl RecordMove {
+    /// 不带注释和变着的一步
+    pub fn new(mv: Move) -> Self {
+        Self { mv, comment: None, glyph: None, variations: Vec::new() }
+    }
+}
+
+/// 对局记录，PGN 导入导出的中间形式
+#[derive(Debug, Clone)]
+pub struct GameRecord {
+    /// 标签，保持原有顺序
+    pub tags: Vec<(String, String)>,
+    /// 起始局面FEN
+    pub start_fen: String,
+    /// 第一步之前的注释
+    pub comment: Option<String>,
+    /// 主线着法
+    pub moves: Vec<RecordMove>,
+}
+
+impl GameRecord {
+    /// 从指定局面开始的空记录
+    pub fn new(start_fen: &str) -> Self {
+        Self {
+            tags: Vec::new(),
+            start_fen: start_fen.to_string(),
+            comment: None,
+            moves: Vec::new(),
+        }
+    }
+
+    /// 读取标签
+    pub fn tag(&self, key: &str) -> Option<&str> {
+        self.tags.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// 设置标签，已有则覆盖
+    pub fn set_tag(&mut self, key: &str, value: &str) {
+        match self.tags.iter_mut().find(|(k, _)| k.eq_ignore_ascii_case(key)) {
+            Some((_, v)) => *v = value.to_string(),
+            None => self.tags.push((key.to_string(), value.to_string())),
+        }
+    }
+
+    /// 对局结果
+    pub fn result(&self) -> GameResult {
+        self.tag("Result").and_then(|r| GameResult::from_str(r).ok()).unwrap_or_default()
+    }
+
+    /// 起始局面
+    pub fn initial_state(&self) -> Result<GameState> {
+        FenProcessor::parse_fen(&self.start_fen)
+    }
+
+    /// 从起始局面走完全部着法
+    pub fn replay(&self) -> Result<GameState> {
+        let mut state: GameState = self.initial_state()?;
+        for (i, record_move) in self.moves.iter().enumerate() {
+            state.apply_move(&record_move.mv.to_string())
+                .with_context(|| format!("第{}步着法无效", i + 1))?;
+        }
+        Ok(state)
+    }
+
+    /// 检查全部着法是否合法，不生成走子历史
+    pub fn validate(&self) -> Result<()> {
+        let mut state: GameState = self.initial_state()?;
+        for (i, record_move) in self.moves.iter().enumerate() {
+            state.play_move(record_move.mv)
+                .with_context(|| format!("第{}步着法无效", i + 1))?;
+        }
+        Ok(())
+    }
+
+    /// 导出为 PGN 文本
+    pub fn to_pgn(&self, format: MoveFormat) -> Result<String> {
+        let mut record: GameRecord = self.clone();
+        record.set_tag("Game", GAME_TAG);
+        if record.tag("Result").is_none() {
+            record.set_tag("Result", GameResult::Unknown.as_str());
+        }
+        if self.start_fen != START_FEN {
+            record.set_tag("FEN", &format!("{} - - 0 1", self.start_fen));
+        }
+        record.set_tag("Format", format.name());
+
+        // Game 标签固定在首位
+        let mut out: String = String::new();
+        record.tags.sort_by_key(|(k, _)| k != "Game");
+        for (key, value) in &record.tags {
+            out.push_str(&format!("[{} \"{}\"]\n", key, escape(value)));
+        }
+        out.push('\n');
+        if let Some(comment) = &self.comment {
+            out.push_str(&format!("{{{}}}\n", comment_text(comment)));
+        }
+
+        // 主线每回合一行，变着以括号内联
+        let mut state: GameState = self.initial_state()?;
+        let offset: usize = usize::from(state.current_player == PlayerColor::Black);
+        write_moves(&mut state, &self.moves, offset, format, true, false, &mut out)?;
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+        out.push_str(record.result().as_str());
+        out.push('\n');
+        Ok(out)
+    }
+
+    /// 用于显示的着法文本：主线每回合一行，变着以括号内联，评注符号直接跟在着法后
+    pub fn movetext(&self, format: MoveFormat) -> Result<String> {
+        let mut out: String = String::new();
+        if let Some(comment) = &self.comment {
+            out.push_str(&format!("{{{}}}\n", comment_text(comment)));
+        }
+        let mut state: GameState = self.initial_state()?;
+        let offset: usize = usize::from(state.current_player == PlayerColor::Black);
+        write_moves(&mut state, &self.moves, offset, format, true, true, &mut out)?;
+        Ok(out)
+    }
+}
+
+/// 写出一串着法及其变着；ply 为第一步的半回合序号（0为红方第一步）
+/// symbols 表示评注符号写为 ! ? 等符号，否则写为 PGN 数字注释
+fn write_moves(
+    state: &mut GameState,
+    moves: &[RecordMove],
+    ply: usize,
+    format: MoveFormat,
+    main_line: bool,
+    symbols: bool,
+    out: &mut String,
+) -> Result<()> {
+    let mut need_number: bool = true;
+    for (i, record_move) in moves.iter().enumerate() {
+        let ply: usize = ply + i;
+        if ply.is_multiple_of(2) {
+            if main_line && i > 0 {
+                out.push('\n');
+            } else if !out.is_empty() && !out.ends_with(['\n', '(']) {
+                out.push(' ');
+            }
+            out.push_str(&format!("{}.", ply / 2 + 1));
+        } else if need_number {
+            if !out.is_empty() && !out.ends_with(['\n', '(']) {
+                out.push(' ');
+            }
+            out.push_str(&format!("{}...", ply / 2 + 1));
+        }
+        out.push_str(&format!(" {}", state.format_move(record_move.mv, format)?));
+        match record_move.glyph {
+            Some(glyph) if symbols => out.push_str(glyph.symbol()),
+            Some(glyph) => out.push_str(&format!(" ${}", glyph.nag())),
+            None => {}
+        }
+        if let Some(comment) = &record_move.comment {
+            out.push_str(&format!(" {{{}}}", comment_text(comment)));
+        }
+        need_number = false;
+
+        for variation in &record_move.variations {
+            let mut branch: GameState = state.snapshot();
+            out.push_str(" (");
+            write_moves(&mut branch, variation, ply, format, false, symbols, out)?;
+            out.push(')');
+            need_number = true;
+        }
+
+        state.play_move(record_move.mv)
+            .with_context(|| format!("第{}步着法无效", ply + 1))?;
+    }
+    Ok(())
+}
+
+/// PGN 词法单元
+#[derive(Debug)]
+enum Token {
+    Tag(String, String),
+    Comment(String),
+    Move(String),
+    Glyph(Glyph),
+    Result(GameResult),
+    VariationStart,
+    VariationEnd,
+}
+
+/// 转义标签值中的引号和反斜杠
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// 注释中不能出现右花括号，导出时去掉
+fn comment_text(comment: &str) -> String {
+    comment.replace('}', "")
+}
+
+/// 读取标签 [Key "value"]，左方括号已读取；值按带转义的字符串读取，其中可以有方括号
+fn read_tag(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Result<Token> {
+    let skip_spaces = |chars: &mut std::iter::Peekable<std::str::Chars<'_>>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+    skip_spaces(chars);
+    let key: String = std::iter::from_fn(|| chars.next_if(|&c| !c.is_whitespace() && c != ']' && c != '"')).collect();
+    skip_spaces(chars);
+    let value: String = if chars.next_if_eq(&'"').is_some() {
+        let mut value: String = String::new();
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') => value.extend(chars.next()),
+                Some(c) => value.push(c),
+                None => return Err(anyhow!("标签{}的值缺少右引号", key)),
+            }
+        }
+        skip_spaces(chars);
+        value
+    } else {
+        let value: String = std::iter::from_fn(|| chars.next_if(|&c| c != ']')).collect();
+        value.trim().to_string()
+    };
+    if key.is_empty() || chars.next() != Some(']') {
+        return Err(anyhow!("标签格式错误: [{} \"{}\"", key, value));
+    }
+    Ok(Token::Tag(key, value))
+}
+
+/// 切分 PGN 文本
+fn tokenize(text: &str) -> Result<Vec<Token>> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut chars: std::iter::Peekable<std::str::Chars<'_>> = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '[' => {
+                chars.next();
+                tokens.push(read_tag(&mut chars)?);
+            }
+            '{' => {
+                chars.next();
+                let body: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                tokens.push(Token::Comment(body.trim().to_string()));
+            }
+            ';' => {
+                let body: String = chars.by_ref().take_while(|&c| c != '\n').collect();
+                tokens.push(Token::Comment(body[1..].trim().to_string()));
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::VariationStart);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::VariationEnd);
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            ']' | '}' => {
+                chars.next();
+                return Err(anyhow!("多余的'{}'", c));
+            }
+            _ => {
+                let mut word: String = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "[]{}();".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                if let Ok(result) = GameResult::from_str(&word) {
+                    tokens.push(Token::Result(result));
+                    continue;
+                }
+                // 数字注释 "$1"，只保留评注符号对应的编号
+                if let Some(nag) = word.strip_prefix('$') {
+                    if let Some(glyph) = nag.parse().ok().and_then(Glyph::from_nag) {
+                        tokens.push(Token::Glyph(glyph));
+                    }
+                    continue;
+                }
+                // 去掉回合编号 "1." "1..."
+                let word: &str = word.trim_start_matches(|c: char| c.is_ascii_digit())
+                    .trim_start_matches('.');
+                if word.is_empty() || word == "…" {
+                    continue;
+                }
+                // 着法后缀的评注符号 "炮二平五!?"
+                let text: &str = word.trim_end_matches(['!', '?']);
+                if !text.is_empty() {
+                    tokens.push(Token::Move(text.to_string()));
+                }
+                // 无法识别的后缀如 "!!!" 直接忽略
+                if let Ok(glyph) = Glyph::from_str(&word[text.len()..]) {
+                    tokens.push(Token::Glyph(glyph));
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// 尚未校验的一步
+struct RawMove {
+    text: String,
+    comment: Option<String>,
+    glyph: Option<Glyph>,
+    variations: Vec<Vec<RawMove>>,
+}
+
+/// 多段注释以空格连接
+fn append_comment(slot: &mut Option<String>, comment: String) {
+    match slot {
+        Some(existing) => {
+            existing.push(' ');
+            existing.push_str(&comment);
+        }
+        None => *slot = Some(comment),
+    }
+}
+
+/// 尚未校验着法的对局
+#[derive(Default)]
+struct RawGame {
+    tags: Vec<(String, String)>,
+    comment: Option<String>,
+    moves: Vec<RawMove>,
+    /// 正在读取的变着，外层在前
+    open_variations: Vec<Vec<RawMove>>,
+    /// 变着开头、尚无着法可挂的注释
+    pending_comment: Option<String>,
+    result: Option<GameResult>,
+}
+
+impl RawGame {
+    fn has_movetext(&self) -> bool {
+        !self.moves.is_empty() || self.comment.is_some() || self.result.is_some()
+    }
+
+    /// 正在读取的着法序列
+    fn current_line(&mut self) -> &mut Vec<RawMove> {
+        self.open_variations.last_mut().unwrap_or(&mut self.moves)
+    }
+
+    fn push_move(&mut self, text: String) {
+        let comment: Option<String> = self.pending_comment.take();
+        self.current_line().push(RawMove { text, comment, glyph: None, variations: Vec::new() });
+    }
+
+    fn push_comment(&mut self, comment: String) {
+        let in_variation: bool = !self.open_variations.is_empty();
+        match self.current_line().last_mut() {
+            Some(last) => append_comment(&mut last.comment, comment),
+            None if in_variation => append_comment(&mut self.pending_comment, comment),
+            None => append_comment(&mut self.comment, comment),
+        }
+    }
+
+    fn push_glyph(&mut self, glyph: Glyph) -> Result<()> {
+        let last: &mut RawMove = self.current_line().last_mut()
+            .ok_or_else(|| anyhow!("评注符号{}之前没有着法", glyph.symbol()))?;
+        last.glyph = Some(glyph);
+        Ok(())
+    }
+
+    /// 变着替代当前序列的最后一步
+    fn start_variation(&mut self) -> Result<()> {
+        if self.current_line().is_empty() {
+            return Err(anyhow!("变着之前没有着法"));
+        }
+        self.open_variations.push(Vec::new());
+        Ok(())
+    }
+
+    fn end_variation(&mut self) -> Result<()> {
+        let variation: Vec<RawMove> = self.open_variations.pop()
+            .ok_or_else(|| anyhow!("多余的右括号"))?;
+        self.pending_comment = None;
+        if !variation.is_empty() {
+            self.current_line().last_mut().unwrap().variations.push(variation);
+        }
+        Ok(())
+    }
+
+    /// 在 state 局面下校验一串着法及其变着；ply 为第一步的序号（从1开始）
+    fn build_line(state: &GameState, moves: Vec<RawMove>, ply: usize) -> Result<Vec<RecordMove>> {
+        let mut state: GameState = state.snapshot();
+        let mut line: Vec<RecordMove> = Vec::with_capacity(moves.len());
+        for (i, raw) in moves.into_iter().enumerate() {
+            let ply: usize = ply + i;
+            let mv: Move = state.parse_notation(&raw.text)
+                .with_context(|| format!("第{}步", ply))?;
+            let variations: Vec<Vec<RecordMove>> = raw.variations.into_iter()
+                .map(|variation| Self::build_line(&state, variation, ply).with_context(|| format!("第{}步的变着", ply)))
+                .collect::<Result<_>>()?;
+            state.play_move(mv)
+                .with_context(|| format!("第{}步", ply))?;
+            line.push(RecordMove { mv, comment: raw.comment, glyph: raw.glyph, variations });
+        }
+        Ok(line)
+    }
+
+    /// 校验全部着法并生成对局记录
+    fn into_record(self) -> Result<GameRecord> {
+        let mut record: GameRecord = GameRecord::new(START_FEN);
+        record.tags = self.tags;
+        record.comment = self.comment;
+        if let Some(game) = record.tag("Game")
+            && !game.eq_ignore_ascii_case(GAME_TAG)
+        {
+            return Err(anyhow!("不是中国象棋棋谱: {}", game));
+        }
+        if let Some(fen) = record.tag("FEN") {
+            record.start_fen = FenProcessor::parse_fen(fen)?.to_fen();
+        }
+        if record.tag("Result").is_none()
+            && let Some(result) = self.result
+        {
+            record.set_tag("Result", result.as_str());
+        }
+
+        if !self.open_variations.is_empty() {
+            return Err(anyhow!("变着括号未闭合"));
+        }
+        let state: GameState = record.initial_state()?;
+        record.moves = Self::build_line(&state, self.moves, 1)?;
+        Ok(record)
+    }
+}
+
+/// 解析 PGN 文本，支持一个文件包含多局
+pub fn parse_pgn(text: &str) -> Result<Vec<GameRecord>> {
+    let mut raw_games: Vec<RawGame> = Vec::new();
+    let mut current: RawGame = RawGame::default();
+
+    for token in tokenize(text)? {
+        let game: usize = raw_games.len() + 1;
+        match token {
+            Token::Tag(key, value) => {
+                if current.has_movetext() {
+                    raw_games.push(std::mem::take(&mut current));
+                }
+                current.tags.push((key, value));
+            }
+            Token::Comment(comment) => current.push_comment(comment),
+            Token::Move(text) => current.push_move(text),
+            Token::Glyph(glyph) => current.push_glyph(glyph).with_context(|| format!("第{}局", game))?,
+            Token::VariationStart => current.start_variation().with_context(|| format!("第{}局", game))?,
+            Token::VariationEnd => current.end_variation().with_context(|| format!("第{}局", game))?,
+            Token::Result(result) => {
+                current.result = Some(result);
+                raw_games.push(std::mem::take(&mut current));
+            }
+        }
+    }
+    if current.has_movetext() || !current.tags.is_empty() {
+        raw_games.push(current);
+    }
+
+    if raw_games.is_empty() {
+        return Err(anyhow!("文件中没有找到棋谱"));
+    }
+    raw_games.into_iter()
+        .enumerate()
+        .map(|(i, raw)| raw.into_record().with_context(|| format!("第{}局", i + 1)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAME: &str = "[Game \"Chinese Chess\"]
+[Event \"Cup [final]\"]
+[Red \"A \\\"B\\\" C\"]
+[Result \"1-0\"]
+
+{开局前}
+1. h2e2 {中炮} h9g7 $2
+2. h0g2 (2. b2c2 {变着} b9c7) i9h9
+1-0
+";
+
+    /// 主线着法、注释和评注符号
+    fn summary(record: &GameRecord) -> Vec<(String, Option<String>, Option<Glyph>, usize)> {
+        record.moves.iter()
+            .map(|m| (m.mv.to_string(), m.comment.clone(), m.glyph, m.variations.len()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_tags_comments_and_variations() {
+        let records: Vec<GameRecord> = parse_pgn(GAME).unwrap();
+        let [record] = records.as_slice() else { panic!("应为一局") };
+        assert_eq!(record.tag("Event"), Some("Cup [final]"));
+        assert_eq!(record.tag("Red"), Some("A \"B\" C"));
+        assert_eq!(record.result(), GameResult::RedWin);
+        assert_eq!(record.comment.as_deref(), Some("开局前"));
+        assert_eq!(record.moves.len(), 4);
+        assert_eq!(record.moves[0].comment.as_deref(), Some("中炮"));
+        assert_eq!(record.moves[1].glyph, Glyph::from_nag(2));
+        let variation: Vec<String> = record.moves[2].variations[0].iter().map(|m| m.mv.to_string()).collect();
+        assert_eq!(variation, ["b2c2", "b9c7"]);
+        assert_eq!(record.moves[2].variations[0][0].comment.as_deref(), Some("变着"));
+    }
+
+    #[test]
+    fn round_trip_in_every_format() {
+        let record: GameRecord = parse_pgn(GAME).unwrap().remove(0);
+        for format in [MoveFormat::Iccs, MoveFormat::Wxf, MoveFormat::Chinese] {
+            let text: String = record.to_pgn(format).unwrap();
+            let parsed: GameRecord = parse_pgn(&text).unwrap().remove(0);
+            assert_eq!(summary(&parsed), summary(&record), "{}", text);
+            assert_eq!(parsed.tag("Event"), Some("Cup [final]"));
+            assert_eq!(parsed.tag("Red"), Some("A \"B\" C"));
+            assert_eq!(parsed.comment, record.comment);
+        }
+    }
+
+    #[test]
+    fn round_trip_from_position() {
+        let text: &str = "[FEN \"4k4/9/9/9/9/9/9/9/4R4/3K5 b - - 0 1\"]\n\n1... e9f9 2. e1f1 *\n";
+        let record: GameRecord = parse_pgn(text).unwrap().remove(0);
+        assert_eq!(record.start_fen, "4k4/9/9/9/9/9/9/9/4R4/3K5 b");
+        let parsed: GameRecord = parse_pgn(&record.to_pgn(MoveFormat::Chinese).unwrap()).unwrap().remove(0);
+        assert_eq!(parsed.start_fen, record.start_fen);
+        assert_eq!(summary(&parsed), summary(&record));
+    }
+
+    #[test]
+    fn validate_matches_replay() {
+        let mut record: GameRecord = GameRecord::new("4k4/9/9/9/9/9/9/9/4R4/3K5 b");
+        record.moves.push(RecordMove::new(Move::from_str("e9f9").unwrap()));
+        record.validate().unwrap();
+        assert_eq!(record.replay().unwrap().history.len(), 1);
+        // 走后仍被将军
+        record.moves[0] = RecordMove::new(Move::from_str("e9e8").unwrap());
+        assert!(record.validate().is_err());
+        assert!(record.replay().is_err());
+    }
+
+    #[test]
+    fn comment_with_closing_brace_is_written_safely() {
+        let mut record: GameRecord = parse_pgn(GAME).unwrap().remove(0);
+        record.comment = Some("a } b".to_string());
+        record.moves[0].comment = Some("{x}".to_string());
+        let parsed: GameRecord = parse_pgn(&record.to_pgn(MoveFormat::Iccs).unwrap()).unwrap().remove(0);
+        assert_eq!(parsed.comment.as_deref(), Some("a  b"));
+        assert_eq!(parsed.moves[0].comment.as_deref(), Some("{x"));
+    }
+
+    #[test]
+    fn unknown_suffix_is_ignored() {
+        let record: GameRecord = parse_pgn("1. h2e2!!! h9g7!? *\n").unwrap().remove(0);
+        assert_eq!(record.moves[0].glyph, None);
+        assert_eq!(record.moves[1].glyph, Some(Glyph::from_str("!?").unwrap()));
+    }
+
+    #[test]
+    fn malformed_input_is_rejected() {
+        for text in [
+            "[Result \"*\"]\n\n1. h2e2 } h9g7 *\n",
+            "[Result \"*\"]\n\n1. h2e2 ] h9g7 *\n",
+            "1. h2e2 {see {x} here} *\n",
+            "[Event \"unterminated]\n\n1. h2e2 *\n",
+            "[Event \"x\"\n\n1. h2e2 *\n",
+            "1. h2e2 (h9g7 *\n",
+            "1. h2e2 ) *\n",
+            "1. e0e2 *\n",
+            "[Game \"Chess\"]\n\n1. e4 *\n",
+            "",
+        ] {
+            assert!(parse_pgn(text).is_err(), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn several_games_in_one_file() {
+        let text: String = format!("{}\n{}", GAME, "[Event \"2\"]\n\n1. c3c4 0-1\n");
+        let records: Vec<GameRecord> = parse_pgn(&text).unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].tag("Event"), Some("2"));
+        assert_eq!(records[1].result(), GameResult::BlackWin);
+    }
+}
@@ -0,0 +1,408 @@
+use crate::utils::*;
+use crate::engine::{EngineGoResult, EngineProtocol};
+use crate::game::{FenProcessor, GameManager, GameRecord, GameState, Move, PlayerColor, Variant};
+
+/// 引擎验证非题解着法时的思考时间（毫秒）
+const VERIFY_TIME: usize = 2000;
+
+// 题库文件每行一题，字段用分号分隔：FEN; 题解着法; 等级分; 主题
+// 题解第一步由局面中的行棋方走出，双方交替，最后一步必须是解题方的着法，可使用任意记谱格式
+// 等级分和主题可以省略，主题之间用逗号分隔；空行和 # 开头的行忽略
+
+/// 一道题目
+#[derive(Debug, Clone)]
+pub struct Puzzle {
+    /// 题目标识，由 FEN 和题解计算，题库增删题目或调整顺序后不变
+    pub id: u64,
+    pub fen: String,
+    pub solution: Vec<Move>,
+    pub rating: Option<u32>,
+    pub themes: Vec<String>,
+    /// 解题方
+    pub side: PlayerColor,
+    /// 题解走完后对方无着可走，即杀法题；杀法题接受引擎确认的其他杀法
+    pub mate: bool,
+}
+
+impl Puzzle {
+    /// 解析题库中的一行
+    fn parse(line: &str) -> Result<Self> {
+        let mut fields = line.split(';').map(str::trim);
+        let fen: &str = fields.next().filter(|s| !s.is_empty()).ok_or_else(|| anyhow!("缺少 FEN"))?;
+        let mut state: GameState = FenProcessor::parse_fen(fen)?;
+        if state.variant != Variant::Standard {
+            return Err(anyhow!("只支持标准象棋局面"));
+        }
+        let side: PlayerColor = state.current_player;
+        let mut solution: Vec<Move> = Vec::new();
+        for text in fields.next().unwrap_or_default().split_whitespace() {
+            let mv: Move = state.parse_notation(text).with_context(|| format!("题解第{}步无效", solution.len() + 1))?;
+            state.apply_move(&mv.to_string())?;
+            solution.push(mv);
+        }
+        if solution.len().is_multiple_of(2) {
+            return Err(anyhow!("题解为空或最后一步不是解题方的着法"));
+        }
+        let rating: Option<u32> = match fields.next().filter(|s| !s.is_empty()) {
+            Some(text) => Some(text.parse().map_err(|_| anyhow!("无效的等级分: {}", text))?),
+            None => None,
+        };
+        let themes: Vec<String> = fields.next().unwrap_or_default()
+            .split([',', '，'])
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+            .collect();
+        let moves: Vec<String> = solution.iter().map(|mv| mv.to_string()).collect();
+        let id: u64 = stable_hash(format!("{};{}", fen, moves.join(" ")));
+        Ok(Self { id, fen: fen.to_string(), solution, rating, themes, side, mate: state.is_game_over() })
+    }
+}
+
+/// 解析题库文本
+pub fn parse_puzzles(text: &str) -> Result<Vec<Puzzle>> {
+    let mut puzzles: Vec<Puzzle> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line: &str = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        puzzles.push(Puzzle::parse(line).with_context(|| format!("第{}行", i + 1))?);
+    }
+    if puzzles.is_empty() {
+        return Err(anyhow!("题库中没有题目"));
+    }
+    Ok(puzzles)
+}
+
+/// 解题统计，按题库分别保存
+#[derive(Debug, Clone, Default)]
+pub struct PuzzleStats {
+    /// 完成的题目次数
+    pub attempted: usize,
+    pub solved: usize,
+    /// 当前连续解出的题数
+    pub streak: usize,
+    pub best_streak: usize,
+    /// 解出过的题目标识
+    pub solved_set: hashbrown::HashSet<u64>,
+}
+
+impl PuzzleStats {
+    /// 解出率（百分比），还没有完成过题目时为 None
+    pub fn solve_rate(&self) -> Option<f64> {
+        (self.attempted > 0).then(|| self.solved as f64 * 100.0 / self.attempted as f64)
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let table: toml::Table = toml::from_str(text)?;
+        let integer = |name: &str| table.get(name).and_then(|v| v.as_integer()).unwrap_or(0) as usize;
+        Ok(Self {
+            attempted: integer("attempted"),
+            solved: integer("solved"),
+            streak: integer("streak"),
+            best_streak: integer("best_streak"),
+            solved_set: table.get("solved_puzzles").and_then(|v| v.as_array()).into_iter().flatten()
+                .filter_map(|v| v.as_str())
+                .filter_map(|id| u64::from_str_radix(id, 16).ok())
+                .collect(),
+        })
+    }
+
+    fn to_toml(&self) -> String {
+        let mut solved: Vec<u64> = self.solved_set.iter().copied().collect();
+        solved.sort_unstable();
+        let mut table: toml::Table = toml::Table::new();
+        table.insert("attempted".to_string(), toml::Value::Integer(self.attempted as i64));
+        table.insert("solved".to_string(), toml::Value::Integer(self.solved as i64));
+        table.insert("streak".to_string(), toml::Value::Integer(self.streak as i64));
+        table.insert("best_streak".to_string(), toml::Value::Integer(self.best_streak as i64));
+        table.insert(
+            "solved_puzzles".to_string(),
+            toml::Value::Array(solved.into_iter().map(|id| toml::Value::String(format!("{:016x}", id))).collect()),
+        );
+        table.to_string()
+    }
+}
+
+/// 解题中一次走子的结果
+#[derive(Debug, Clone)]
+pub enum PuzzleAnswer {
+    /// 着法正确，对方已走出应着
+    Continue(Move),
+    Solved,
+    /// 着法错误，附从当前局面起的正解
+    Failed(Vec<Move>),
+}
+
+/// 解题模式：依次出题，检查玩家着法并走出对方的应着
+pub struct PuzzleSession {
+    puzzles: Vec<Puzzle>,
+    /// 验证其他杀法的引擎，没有时只接受题解和直接取胜的着法
+    engine: Option<Box<dyn EngineProtocol>>,
+    stats_path: PathBuf,
+    pub stats: PuzzleStats,
+    /// 当前题目序号（从0开始）
+    pub index: usize,
+    /// 已走的题解步数，走出引擎确认的其他杀法后为 None
+    progress: Option<usize>,
+    /// 解题方还可以走的步数
+    budget: usize,
+    /// 当前题目已解出或失败
+    finished: bool,
+    /// 当前题目对局的会话编号
+    session_id: u64,
+}
+
+/// 解题统计目录
+fn puzzle_dir() -> Result<PathBuf> {
+    data_dir("puzzles")
+}
+
+impl PuzzleSession {
+    /// 读取题库和解题统计
+    pub fn open(path: &Path, engine: Option<Box<dyn EngineProtocol>>) -> Result<Self> {
+        let text: String = read_to_string(path).with_context(|| format!("无法读取题库 {}", path.display()))?;
+        let puzzles: Vec<Puzzle> = parse_puzzles(&text)?;
+        let stats_path: PathBuf = puzzle_dir()?.join(format!("{}.toml", data_file_name(path)));
+        let stats: PuzzleStats = if stats_path.exists() {
+            PuzzleStats::parse(&read_to_string(&stats_path)?)?
+        } else {
+            PuzzleStats::default()
+        };
+        Ok(Self {
+            puzzles,
+            engine,
+            stats_path,
+            stats,
+            index: 0,
+            progress: Some(0),
+            budget: 0,
+            finished: true,
+            session_id: 0,
+        })
+    }
+
+    /// 题目数
+    pub fn len(&self) -> usize {
+        self.puzzles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.puzzles.is_empty()
+    }
+
+    /// 题库中解出过的题数
+    pub fn solved_count(&self) -> usize {
+        self.puzzles.iter().filter(|p| self.stats.solved_set.contains(&p.id)).count()
+    }
+
+    pub fn current(&self) -> &Puzzle {
+        &self.puzzles[self.index]
+    }
+
+    /// 是否有引擎验证其他杀法
+    pub fn has_engine(&self) -> bool {
+        self.engine.is_some()
+    }
+
+    /// game 是否为未完成的题目对局
+    pub fn owns(&self, game: &GameManager) -> bool {
+        !self.finished && game.session_id == self.session_id
+    }
+
+    /// game 是否为当前题目的对局，不论是否已结束
+    pub fn presents(&self, game: &GameManager) -> bool {
+        game.session_id == self.session_id
+    }
+
+    /// 出第 index 题（从0开始）
+    pub fn present(&mut self, index: usize, player_name: &str) -> Result<GameManager> {
+        let puzzle: &Puzzle = self.puzzles.get(index)
+            .ok_or_else(|| anyhow!("题号超出范围，共{}题", self.puzzles.len()))?;
+        let mut game: GameManager = GameManager::from_record(&GameRecord::new(&puzzle.fen), player_name)?;
+        game.player_color = Some(puzzle.side);
+        game.state.flipped = puzzle.side == PlayerColor::Black;
+        self.index = index;
+        self.progress = Some(0);
+        self.budget = puzzle.solution.len().div_ceil(2);
+        self.finished = false;
+        self.session_id = game.session_id;
+        Ok(game)
+    }
+
+    /// 从 start 开始（含）找第一道没有解出过的题，全部解出过时就是 start
+    pub fn first_unsolved(&self, start: usize) -> usize {
+        (0..self.puzzles.len())
+            .map(|i| (start + i) % self.puzzles.len())
+            .find(|&i| !self.stats.solved_set.contains(&self.puzzles[i].id))
+            .unwrap_or(start % self.puzzles.len())
+    }
+
+    /// 出下一道没有解出过的题；放弃未完成的题目会中断连续解出
+    pub fn next(&mut self, player_name: &str) -> Result<GameManager> {
+        if !self.finished {
+            self.stats.streak = 0;
+            self.save()?;
+        }
+        let index: usize = self.first_unsolved(self.index + 1);
+        self.present(index, player_name)
+    }
+
+    /// 检查玩家的着法：题解着法或直接取胜的着法算对，杀法题中引擎确认仍能在剩余步数内杀棋的着法也算对
+    /// 答对且未结束时对方走出应着；答错时不走子
+    pub async fn answer(&mut self, game: &mut GameManager, move_str: &str) -> Result<PuzzleAnswer> {
+        if self.finished {
+            return Err(anyhow!("本题已结束"));
+        }
+        if game.review.is_some() {
+            return Err(anyhow!("请先回到最新局面再解题"));
+        }
+        let state: GameState = game.state.clone();
+        let mv: Move = state.parse_notation(move_str)?;
+        let puzzle: Puzzle = self.puzzles[self.index].clone();
+
+        if let Some(progress) = self.progress
+            && puzzle.solution[progress] == mv
+        {
+            game.player_move(&mv.to_string()).await?;
+            self.budget -= 1;
+            let Some(&reply) = puzzle.solution.get(progress + 1) else {
+                self.finish(true)?;
+                return Ok(PuzzleAnswer::Solved);
+            };
+            game.player_move(&reply.to_string()).await?;
+            self.progress = Some(progress + 2);
+            return Ok(PuzzleAnswer::Continue(reply));
+        }
+
+        let mut next: GameState = state.clone();
+        next.apply_move(&mv.to_string())?;
+        if next.is_game_over() {
+            game.player_move(&mv.to_string()).await?;
+            self.finish(true)?;
+            return Ok(PuzzleAnswer::Solved);
+        }
+        if puzzle.mate
+            && self.budget > 1
+            && let Some((moves, reply)) = self.mated_in(&next).await?
+            && moves < self.budget
+        {
+            game.player_move(&mv.to_string()).await?;
+            game.player_move(&reply.to_string()).await?;
+            self.budget -= 1;
+            self.progress = None;
+            return Ok(PuzzleAnswer::Continue(reply));
+        }
+
+        let solution: Vec<Move> = match self.progress {
+            Some(progress) => puzzle.solution[progress..].to_vec(),
+            None => self.best_line(&state).await?,
+        };
+        self.finish(false)?;
+        Ok(PuzzleAnswer::Failed(solution))
+    }
+
+    /// 引擎判断局面中行棋方是否会被杀，返回杀棋方还需走的步数和引擎的应着
+    async fn mated_in(&mut self, state: &GameState) -> Result<Option<(usize, Move)>> {
+        let Some(engine) = &mut self.engine else {
+            return Ok(None);
+        };
+        engine.set_position(&state.to_fen()).await?;
+        let result: EngineGoResult = engine.go(Some(VERIFY_TIME)).await?;
+        let mate: Option<isize> = result.main_infos().rev().find_map(|info| info.mate);
+        match mate {
+            Some(mate) if mate < 0 => Ok(Some((mate.unsigned_abs(), Move::from_str(&result.best_move)?))),
+            _ => Ok(None),
+        }
+    }
+
+    /// 偏离题解后由引擎给出正解，没有引擎时为空
+    async fn best_line(&mut self, state: &GameState) -> Result<Vec<Move>> {
+        let Some(engine) = &mut self.engine else {
+            return Ok(Vec::new());
+        };
+        engine.set_position(&state.to_fen()).await?;
+        let result: EngineGoResult = engine.go(Some(VERIFY_TIME)).await?;
+        let pv: Vec<String> = result.main_infos().rev()
+            .find_map(|info| info.pv.clone())
+            .unwrap_or_else(|| vec![result.best_move.clone()]);
+        pv.iter().map(|mv| Move::from_str(mv)).collect()
+    }
+
+    /// 记录当前题目的结果
+    fn finish(&mut self, solved: bool) -> Result<()> {
+        self.finished = true;
+        self.stats.attempted += 1;
+        if solved {
+            self.stats.solved += 1;
+            self.stats.streak += 1;
+            self.stats.best_streak = self.stats.best_streak.max(self.stats.streak);
+            self.stats.solved_set.insert(self.puzzles[self.index].id);
+        } else {
+            self.stats.streak = 0;
+        }
+        self.save()
+    }
+
+    /// 保存解题统计
+    fn save(&self) -> Result<()> {
+        write_atomic(&self.stats_path, self.stats.to_toml())
+    }
+
+    /// 结束解题，关闭引擎
+    pub async fn close(&mut self) -> Result<()> {
+        if let Some(engine) = &mut self.engine {
+            engine.quit().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 双车错杀，红先一步杀
+    const MATE: &str = "3k5/R8/9/9/9/9/9/9/9/4K3R w";
+
+    #[test]
+    fn parse_fields() {
+        let puzzle: Puzzle = Puzzle::parse(&format!("{}; i0i9; 1500; 杀法，双车", MATE)).unwrap();
+        assert_eq!(puzzle.solution, [Move::from_str("i0i9").unwrap()]);
+        assert_eq!(puzzle.rating, Some(1500));
+        assert_eq!(puzzle.themes, ["杀法", "双车"]);
+        assert_eq!(puzzle.side, PlayerColor::Red);
+        assert!(puzzle.mate);
+
+        // 等级分和主题可以省略，题解可用中文记谱
+        let puzzle: Puzzle = Puzzle::parse(&format!("{}; 车九进一 将4进1 车一进八", MATE)).unwrap();
+        assert_eq!(puzzle.solution.len(), 3);
+        assert_eq!((puzzle.rating, puzzle.themes.len()), (None, 0));
+    }
+
+    #[test]
+    fn parse_rejects_bad_lines() {
+        // 题解为空或以对方的着法结束
+        assert!(Puzzle::parse(MATE).is_err());
+        assert!(Puzzle::parse(&format!("{}; i0i8 d9e9", MATE)).is_err());
+        assert!(Puzzle::parse(&format!("{}; i0i9; 高", MATE)).is_err());
+        assert!(Puzzle::parse(&format!("{}; e0e2", MATE)).is_err());
+        assert!(parse_puzzles("# 空题库\n\n").is_err());
+    }
+
+    #[test]
+    fn ids_follow_content() {
+        let text: String = format!("{}; i0i9\n{}; a8a9", MATE, MATE);
+        let puzzles: Vec<Puzzle> = parse_puzzles(&text).unwrap();
+        assert_ne!(puzzles[0].id, puzzles[1].id);
+        // 调整顺序后标识不变
+        let swapped: Vec<Puzzle> = parse_puzzles(&format!("{}; a8a9\n{}; i0i9", MATE, MATE)).unwrap();
+        assert_eq!(swapped[1].id, puzzles[0].id);
+
+        let stats: PuzzleStats = PuzzleStats { attempted: 3, solved: 2, solved_set: [puzzles[1].id].into(), ..PuzzleStats::default() };
+        let parsed: PuzzleStats = PuzzleStats::parse(&stats.to_toml()).unwrap();
+        assert_eq!((parsed.attempted, parsed.solved), (3, 2));
+        assert_eq!(parsed.solved_set, stats.solved_set);
+    }
+}
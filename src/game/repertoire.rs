@@ -0,0 +1,129 @@
+use crate::utils::*;
+use crate::game::{Book, BookEntry, FenProcessor, Move, MoveMark};
+
+// 开局谱文件为 TOML 格式，每个局面一个 [[position]] 表：
+// key 为局面键值（十六进制），fen 为局面；手写时可以只填 fen
+// moves 为着法数组，每项包含 move（坐标格式）、weight、games、mark（main/avoid）和 comment
+
+impl Book {
+    /// 由开局谱文本读取
+    pub(crate) fn from_repertoire(text: &str) -> Result<Self> {
+        let table: toml::Table = toml::from_str(text)?;
+        let mut book: Book = Book::default();
+        let positions: &[toml::Value] = table.get("position").and_then(|v| v.as_array()).map_or(&[], |v| v.as_slice());
+        for (i, position) in positions.iter().enumerate() {
+            let context = || format!("第{}个局面", i + 1);
+            let fen: Option<&str> = position.get("fen").and_then(|v| v.as_str());
+            let key: u64 = match (position.get("key").and_then(|v| v.as_str()), fen) {
+                (Some(key), _) => u64::from_str_radix(key, 16).map_err(|_| anyhow!("无效的局面键值: {}", key)).with_context(context)?,
+                (None, Some(fen)) => FenProcessor::parse_fen(fen).with_context(context)?.key(),
+                (None, None) => return Err(anyhow!("缺少 key 或 fen")).with_context(context),
+            };
+            if let Some(fen) = fen {
+                book.fens.insert(key, fen.to_string());
+            }
+            for mv in position.get("moves").and_then(|v| v.as_array()).map_or(&[][..], |v| v.as_slice()) {
+                let number = |name: &str| mv.get(name).and_then(|v| v.as_integer()).unwrap_or(0);
+                let text: &str = mv.get("move").and_then(|v| v.as_str()).ok_or_else(|| anyhow!("着法缺少 move 字段")).with_context(context)?;
+                book.entries.push(BookEntry {
+                    weight: number("weight").clamp(0, u16::MAX as i64) as u16,
+                    games: number("games").clamp(0, u32::MAX as i64) as u32,
+                    mark: mv.get("mark").and_then(|v| v.as_str()).map(MoveMark::from_str).transpose().with_context(context)?,
+                    comment: mv.get("comment").and_then(|v| v.as_str()).map(|s| s.to_string()),
+                    ..BookEntry::new(key, Move::from_str(text).with_context(context)?, 0)
+                });
+            }
+        }
+        book.entries.sort_by_key(|e| (e.key, std::cmp::Reverse(e.weight)));
+        Ok(book)
+    }
+
+    /// 写为开局谱文本
+    pub(crate) fn to_repertoire(&self) -> String {
+        let mut positions: Vec<toml::Value> = Vec::new();
+        for chunk in self.entries.chunk_by(|a, b| a.key == b.key) {
+            let key: u64 = chunk[0].key;
+            let mut position: toml::Table = toml::Table::new();
+            position.insert("key".to_string(), toml::Value::String(format!("{:016x}", key)));
+            if let Some(fen) = self.fens.get(&key) {
+                position.insert("fen".to_string(), toml::Value::String(fen.clone()));
+            }
+            let moves: Vec<toml::Value> = chunk.iter()
+                .map(|entry| {
+                    let mut mv: toml::Table = toml::Table::new();
+                    mv.insert("move".to_string(), toml::Value::String(entry.mv.to_string()));
+                    mv.insert("weight".to_string(), toml::Value::Integer(entry.weight as i64));
+                    if entry.games > 0 {
+                        mv.insert("games".to_string(), toml::Value::Integer(entry.games as i64));
+                    }
+                    if let Some(mark) = entry.mark {
+                        mv.insert("mark".to_string(), toml::Value::String(mark.as_str().to_string()));
+                    }
+                    if let Some(comment) = &entry.comment {
+                        mv.insert("comment".to_string(), toml::Value::String(comment.clone()));
+                    }
+                    toml::Value::Table(mv)
+                })
+                .collect();
+            position.insert("moves".to_string(), toml::Value::Array(moves));
+            positions.push(toml::Value::Table(position));
+        }
+        let mut table: toml::Table = toml::Table::new();
+        table.insert("position".to_string(), toml::Value::Array(positions));
+        table.to_string()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{GameState, START_FEN};
+
+    const TEXT: &str = r#"
+[[position]]
+fen = "rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w"
+moves = [
+    { move = "h2e2", weight = 10, games = 3, mark = "main", comment = "中炮" },
+    { move = "b0c2", weight = 2, mark = "avoid" },
+    { move = "g3g4", weight = 1 },
+]
+"#;
+
+    fn summary(book: &Book) -> Vec<String> {
+        book.entries.iter()
+            .map(|e| format!("{:016x} {} {} {} {:?} {:?}", e.key, e.mv, e.weight, e.games, e.mark, e.comment))
+            .collect()
+    }
+
+    #[test]
+    fn reads_positions_given_only_by_fen() {
+        let book: Book = Book::from_repertoire(TEXT).unwrap();
+        let key: u64 = GameState::new().key();
+        let moves: Vec<String> = book.probe(key).iter().map(|e| e.mv.to_string()).collect();
+        assert_eq!(moves, ["h2e2", "b0c2", "g3g4"]);
+        assert_eq!(book.probe(key)[0].mark, Some(MoveMark::Main));
+        assert_eq!(book.probe(key)[0].comment.as_deref(), Some("中炮"));
+        assert_eq!(book.probe(key)[1].mark, Some(MoveMark::Avoid));
+        assert_eq!(book.fens.get(&key).map(|s| s.as_str()), Some(START_FEN));
+    }
+
+    #[test]
+    fn round_trip_keeps_marks_comments_and_fens() {
+        let book: Book = Book::from_repertoire(TEXT).unwrap();
+        let text: String = book.to_repertoire();
+        let parsed: Book = Book::from_repertoire(&text).unwrap();
+        assert_eq!(summary(&parsed), summary(&book));
+        assert_eq!(parsed.fens, book.fens);
+        // 写出的文件带有键值，不依赖 FEN
+        assert!(text.contains(&format!("{:016x}", GameState::new().key())));
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        assert!(Book::from_repertoire("[[position]]\nmoves = []\n").is_err());
+        assert!(Book::from_repertoire("[[position]]\nkey = \"zz\"\nmoves = []\n").is_err());
+        let bad_mark: String = TEXT.replace("\"avoid\"", "\"never\"");
+        assert!(Book::from_repertoire(&bad_mark).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+use crate::utils::*;
+use crate::engine::EngineType;
+use crate::game::{parse_pgn, FenProcessor, GameManager, GameRecord, GameState, Handicap, HiddenPool, Move, MoveFormat, PlayerColor, Variant, ROOT};
+
+/// 最多保留的自动保存对局数
+const MAX_SESSIONS: usize = 20;
+
+/// 自动保存的对局
+#[derive(Debug, Clone)]
+pub struct Session {
+    /// 会话编号，文件名
+    pub id: u64,
+    /// 最后保存时间戳（秒）
+    pub saved_at: u64,
+    pub engine_type: Option<EngineType>,
+    /// 对局中设置过的引擎选项
+    pub options: Vec<(String, Option<String>)>,
+    pub player_color: Option<PlayerColor>,
+    pub handicap: Option<&'static Handicap>,
+    pub flipped: bool,
+    /// 对局棋谱；揭棋翻子无法重现，只保存当前局面
+    pub record: GameRecord,
+    /// 当前路线的着法
+    pub line: Vec<Move>,
+    /// 正在查看的半回合数
+    pub view_ply: usize,
+    /// 揭棋双方的暗子池，无法由局面推算，单独保存
+    pub pool: Option<HiddenPool>,
+}
+
+/// 自动保存目录
+fn sessions_dir() -> Result<PathBuf> {
+    data_dir("sessions")
+}
+
+impl Session {
+    /// 记录对局当前状态
+    pub fn capture(game: &GameManager) -> Self {
+        let jieqi: bool = game.state.variant == Variant::Jieqi;
+        let mut record: GameRecord = game.to_record();
+        if jieqi {
+            record.start_fen = game.state.to_fen();
+            record.comment = None;
+            record.moves.clear();
+        }
+        Self {
+            id: game.session_id,
+            saved_at: unix_time(),
+            engine_type: game.engine_type,
+            options: game.options.clone(),
+            player_color: game.player_color,
+            handicap: game.handicap,
+            flipped: game.state.flipped,
+            record,
+            line: if jieqi { Vec::new() } else { game.state.moves.clone() },
+            view_ply: if jieqi { 0 } else { game.view_ply() },
+            pool: jieqi.then(|| game.state.pool.clone()),
+        }
+    }
+
+    /// 由保存的会话重建游戏管理器，不带引擎
+    pub fn restore(&self, player_name: &str) -> Result<GameManager> {
+        let mut game: GameManager = GameManager::from_record(&self.record, player_name)?;
+        game.session_id = self.id;
+        game.handicap = self.handicap.or(game.handicap);
+        game.player_color = self.player_color;
+        game.state.flipped = self.flipped;
+        game.restore_line(&self.line, self.view_ply)?;
+        if let Some(pool) = &self.pool {
+            game.state.pool = pool.clone();
+        }
+        Ok(game)
+    }
+
+    fn to_toml(&self) -> Result<String> {
+        let mut table: toml::Table = toml::Table::new();
+        table.insert("id".to_string(), toml::Value::Integer(self.id as i64));
+        table.insert("saved_at".to_string(), toml::Value::Integer(self.saved_at as i64));
+        if let Some(engine_type) = self.engine_type {
+            table.insert("engine".to_string(), toml::Value::String(engine_type.to_string()));
+        }
+        if let Some(color) = self.player_color {
+            let color: &str = match color {
+                PlayerColor::Red => "red",
+                PlayerColor::Black => "black",
+            };
+            table.insert("player_color".to_string(), toml::Value::String(color.to_string()));
+        }
+        if let Some(handicap) = self.handicap {
+            table.insert("handicap".to_string(), toml::Value::String(handicap.alias.to_string()));
+        }
+        table.insert("flipped".to_string(), toml::Value::Boolean(self.flipped));
+        let line: Vec<String> = self.line.iter().map(|mv| mv.to_string()).collect();
+        table.insert("line".to_string(), toml::Value::String(line.join(" ")));
+        table.insert("view_ply".to_string(), toml::Value::Integer(self.view_ply as i64));
+        table.insert("pgn".to_string(), toml::Value::String(self.record.to_pgn(MoveFormat::Iccs)?));
+        if let Some(pool) = &self.pool {
+            let mut entry: toml::Table = toml::Table::new();
+            entry.insert("red".to_string(), toml::Value::String(pool.letters(PlayerColor::Red)));
+            entry.insert("black".to_string(), toml::Value::String(pool.letters(PlayerColor::Black)));
+            table.insert("pool".to_string(), toml::Value::Table(entry));
+        }
+        // 按设置顺序保存为 [名称, 值] 数组，与 engines.toml 相同，空字符串表示无值选项
+        let options: Vec<toml::Value> = self.options.iter()
+            .map(|(name, value)| {
+                toml::Value::Array(vec![
+                    toml::Value::String(name.clone()),
+                    toml::Value::String(value.clone().unwrap_or_default()),
+                ])
+            })
+            .collect();
+        table.insert("options".to_string(), toml::Value::Array(options));
+        Ok(table.to_string())
+    }
+
+    fn from_toml(text: &str) -> Result<Self> {
+        let table: toml::Table = toml::from_str(text)?;
+        let get = |key: &str| table.get(key).and_then(|v| v.as_str());
+        let number = |key: &str| -> Result<u64> {
+            table.get(key).and_then(|v| v.as_integer()).map(|n| n as u64).ok_or_else(|| anyhow!("缺少 {} 字段", key))
+        };
+        let pgn: &str = get("pgn").ok_or_else(|| anyhow!("缺少棋谱"))?;
+        let record: GameRecord = parse_pgn(pgn)?.into_iter().next().ok_or_else(|| anyhow!("缺少棋谱"))?;
+        let player_color: Option<PlayerColor> = match get("player_color") {
+            Some("red") => Some(PlayerColor::Red),
+            Some("black") => Some(PlayerColor::Black),
+            _ => None,
+        };
+        let line: Vec<Move> = get("line").unwrap_or_default()
+            .split_whitespace()
+            .map(Move::from_str)
+            .collect::<Result<_>>()?;
+        let options: Vec<(String, Option<String>)> = table.get("options")
+            .and_then(|v| v.as_array())
+            .map(|options| {
+                options.iter()
+                    .filter_map(|option| {
+                        let [name, value] = option.as_array()?.as_slice() else { return None };
+                        let value: &str = value.as_str()?;
+                        Some((name.as_str()?.to_string(), Some(value.to_string()).filter(|v| !v.is_empty())))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        // 暗子池与棋谱局面不符时退回由局面推算
+        let pool: Option<HiddenPool> = match table.get("pool") {
+            Some(entry) => {
+                let side = |key: &str| entry.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+                let state: GameState = FenProcessor::parse_fen(&record.start_fen)?;
+                match HiddenPool::parse(side("red"), side("black"), &state.board) {
+                    Ok(pool) => Some(pool),
+                    Err(e) => {
+                        log_warn!(e);
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+        Ok(Self {
+            id: number("id")?,
+            saved_at: number("saved_at")?,
+            engine_type: get("engine").map(EngineType::from_str).transpose()?,
+            options,
+            player_color,
+            handicap: get("handicap").map(Handicap::find).transpose()?,
+            flipped: table.get("flipped").and_then(|v| v.as_bool()).unwrap_or(false),
+            record,
+            line,
+            view_ply: number("view_ply").unwrap_or(0) as usize,
+            pool,
+        })
+    }
+
+    /// 写入自动保存目录
+    pub fn save(&self) -> Result<()> {
+        let dir: PathBuf = sessions_dir()?;
+        let path: PathBuf = dir.join(format!("{}.toml", self.id));
+        write_atomic(&path, &self.to_toml()?)?;
+
+        // 只保留最近的若干局
+        for (id, _) in Self::entries()?.into_iter().skip(MAX_SESSIONS) {
+            let _ = std::fs::remove_file(dir.join(format!("{}.toml", id)));
+        }
+        Ok(())
+    }
+
+    /// 全部存档的编号和修改时间，最近的在前
+    fn entries() -> Result<Vec<(u64, std::time::SystemTime)>> {
+        let mut entries: Vec<(u64, std::time::SystemTime)> = std::fs::read_dir(sessions_dir()?)?
+            .filter_map(|entry| {
+                let entry: std::fs::DirEntry = entry.ok()?;
+                let id: u64 = entry.file_name().to_str()?.strip_suffix(".toml")?.parse().ok()?;
+                Some((id, entry.metadata().ok()?.modified().ok()?))
+            })
+            .collect();
+        entries.sort_by_key(|&(_, modified)| std::cmp::Reverse(modified));
+        Ok(entries)
+    }
+
+    /// 最近自动保存的对局，最近的在前；无法解析的存档被跳过
+    pub fn list() -> Result<Vec<Session>> {
+        let dir: PathBuf = sessions_dir()?;
+        Ok(Self::entries()?
+            .into_iter()
+            .filter_map(|(id, _)| {
+                let text: String = read_to_string(dir.join(format!("{}.toml", id))).ok()?;
+                Self::from_toml(&text).ok()
+            })
+            .collect())
+    }
+}
+
+impl GameManager {
+    /// 自动保存当前对局，尚未走子的对局不保存
+    pub fn autosave(&self) -> Result<()> {
+        if self.tree.node(ROOT).children.is_empty() && self.state.moves.is_empty() {
+            return Ok(());
+        }
+        Session::capture(self).save()
+    }
+}
+
@@ -0,0 +1,239 @@
+use crate::utils::*;
+use crate::game::{FenProcessor, GameState, Piece, PieceKind, PlayerColor, Position, Variant, disguise_kind};
+
+/// 空棋盘FEN
+pub const EMPTY_FEN: &str = "9/9/9/9/9/9/9/9/9/9 w";
+
+/// 摆棋起始局面
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetupBase {
+    /// 空棋盘
+    Empty,
+    /// 对局中正在查看的局面
+    Current,
+    /// 标准开局局面
+    Start,
+}
+
+impl FromStr for SetupBase {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "empty" | "空" => Ok(SetupBase::Empty),
+            "current" | "当前" => Ok(SetupBase::Current),
+            "start" | "初始" => Ok(SetupBase::Start),
+            _ => Err(anyhow!("无效的摆棋起始局面: {}，使用 empty/current/start", s)),
+        }
+    }
+}
+
+impl GameState {
+    /// 创建空棋盘
+    pub fn empty() -> Self {
+        FenProcessor::parse_fen(EMPTY_FEN).unwrap()
+    }
+
+    /// 在指定格子放置棋子，覆盖原有棋子
+    pub fn put_piece(&mut self, piece: Piece, pos: Position) -> Result<()> {
+        self.board.put(pos, piece)?;
+        self.clear_history();
+        Ok(())
+    }
+
+    /// 清空指定格子，返回被移除的棋子
+    pub fn clear_square(&mut self, pos: Position) -> Option<Piece> {
+        self.clear_history();
+        self.board.remove(pos)
+    }
+
+    /// 校验摆出的局面是否符合象棋摆子规则
+    pub fn validate_setup(&self) -> Result<()> {
+        for color in [PlayerColor::Red, PlayerColor::Black] {
+            self.validate_side(color)?;
+        }
+
+        // 将帅不能照面
+        if self.generals_facing() {
+            return Err(anyhow!("将帅不能照面"));
+        }
+
+        // 非行棋方不能正被将军
+        if self.is_in_check(self.current_player.opponent()) {
+            return Err(anyhow!("非行棋方正被将军"));
+        }
+
+        Ok(())
+    }
+
+    /// 查找指定方的将/帅位置
+    pub fn find_general(&self, color: PlayerColor) -> Option<Position> {
+        self.board.general(color)
+    }
+
+    /// 校验单方棋子的数量和位置
+    fn validate_side(&self, color: PlayerColor) -> Result<()> {
+        let side: &str = match color {
+            PlayerColor::Red => "红方",
+            PlayerColor::Black => "黑方",
+        };
+        let mut counts: HashMap<&'static str, usize> = HashMap::new();
+        let mut hidden: usize = 0;
+
+        for (pos, piece) in self.board.iter() {
+            if piece.color != color {
+                continue;
+            }
+            let name: &'static str = piece.get_chinese_name();
+            if piece.hidden {
+                hidden += 1;
+            } else {
+                *counts.entry(name).or_insert(0) += 1;
+            }
+
+            if !self.is_legal_square(piece, pos) {
+                return Err(anyhow!("{}的{}不能放在{}", side, name, pos));
+            }
+        }
+
+        let limits: [(PieceKind, usize); 7] = [
+            (PieceKind::General, 1),
+            (PieceKind::Advisor, 2),
+            (PieceKind::Elephant, 2),
+            (PieceKind::Horse, 2),
+            (PieceKind::Rook, 2),
+            (PieceKind::Cannon, 2),
+            (PieceKind::Pawn, 5),
+        ];
+        for (kind, limit) in limits {
+            let name: &'static str = Piece { color, kind, hidden: false }.get_chinese_name();
+            let count: usize = counts.get(name).copied().unwrap_or(0);
+            if kind == PieceKind::General && count != limit {
+                return Err(anyhow!("{}必须有且只有一个{}", side, name));
+            }
+            if count > limit {
+                return Err(anyhow!("{}的{}最多{}个，当前{}个", side, name, limit, count));
+            }
+        }
+
+        // 暗子的身份取自尚未出现的棋子，与明子合计不能超过一整套
+        let total: usize = counts.values().sum::<usize>() + hidden;
+        let full_set: usize = limits.iter().map(|(_, limit)| limit).sum();
+        if total > full_set {
+            return Err(anyhow!("{}最多{}个棋子，当前{}个（其中暗子{}个）", side, full_set, total, hidden));
+        }
+
+        Ok(())
+    }
+
+    /// 判断棋子能否出现在指定格子
+    fn is_legal_square(&self, piece: Piece, pos: Position) -> bool {
+        // 暗子必须位于初始位置
+        if piece.hidden {
+            return disguise_kind(piece.color, pos) == Some(piece.kind);
+        }
+
+        // 统一转换为红方视角，行0为己方底线
+        let row: usize = match piece.color {
+            PlayerColor::Red => pos.row,
+            PlayerColor::Black => 9 - pos.row,
+        };
+        let col: usize = pos.col;
+
+        match piece.kind {
+            PieceKind::General => row <= 2 && (3..=5).contains(&col),
+            // 揭棋中翻开的士象可以离开九宫、过河
+            PieceKind::Advisor | PieceKind::Elephant if self.variant == Variant::Jieqi => true,
+            PieceKind::Advisor => matches!((row, col), (0, 3) | (0, 5) | (1, 4) | (2, 3) | (2, 5)),
+            PieceKind::Elephant => matches!(
+                (row, col),
+                (0, 2) | (0, 6) | (2, 0) | (2, 4) | (2, 8) | (4, 2) | (4, 6)
+            ),
+            // 兵过河前只能在初始的兵线上
+            PieceKind::Pawn => row >= 5 || ((3..=4).contains(&row) && col.is_multiple_of(2)),
+            PieceKind::Horse | PieceKind::Rook | PieceKind::Cannon => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::JIEQI_START_FEN;
+
+    fn state(fen: &str) -> GameState {
+        FenProcessor::parse_fen(fen).unwrap()
+    }
+
+    fn piece(c: char) -> Piece {
+        FenProcessor::char_to_piece(c).unwrap()
+    }
+
+    fn pos(s: &str) -> Position {
+        Position::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn validate_setup_rules() {
+        GameState::new().validate_setup().unwrap();
+        state("3k5/9/9/9/9/9/9/9/9/4K4 w").validate_setup().unwrap();
+        // 缺少将帅、将帅照面、非行棋方被将军、棋子过多
+        assert!(state("3k5/9/9/9/9/9/9/9/9/9 w").validate_setup().is_err());
+        assert!(state("4k4/9/9/9/9/9/9/9/9/4K4 w").validate_setup().is_err());
+        assert!(state("3k5/9/9/9/9/9/9/9/9/3RK4 w").validate_setup().is_err());
+        assert!(state("3k5/9/9/9/9/9/9/9/9/RRR1K4 w").validate_setup().is_err());
+        // 仕不在九宫的点上
+        assert!(state("3k5/9/9/9/9/9/9/9/3A5/4K4 w").validate_setup().is_err());
+    }
+
+    #[test]
+    fn legal_squares() {
+        let standard: GameState = GameState::empty();
+        assert!(standard.is_legal_square(piece('A'), pos("e1")));
+        assert!(!standard.is_legal_square(piece('A'), pos("e2")));
+        assert!(standard.is_legal_square(piece('b'), pos("c9")));
+        assert!(!standard.is_legal_square(piece('b'), pos("c4")));
+        assert!(standard.is_legal_square(piece('P'), pos("a3")));
+        assert!(!standard.is_legal_square(piece('P'), pos("b3")));
+        assert!(standard.is_legal_square(piece('P'), pos("b5")));
+        assert!(!standard.is_legal_square(piece('K'), pos("e3")));
+    }
+
+    #[test]
+    fn legal_squares_in_jieqi() {
+        let jieqi: GameState = state(JIEQI_START_FEN);
+        assert_eq!(jieqi.variant, Variant::Jieqi);
+        jieqi.validate_setup().unwrap();
+        // 暗子只能位于走法种类对应的初始位置
+        let hidden = |c: char, kind: PieceKind| Piece { kind, hidden: true, ..piece(c) };
+        assert!(jieqi.is_legal_square(hidden('R', PieceKind::Rook), pos("a0")));
+        assert!(!jieqi.is_legal_square(hidden('R', PieceKind::Rook), pos("b0")));
+        assert!(!jieqi.is_legal_square(hidden('R', PieceKind::Rook), pos("a9")));
+        assert!(jieqi.is_legal_square(hidden('r', PieceKind::Cannon), pos("b7")));
+        assert!(!jieqi.is_legal_square(hidden('R', PieceKind::General), pos("e0")));
+        // 翻开的士象不受九宫和河界限制
+        assert!(jieqi.is_legal_square(piece('A'), pos("a5")));
+        assert!(jieqi.is_legal_square(piece('b'), pos("e0")));
+        assert!(!jieqi.is_legal_square(piece('K'), pos("a5")));
+    }
+
+    #[test]
+    fn put_and_clear() {
+        let mut setup: GameState = GameState::empty();
+        setup.put_piece(piece('K'), pos("e0")).unwrap();
+        setup.put_piece(piece('k'), pos("d9")).unwrap();
+        setup.put_piece(piece('R'), pos("a5")).unwrap();
+        // 覆盖原有棋子
+        setup.put_piece(piece('n'), pos("a5")).unwrap();
+        assert_eq!(setup.to_fen(), "3k5/9/9/9/n8/9/9/9/9/4K4 w");
+        assert!(setup.clear_square(pos("a5")).is_some_and(|p| p.kind == PieceKind::Horse));
+        assert!(setup.clear_square(pos("a5")).is_none());
+        assert_eq!(setup.find_general(PlayerColor::Black), Some(pos("d9")));
+        setup.validate_setup().unwrap();
+
+        // 每方最多16个棋子
+        let mut full: GameState = GameState::new();
+        assert!(full.put_piece(piece('R'), pos("e4")).is_err());
+        full.put_piece(piece('R'), pos("a3")).unwrap();
+    }
+}
@@ -1,5 +1,5 @@
 use crate::utils::*;
-use crate::game::FenProcessor;
+use crate::game::{Board, FenProcessor, HiddenPool, Move, Variant, JIEQI_START_FEN, START_FEN, ZOBRIST_SIDE};
 
 /// 玩家颜色
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -34,7 +34,10 @@ pub enum PieceKind {
 #[derive(Debug, Clone, Copy)]
 pub struct Piece {
     pub color: PlayerColor,
+    /// 棋子种类，揭棋暗子为其初始位置对应的走法种类
     pub kind: PieceKind,
+    /// 是否为揭棋暗子
+    pub hidden: bool,
 }
 
 /// 坐标
@@ -44,23 +47,108 @@ pub struct Position {
     pub col: usize,
 }
 
+impl FromStr for Position {
+    type Err = anyhow::Error;
+
+    /// 解析格子坐标，例如 "a9" -> 第0列第9行
+    fn from_str(s: &str) -> Result<Self> {
+        let chars: Vec<char> = s.to_lowercase().chars().collect();
+        if chars.len() != 2 {
+            return Err(anyhow!("格子格式错误，应为2个字符，如 'e0'"));
+        }
+        let col: usize = match chars[0] {
+            'a'..='i' => chars[0] as usize - 'a' as usize,
+            _ => return Err(anyhow!("列无效: {}", chars[0])),
+        };
+        let row: usize = match chars[1] {
+            '0'..='9' => chars[1] as usize - '0' as usize,
+            _ => return Err(anyhow!("行无效: {}", chars[1])),
+        };
+        Ok(Position { row, col })
+    }
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", (b'a' + self.col as u8) as char, self.row)
+    }
+}
+
+/// 撤销走子所需的信息
+#[derive(Debug, Clone, Copy)]
+pub struct Undo {
+    /// 所走着法
+    pub mv: Move,
+    /// 走子前的棋子，揭棋中可能是暗子
+    moved: Piece,
+    /// 被吃掉的棋子
+    captured: Option<Piece>,
+    /// 被吃暗子从暗子池中抽出的身份
+    captured_identity: Option<PieceKind>,
+}
+
+impl Undo {
+    /// 本步是否吃子
+    pub fn is_capture(&self) -> bool {
+        self.captured.is_some()
+    }
+
+    /// 本步是否翻开了暗子
+    pub fn is_reveal(&self) -> bool {
+        self.moved.hidden
+    }
+}
+
 /// 游戏状态
 #[derive(Clone)]
 pub struct GameState {
     /// 棋盘，10行9列，行0-9，列0-8
-    pub board: [[Option<Piece>; 9]; 10],
+    pub board: Board,
     /// 当前轮到哪个玩家
     pub current_player: PlayerColor,
     /// 走子历史
     pub history: Vec<String>,
+    /// 走子着法记录，与 history 一一对应
+    pub moves: Vec<Move>,
     /// 棋盘是否翻转显示
     pub flipped: bool,
+    /// 棋类变体
+    pub variant: Variant,
+    /// 揭棋暗子池
+    pub pool: HiddenPool,
+    /// 每步走子前的局面键值，用于检测重复局面
+    keys: Vec<u64>,
 }
 
 impl GameState {
     /// 创建初始游戏状态
     pub fn new() -> Self {
-        FenProcessor::parse_fen("rnbakabnr/9/1c5c1/p1p1p1p1p/9/9/P1P1P1P1P/1C5C1/9/RNBAKABNR w").unwrap()
+        FenProcessor::parse_fen(START_FEN).unwrap()
+    }
+
+    /// 创建揭棋初始状态
+    pub fn new_jieqi() -> Self {
+        FenProcessor::parse_fen(JIEQI_START_FEN).unwrap()
+    }
+
+    /// 由棋盘和行棋方构造局面，含暗子的局面视为揭棋
+    pub fn from_board(board: Board, current_player: PlayerColor) -> Self {
+        let has_hidden: bool = board.iter().any(|(_, p)| p.hidden);
+        let (variant, pool) = if has_hidden {
+            (Variant::Jieqi, HiddenPool::from_board(&board))
+        } else {
+            (Variant::Standard, HiddenPool::default())
+        };
+        Self {
+            board,
+            current_player,
+            history: Vec::new(),
+            moves: Vec::new(),
+            flipped: false,
+            variant,
+            pool,
+            keys: Vec::new(),
+        }
     }
 
     /// 重置为初始状态
@@ -68,6 +156,106 @@ impl GameState {
         *self = Self::new();
     }
 
+    /// 清空走子历史，局面被编辑后历史不再有效
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+        self.moves.clear();
+        self.keys.clear();
+    }
+
+    /// 复制当前局面，不带走子历史
+    pub fn snapshot(&self) -> Self {
+        Self {
+            board: self.board.clone(),
+            current_player: self.current_player,
+            history: Vec::new(),
+            moves: Vec::new(),
+            flipped: self.flipped,
+            variant: self.variant,
+            pool: self.pool.clone(),
+            keys: Vec::new(),
+        }
+    }
+
+    /// 空着：复制当前局面并交换行棋方，不带走子历史；行棋方正被将军时空着不合法
+    pub fn null_move(&self) -> Result<Self> {
+        if self.is_in_check(self.current_player) {
+            return Err(anyhow!("正被将军，无法让对方连走"));
+        }
+        let mut state: GameState = self.snapshot();
+        state.current_player = self.current_player.opponent();
+        Ok(state)
+    }
+
+    /// 当前局面的 Zobrist 键值（含行棋方）
+    pub fn key(&self) -> u64 {
+        match self.current_player {
+            PlayerColor::Red => self.board.key(),
+            PlayerColor::Black => self.board.key() ^ ZOBRIST_SIDE,
+        }
+    }
+
+    /// 从起始局面到当前局面经过的全部局面键值
+    pub fn position_keys(&self) -> impl Iterator<Item = u64> + '_ {
+        self.keys.iter().copied().chain(std::iter::once(self.key()))
+    }
+
+    /// 当前局面在历史中（同一方行棋时）已出现的次数
+    pub fn repetition_count(&self) -> usize {
+        let key: u64 = self.key();
+        self.keys.iter().rev().skip(1).step_by(2).filter(|&&k| k == key).count()
+    }
+
+    /// 不做合法性检查地走子，揭棋暗子随机翻开；返回用于撤销的信息
+    pub fn make_move(&mut self, mv: Move) -> Undo {
+        self.keys.push(self.key());
+
+        let moved: Piece = self.board.get(mv.from).expect("起始格子没有棋子");
+
+        // 被吃掉的暗子从对方暗子池中抽出身份
+        let captured_identity: Option<PieceKind> = match self.board.get(mv.to) {
+            Some(captured) if captured.hidden => self.pool.draw(captured.color),
+            _ => None,
+        };
+        let captured: Option<Piece> = self.board.move_piece(mv.from, mv.to);
+
+        // 揭棋暗子走动后翻开，暗子池耗尽时保持原走法种类
+        if moved.hidden {
+            let kind: PieceKind = self.pool.draw(moved.color).unwrap_or(moved.kind);
+            self.board.replace(mv.to, Piece { color: moved.color, kind, hidden: false });
+        }
+
+        self.current_player = self.current_player.opponent();
+        Undo { mv, moved, captured, captured_identity }
+    }
+
+    /// 撤销 make_move
+    pub fn unmake_move(&mut self, undo: Undo) {
+        let Undo { mv, moved, captured, captured_identity } = undo;
+        if moved.hidden {
+            let revealed: Piece = self.board.get(mv.to).expect("目标格子没有棋子");
+            self.pool.put_back(revealed.color, revealed.kind);
+            self.board.replace(mv.to, moved);
+        }
+        self.board.undo_move(mv.from, mv.to, captured);
+        if let (Some(piece), Some(kind)) = (captured, captured_identity) {
+            self.pool.put_back(piece.color, kind);
+        }
+        self.current_player = self.current_player.opponent();
+        self.keys.pop();
+    }
+
+    /// 检查走法和走后将帅安全后走子，不生成中文记谱，也不记入 history 和 moves
+    /// 供导入、建库等批量回放使用
+    pub fn play_move(&mut self, mv: Move) -> Result<()> {
+        self.is_valid_move(mv.source(), mv.target())?;
+        if !self.board.is_king_safe_after(self.current_player, mv) {
+            return Err(anyhow!("走子后己方将帅被将军或照面"));
+        }
+        self.make_move(mv);
+        Ok(())
+    }
+
     /// 应用一个走法
     /// 走法字符串格式：起始位置+目标位置，例如 "h2e2"
     /// 起始位置：列从a到i，行从0到9（0在底部，9在顶部）
@@ -76,19 +264,24 @@ impl GameState {
         let (from, to) = Self::parse_move(move_str)?;
         
         // 合法性检查
-        self.is_valid_move(from, to)?;
+        self.is_legal_move(from, to)?;
 
         // 记录走法
-        let chinese_move: String = self.move_to_chinese(&move_str)?;
-        log_info!(self.current_player, move_str, chinese_move, from, to);
-        self.history.push(chinese_move);
+        let mut chinese_move: String = self.move_to_chinese(move_str)?;
         
-        // 执行移动：将棋子移动到目标位置，起始位置置空
-        self.board[to.row][to.col] = self.board[from.row][from.col];
-        self.board[from.row][from.col] = None;
+        // 执行移动
+        let undo: Undo = self.make_move(Move::new(from, to));
         
-        // 切换玩家
-        self.current_player = self.current_player.opponent();
+        // 揭棋暗子走动后翻开
+        if undo.is_reveal()
+            && let Some(piece) = self.board[to]
+        {
+            chinese_move.push_str(&format!("(翻{})", piece.get_chinese_name()));
+        }
+        
+        log_info!(self.current_player, move_str, chinese_move, from, to);
+        self.history.push(chinese_move);
+        self.moves.push(undo.mv);
         
         Ok(())
     }
@@ -133,7 +326,7 @@ impl GameState {
     /// 走法合法性验证
     pub fn is_valid_move(&self, from: Position, to: Position) -> Result<()> {
         // 检查起始位置是否有棋子
-        let piece: Piece = self.board[from.row][from.col]
+        let piece: Piece = self.board.at(from.row, from.col)
             .ok_or_else(|| anyhow!("起始位置没有棋子"))?;
         
         // 检查棋子颜色是否与当前玩家一致   
@@ -142,10 +335,10 @@ impl GameState {
         }
 
         // 检查目标位置是否有己方棋子
-        if let Some(target_piece) = self.board[to.row][to.col] {
-            if target_piece.color == self.current_player {
-                return Err(anyhow!("目标位置已有己方棋子"));
-            }
+        if let Some(target_piece) = self.board.at(to.row, to.col)
+            && target_piece.color == self.current_player
+        {
+            return Err(anyhow!("目标位置已有己方棋子"));
         }
 
         // 根据棋子种类检查
@@ -175,18 +368,21 @@ impl GameState {
             },
             // 士/仕
             PieceKind::Advisor => {
-                // 士/仕只能在九宫内移动
-                match self.current_player {
-                    PlayerColor::Red => {
-                        if to.row > 2 || to.col < 3 || to.col > 5 {
-                            return Err(anyhow!("仕只能在九宫内移动"));
-                        }
-                    },
-                    PlayerColor::Black => {
-                        if to.row < 7 || to.col < 3 || to.col > 5 {
-                            return Err(anyhow!("士只能在九宫内移动"));
-                        }
-                    },
+                // 士/仕只能在九宫内移动，揭棋中翻开的士/仕不受限制
+                let relaxed: bool = self.variant == Variant::Jieqi && !piece.hidden;
+                if !relaxed {
+                    match self.current_player {
+                        PlayerColor::Red => {
+                            if to.row > 2 || to.col < 3 || to.col > 5 {
+                                return Err(anyhow!("仕只能在九宫内移动"));
+                            }
+                        },
+                        PlayerColor::Black => {
+                            if to.row < 7 || to.col < 3 || to.col > 5 {
+                                return Err(anyhow!("士只能在九宫内移动"));
+                            }
+                        },
+                    }
                 }
                 // 士/仕只能斜向移动一步
                 if (from.row as isize - to.row as isize).abs() != 1
@@ -197,18 +393,21 @@ impl GameState {
             },
             // 象/相
             PieceKind::Elephant => {
-                // 象/相不能过河
-                match self.current_player {
-                    PlayerColor::Red => {
-                        if to.row > 4 {
-                            return Err(anyhow!("相不能过河"));
-                        }
-                    },
-                    PlayerColor::Black => {
-                        if to.row < 5 {
-                            return Err(anyhow!("象不能过河"));
-                        }
-                    },
+                // 象/相不能过河，揭棋中翻开的象/相不受限制
+                let relaxed: bool = self.variant == Variant::Jieqi && !piece.hidden;
+                if !relaxed {
+                    match self.current_player {
+                        PlayerColor::Red => {
+                            if to.row > 4 {
+                                return Err(anyhow!("相不能过河"));
+                            }
+                        },
+                        PlayerColor::Black => {
+                            if to.row < 5 {
+                                return Err(anyhow!("象不能过河"));
+                            }
+                        },
+                    }
                 }
                 // 象/相只能斜向移动两步
                 if (from.row as isize - to.row as isize).abs() != 2
@@ -219,24 +418,24 @@ impl GameState {
                 // 检查象/相是否被挡
                 let mid_row: usize = (from.row + to.row) / 2;
                 let mid_col: usize = (from.col + to.col) / 2;
-                if self.board[mid_row][mid_col].is_some() {
+                if self.board.at(mid_row, mid_col).is_some() {
                     return Err(anyhow!("象/相的路径被挡"));
                 }
             },
             // 马
             PieceKind::Horse => {
                 // 马只能走日字形
-                if !((from.row as isize - to.row as isize).abs() == 2 && (from.col as isize - to.col as isize).abs() == 1)
-                    && !((from.row as isize - to.row as isize).abs() == 1 && (from.col as isize - to.col as isize).abs() == 2)
+                if !(((from.row as isize - to.row as isize).abs() == 2 && (from.col as isize - to.col as isize).abs() == 1)
+                    || ((from.row as isize - to.row as isize).abs() == 1 && (from.col as isize - to.col as isize).abs() == 2))
                 {
                     return Err(anyhow!("马只能走日字形"));
                 }
                 // 检查马腿是否被挡
-                let row_diff: usize = (from.row as isize - to.row as isize).abs() as usize;
-                let col_diff: usize = (from.col as isize - to.col as isize).abs() as usize;
+                let row_diff: usize = (from.row as isize - to.row as isize).unsigned_abs();
+                let col_diff: usize = (from.col as isize - to.col as isize).unsigned_abs();
                 let leg_row: usize = if row_diff == 2 { (to.row + from.row) / 2 } else { from.row };
                 let leg_col: usize = if col_diff == 2 { (to.col + from.col) / 2 } else { from.col };
-                if self.board[leg_row][leg_col].is_some() {
+                if self.board.at(leg_row, leg_col).is_some() {
                     return Err(anyhow!("马腿被挡"));
                 }
             },
@@ -252,7 +451,7 @@ impl GameState {
                     let start_col: usize = from.col.min(to.col);
                     let end_col: usize = from.col.max(to.col);
                     for col in (start_col + 1)..end_col {
-                        if self.board[from.row][col].is_some() {
+                        if self.board.at(from.row, col).is_some() {
                             return Err(anyhow!("车的路径被挡"));
                         }
                     }
@@ -262,7 +461,7 @@ impl GameState {
                     let start_row: usize = from.row.min(to.row);
                     let end_row: usize = from.row.max(to.row);
                     for row in (start_row + 1)..end_row {
-                        if self.board[row][from.col].is_some() {
+                        if self.board.at(row, from.col).is_some() {
                             return Err(anyhow!("车的路径被挡"));
                         }
                     }
@@ -282,7 +481,7 @@ impl GameState {
                     let start_col: usize = from.col.min(to.col);
                     let end_col: usize = from.col.max(to.col);
                     for col in (start_col + 1)..end_col {
-                        if self.board[from.row][col].is_some() {
+                        if self.board.at(from.row, col).is_some() {
                             obstacle_count += 1;
                         }
                     }
@@ -291,7 +490,7 @@ impl GameState {
                     let start_row: usize = from.row.min(to.row);
                     let end_row: usize = from.row.max(to.row);
                     for row in (start_row + 1)..end_row {
-                        if self.board[row][from.col].is_some() {
+                        if self.board.at(row, from.col).is_some() {
                             obstacle_count += 1;
                         }
                     }
@@ -299,7 +498,7 @@ impl GameState {
                 
                 // 如果炮是移动，不能有棋子挡路
                 // 如果炮是吃子，检查炮架有且仅有一个子
-                if self.board[to.row][to.col].is_some() {
+                if self.board.at(to.row, to.col).is_some() {
                     if obstacle_count == 0 {
                         return Err(anyhow!("缺少炮架"));
                     }
@@ -325,8 +524,8 @@ impl GameState {
                         }
                         // 兵过河后可以前进或横向移动
                         else {
-                            if !(to.row == from.row + 1 && to.col == from.col) &&
-                               !(to.row == from.row && (to.col as isize - from.col as isize).abs() == 1) {
+                            if !((to.row == from.row + 1 && to.col == from.col) ||
+                                 (to.row == from.row && (to.col as isize - from.col as isize).abs() == 1)) {
                                 return Err(anyhow!("兵过河后只能前进或横向移动"));
                             }
                         }
@@ -340,8 +539,8 @@ impl GameState {
                         }
                         // 卒过河后可以前进或横向移动
                         else {
-                            if !(to.row == from.row - 1 && to.col == from.col) &&
-                               !(to.row == from.row && (to.col as isize - from.col as isize).abs() == 1) {
+                            if !((to.row + 1 == from.row && to.col == from.col) ||
+                                 (to.row == from.row && (to.col as isize - from.col as isize).abs() == 1)) {
                                 return Err(anyhow!("卒过河后只能前进或横向移动"));
                             }
                         }
@@ -358,102 +557,15 @@ impl GameState {
         FenProcessor::generate_fen(self)
     }
 
-    /// 将走法转换为中文表示
-    /// 例如: "e2h2" -> "炮二平五"
-    pub fn move_to_chinese(&self, move_str: &str) -> Result<String> {
-        let (from, to) = Self::parse_move(move_str)?;
-        
-        // 获取起始位置的棋子
-        let piece: Piece = self.board[from.row][from.col]
-            .ok_or_else(|| anyhow!("起始位置没有棋子"))?;
-        
-        // 获取棋子中文名称
-        let piece_name: &'static str = piece.get_chinese_name();
-
-        let part1: String;
-        // 中文和数字列名
-        const ZH_LIST: [&str; 9] = ["九", "八", "七", "六", "五", "四", "三", "二", "一"];
-        const DIG_LIST: [&str; 9] = ["1", "2", "3", "4", "5", "6", "7", "8", "9"];
-
-        // 检查该列该类棋子的位置
-        let mut same_piece_idxs: Vec<usize> = Vec::new();
-        for row in 0..10 {
-            if let Some(other_piece) = self.board[row][from.col] {
-                if other_piece.color == piece.color && other_piece.kind == piece.kind {
-                    same_piece_idxs.push(row);
-                }
-            }
-        }
-        
-        // 唯一
-        if same_piece_idxs.len() == 1 {
-            let from_col_name: &str = match self.current_player {
-                PlayerColor::Red => ZH_LIST[from.col],
-                PlayerColor::Black => DIG_LIST[from.col],
-            };
-            part1 = format!("{}{}", piece_name, from_col_name);
-        }
-        // 前/后
-        else {
-            let pos_type: &str;
-            let idx: usize = same_piece_idxs.iter().position(|&r| r == from.row).unwrap();
-            match self.current_player {
-                PlayerColor::Red => {
-                    pos_type = if idx == same_piece_idxs.len() - 1 { "前" } else { "后" };
-                }
-                PlayerColor::Black => {
-                    pos_type = if idx == 0 { "前" } else { "后" };
-                }
-            };
-            part1 = format!("{}{}", pos_type, piece_name);
-        }
-
-        let move_type: &str;
-        let move_detail: &str;
-
-        // 平
-        if from.row == to.row {
-            move_type = "平";
-            move_detail = match self.current_player {
-                PlayerColor::Red => ZH_LIST[to.col],
-                PlayerColor::Black => DIG_LIST[to.col],
-            };
-        }
-        // 进 退
-        else {
-            move_type = match self.current_player {
-                PlayerColor::Red => if from.row < to.row { "进" } else { "退" },
-                PlayerColor::Black => if from.row > to.row { "进" } else { "退" },
-            };
-            // 按进退步数
-            if from.col == to.col {
-                let diff: usize = (from.row as isize - to.row as isize).abs() as usize;
-                move_detail = match self.current_player {
-                    PlayerColor::Red => ZH_LIST[9 - diff],
-                    PlayerColor::Black => DIG_LIST[diff - 1],
-                };
-            }
-            // 按列名
-            else {
-                move_detail = match self.current_player {
-                    PlayerColor::Red => ZH_LIST[to.col],
-                    PlayerColor::Black => DIG_LIST[to.col],
-                };
-            }
-        }
-        let part2: String = format!("{}{}", move_type, move_detail);
-
-        Ok(format!("{}{}", part1, part2))
-    }
-
     /// 模拟连续走法转换为中文表示
-    pub fn pv_to_chinese(&self, pv: &Vec<String>) -> Result<Vec<String>> {
-        let mut state: GameState = self.clone();
-        let mut zh_moves: Vec<String> = Vec::new();
+    pub fn pv_to_chinese(&self, pv: &[String]) -> Result<Vec<String>> {
+        let mut state: GameState = self.snapshot();
+        let mut zh_moves: Vec<String> = Vec::with_capacity(pv.len());
         for move_str in pv {
-            let move_zh: String = state.move_to_chinese(&move_str)?;
-            state.apply_move(move_str)?;
-            zh_moves.push(move_zh);
+            let (from, to) = Self::parse_move(move_str)?;
+            state.is_legal_move(from, to)?;
+            zh_moves.push(state.move_to_chinese(move_str)?);
+            state.make_move(Move::new(from, to));
         }
         Ok(zh_moves)
     }
@@ -0,0 +1,36 @@
+use crate::utils::*;
+use crate::engine::{EngineGoResult, EngineProtocol, EngineThinkingInfo};
+use crate::game::{GameManager, GameState, Move};
+
+/// 对方的威胁：假如轮到对方走子，引擎给出的着法和主变
+#[derive(Clone)]
+pub struct Threat {
+    /// 交换行棋方后的局面
+    pub state: GameState,
+    /// 威胁着法开始的主变
+    pub line: Vec<Move>,
+    /// 对方视角的分数，引擎没有给出时为 None
+    pub score: Option<isize>,
+}
+
+impl GameManager {
+    /// 对正在查看的局面做空着搜索，找出对方若能连走一步时的最佳着法
+    pub async fn threat(&mut self, think_time: usize) -> Result<Threat> {
+        let state: GameState = self.view_state().null_move()?;
+        if state.is_game_over() {
+            return Err(anyhow!("对方无着可走，没有威胁"));
+        }
+        let engine: &mut Box<dyn EngineProtocol> = self.engine()?;
+        engine.set_position(&state.to_fen()).await?;
+        let result: EngineGoResult = engine.go(Some(think_time)).await?;
+
+        let best: Move = Move::from_str(&result.best_move)?;
+        let info: Option<&EngineThinkingInfo> = result.main_infos().rev().find(|info| info.centipawns().is_some());
+        let line: Vec<Move> = match info.and_then(|info| info.pv.as_ref()) {
+            Some(pv) if pv.first() == Some(&result.best_move) => pv.iter().map(|mv| Move::from_str(mv)).collect::<Result<_>>()?,
+            _ => vec![best],
+        };
+        let score: Option<isize> = info.and_then(|info| info.centipawns());
+        Ok(Threat { state, line, score })
+    }
+}
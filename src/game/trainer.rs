@@ -0,0 +1,397 @@
+use crate::utils::*;
+use crate::game::{Book, GameManager, GameRecord, GameState, Move, MoveMark, PlayerColor, RecordMove, START_FEN};
+use std::collections::VecDeque;
+
+/// 一天的秒数
+const DAY: u64 = 24 * 60 * 60;
+/// 答错后再次复习的间隔（秒）
+const RELEARN_DELAY: u64 = 10 * 60;
+/// 每次训练最多引入的新局面数
+const MAX_NEW_CARDS: usize = 20;
+/// 遍历开局谱的最大深度（半回合）
+const MAX_DEPTH: usize = 60;
+
+/// 一个需要记住应着的局面的复习记录，使用 SM-2 算法安排
+#[derive(Debug, Clone)]
+pub struct Card {
+    /// 难度系数，不低于 1.3
+    ease: f64,
+    /// 复习间隔（天）
+    interval: f64,
+    /// 下次复习时间戳（秒），0 表示新局面
+    pub due: u64,
+    /// 连续答对次数
+    reps: u32,
+    /// 累计答错次数
+    pub lapses: u32,
+}
+
+impl Default for Card {
+    fn default() -> Self {
+        Self { ease: 2.5, interval: 0.0, due: 0, reps: 0, lapses: 0 }
+    }
+}
+
+impl Card {
+    fn is_new(&self) -> bool {
+        self.due == 0
+    }
+
+    /// 按回答质量（0-5）重新安排，3 以下视为答错
+    fn grade(&mut self, quality: u8, now: u64) {
+        let q: f64 = quality as f64;
+        self.ease = (self.ease + 0.1 - (5.0 - q) * (0.08 + (5.0 - q) * 0.02)).max(1.3);
+        if quality < 3 {
+            self.reps = 0;
+            self.lapses += 1;
+            self.interval = 0.0;
+            self.due = now + RELEARN_DELAY;
+            return;
+        }
+        self.reps += 1;
+        self.interval = match self.reps {
+            1 => 1.0,
+            2 => 6.0,
+            _ => self.interval * self.ease,
+        };
+        self.due = now + (self.interval * DAY as f64) as u64;
+    }
+}
+
+/// 训练中一次回答的结果
+#[derive(Debug, Clone)]
+pub enum Answer {
+    /// 答对，对方已按开局谱应着
+    Correct,
+    /// 答错，附准备的着法
+    Wrong(Vec<Move>),
+}
+
+/// 开局谱训练：程序按开局谱走对方的着法，玩家必须走出准备的应着
+pub struct Trainer {
+    book: Book,
+    pub color: PlayerColor,
+    /// 复习记录文件
+    schedule_path: PathBuf,
+    cards: HashMap<u64, Card>,
+    /// 从开局到各个需要应着的局面的着法
+    lines: HashMap<u64, Vec<Move>>,
+    /// 本次训练待复习的局面
+    queue: VecDeque<u64>,
+    /// 当前题目的局面键值和着法
+    current: Option<(u64, Vec<Move>)>,
+    /// 训练所用对局的会话编号，用于判断玩家是否换了对局
+    session_id: u64,
+    pub correct: usize,
+    pub wrong: usize,
+}
+
+/// 复习记录目录
+fn training_dir() -> Result<PathBuf> {
+    data_dir("training")
+}
+
+impl Trainer {
+    /// 读取开局谱和复习记录，安排本次训练的局面
+    pub fn start(path: &Path, color: PlayerColor) -> Result<Self> {
+        let book: Book = Book::load(path)?;
+        let (side, name) = match color {
+            PlayerColor::Red => ("red", "红"),
+            PlayerColor::Black => ("black", "黑"),
+        };
+        let schedule_path: PathBuf = training_dir()?.join(format!("{}-{}.toml", data_file_name(path), side));
+        let cards: HashMap<u64, Card> = if schedule_path.exists() {
+            Self::load_cards(&read_to_string(&schedule_path)?)?
+        } else {
+            HashMap::new()
+        };
+        let mut trainer: Trainer = Self {
+            book,
+            color,
+            schedule_path,
+            cards,
+            lines: HashMap::new(),
+            queue: VecDeque::new(),
+            current: None,
+            session_id: 0,
+            correct: 0,
+            wrong: 0,
+        };
+        trainer.collect_lines();
+        if trainer.lines.is_empty() {
+            return Err(anyhow!("开局谱中没有{}方需要应着的局面", name));
+        }
+        trainer.schedule();
+        Ok(trainer)
+    }
+
+    /// 玩家在该局面可以走的准备着法：有主着时只算主着，避免的着法不算
+    fn prepared(&self, state: &GameState) -> Vec<Move> {
+        let legal: Vec<Move> = state.legal_moves();
+        let moves: Vec<_> = self.book.probe(state.key()).iter()
+            .filter(|e| e.mark != Some(MoveMark::Avoid) && legal.contains(&e.mv))
+            .collect();
+        let main: bool = moves.iter().any(|e| e.mark == Some(MoveMark::Main));
+        moves.into_iter()
+            .filter(|e| !main || e.mark == Some(MoveMark::Main))
+            .map(|e| e.mv)
+            .collect()
+    }
+
+    /// 从开局局面按开局谱广度优先遍历，记录轮到玩家且有准备着法的局面及最短着法序列
+    fn collect_lines(&mut self) {
+        let mut visited: hashbrown::HashSet<u64> = hashbrown::HashSet::new();
+        let mut pending: VecDeque<(GameState, Vec<Move>)> = VecDeque::from([(GameState::new(), Vec::new())]);
+        while let Some((state, line)) = pending.pop_front() {
+            if !visited.insert(state.key()) || line.len() >= MAX_DEPTH {
+                continue;
+            }
+            let moves: Vec<Move> = if state.current_player == self.color {
+                let prepared: Vec<Move> = self.prepared(&state);
+                if !prepared.is_empty() {
+                    self.lines.insert(state.key(), line.clone());
+                }
+                prepared
+            } else {
+                let legal: Vec<Move> = state.legal_moves();
+                self.book.probe(state.key()).iter()
+                    .filter(|e| e.mark != Some(MoveMark::Avoid) && legal.contains(&e.mv))
+                    .map(|e| e.mv)
+                    .collect()
+            };
+            for mv in moves {
+                let mut next: GameState = state.clone();
+                if next.apply_move(&mv.to_string()).is_ok() {
+                    let mut next_line: Vec<Move> = line.clone();
+                    next_line.push(mv);
+                    pending.push_back((next, next_line));
+                }
+            }
+        }
+    }
+
+    /// 到期的局面按到期时间排在前面，之后是有限数量的新局面（按着法深度）
+    fn schedule(&mut self) {
+        let now: u64 = unix_time();
+        let mut due: Vec<(u64, u64)> = Vec::new();
+        let mut new: Vec<(usize, u64)> = Vec::new();
+        for (&key, line) in &self.lines {
+            match self.cards.get(&key) {
+                Some(card) if !card.is_new() => {
+                    if card.due <= now {
+                        due.push((card.due, key));
+                    }
+                }
+                _ => new.push((line.len(), key)),
+            }
+        }
+        due.sort_unstable();
+        new.sort_unstable();
+        self.queue = due.into_iter().map(|(_, key)| key)
+            .chain(new.into_iter().take(MAX_NEW_CARDS).map(|(_, key)| key))
+            .collect();
+    }
+
+    /// 剩余题目数
+    pub fn remaining(&self) -> usize {
+        self.queue.len() + self.current.is_some() as usize
+    }
+
+    /// 全部局面中最早的下次复习时间
+    pub fn next_due(&self) -> Option<u64> {
+        self.lines.keys().filter_map(|key| self.cards.get(key)).map(|card| card.due).filter(|&due| due > 0).min()
+    }
+
+    /// game 是否为训练中的对局
+    pub fn owns(&self, game: &GameManager) -> bool {
+        self.current.is_some() && game.session_id == self.session_id
+    }
+
+    /// 出下一题，返回走到题目局面的对局；没有题目时返回 None
+    pub fn next(&mut self, player_name: &str) -> Result<Option<GameManager>> {
+        let Some(key) = self.queue.pop_front() else {
+            self.current = None;
+            return Ok(None);
+        };
+        let line: Vec<Move> = self.lines[&key].clone();
+        self.present(key, line, player_name).map(Some)
+    }
+
+    /// 走到题目局面
+    fn present(&mut self, key: u64, line: Vec<Move>, player_name: &str) -> Result<GameManager> {
+        let mut record: GameRecord = GameRecord::new(START_FEN);
+        record.moves = line.iter().map(|&mv| RecordMove::new(mv)).collect();
+        let mut game: GameManager = GameManager::from_record(&record, player_name)?;
+        game.player_color = Some(self.color);
+        game.state.flipped = self.color == PlayerColor::Black;
+        self.session_id = game.session_id;
+        self.current = Some((key, line));
+        Ok(game)
+    }
+
+    /// 检查玩家的着法并安排复习；答对时对方按开局谱应着，若到达待复习的局面则直接继续这一路线
+    /// 返回回答结果和下一题的对局（训练结束时为 None）
+    pub fn answer(&mut self, game: &GameManager, move_str: &str) -> Result<(Answer, Option<GameManager>)> {
+        let (key, line) = self.current.clone().ok_or_else(|| anyhow!("没有正在进行的训练题目"))?;
+        let state: &GameState = game.view_state();
+        let mv: Move = state.parse_notation(move_str)?;
+        let prepared: Vec<Move> = self.prepared(state);
+        let now: u64 = unix_time();
+        let card: &mut Card = self.cards.entry(key).or_default();
+
+        if !prepared.contains(&mv) {
+            card.grade(1, now);
+            self.wrong += 1;
+            // 本次训练稍后再考一次
+            self.queue.push_back(key);
+            self.save()?;
+            let game: Option<GameManager> = self.next(&game.player_name)?;
+            return Ok((Answer::Wrong(prepared), game));
+        }
+
+        card.grade(5, now);
+        self.correct += 1;
+        self.save()?;
+
+        // 对方应着后若到达待复习的局面，沿当前路线继续
+        let mut next_state: GameState = state.clone();
+        next_state.apply_move(&mv.to_string())?;
+        if let Some(reply) = self.book.choose(&next_state) {
+            next_state.apply_move(&reply.to_string())?;
+            let next_key: u64 = next_state.key();
+            if let Some(index) = self.queue.iter().position(|&k| k == next_key) {
+                self.queue.remove(index);
+                let mut next_line: Vec<Move> = line;
+                next_line.extend([mv, reply]);
+                let game: GameManager = self.present(next_key, next_line, &game.player_name)?;
+                return Ok((Answer::Correct, Some(game)));
+            }
+        }
+        let game: Option<GameManager> = self.next(&game.player_name)?;
+        Ok((Answer::Correct, game))
+    }
+
+    fn load_cards(text: &str) -> Result<HashMap<u64, Card>> {
+        let table: toml::Table = toml::from_str(text)?;
+        let mut cards: HashMap<u64, Card> = HashMap::new();
+        for (key, value) in table.get("cards").and_then(|v| v.as_table()).into_iter().flatten() {
+            let Ok(key) = u64::from_str_radix(key, 16) else { continue };
+            let float = |name: &str| value.get(name).and_then(|v| v.as_float());
+            let integer = |name: &str| value.get(name).and_then(|v| v.as_integer()).unwrap_or(0);
+            cards.insert(key, Card {
+                ease: float("ease").unwrap_or(2.5),
+                interval: float("interval").unwrap_or(0.0),
+                due: integer("due") as u64,
+                reps: integer("reps") as u32,
+                lapses: integer("lapses") as u32,
+            });
+        }
+        Ok(cards)
+    }
+
+    /// 保存复习记录，先写临时文件再改名
+    fn save(&self) -> Result<()> {
+        let mut cards: toml::Table = toml::Table::new();
+        for (key, card) in &self.cards {
+            let mut entry: toml::Table = toml::Table::new();
+            entry.insert("ease".to_string(), toml::Value::Float(card.ease));
+            entry.insert("interval".to_string(), toml::Value::Float(card.interval));
+            entry.insert("due".to_string(), toml::Value::Integer(card.due as i64));
+            entry.insert("reps".to_string(), toml::Value::Integer(card.reps as i64));
+            entry.insert("lapses".to_string(), toml::Value::Integer(card.lapses as i64));
+            cards.insert(format!("{:016x}", key), toml::Value::Table(entry));
+        }
+        let mut table: toml::Table = toml::Table::new();
+        table.insert("cards".to_string(), toml::Value::Table(cards));
+        write_atomic(&self.schedule_path, table.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mv(s: &str) -> Move {
+        Move::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn grade_intervals() {
+        let mut card: Card = Card::default();
+        assert!(card.is_new());
+        card.grade(5, 0);
+        assert_eq!((card.interval, card.due), (1.0, DAY));
+        card.grade(5, 0);
+        assert_eq!((card.interval, card.due), (6.0, 6 * DAY));
+        // 第三次起按难度系数放大，答得好时系数上升
+        let ease: f64 = card.ease;
+        assert!(ease > 2.5);
+        card.grade(4, 0);
+        assert!((card.interval - 6.0 * card.ease).abs() < 1e-9);
+        assert!((card.ease - ease).abs() < 1e-9);
+
+        // 答错后重新学习，间隔清零
+        card.grade(1, 100);
+        assert_eq!((card.reps, card.lapses, card.interval, card.due), (0, 1, 0.0, 100 + RELEARN_DELAY));
+        card.grade(5, 0);
+        assert_eq!(card.interval, 1.0);
+
+        // 难度系数不低于 1.3
+        for _ in 0..20 {
+            card.grade(0, 0);
+        }
+        assert_eq!(card.ease, 1.3);
+    }
+
+    fn trainer(color: PlayerColor) -> Trainer {
+        let mut book: Book = Book::default();
+        let mut state: GameState = GameState::new();
+        book.insert(&state, mv("h2e2"), 10);
+        state.apply_move("h2e2").unwrap();
+        book.insert(&state, mv("h9g7"), 10);
+        book.insert(&state, mv("b9c7"), 10);
+        book.update(state.key(), mv("b9c7"), |e| e.mark = Some(MoveMark::Avoid)).unwrap();
+        state.apply_move("h9g7").unwrap();
+        book.insert(&state, mv("h0g2"), 10);
+        book.insert(&state, mv("b0c2"), 10);
+        book.update(state.key(), mv("h0g2"), |e| e.mark = Some(MoveMark::Main)).unwrap();
+
+        let mut trainer: Trainer = Trainer {
+            book,
+            color,
+            schedule_path: PathBuf::new(),
+            cards: HashMap::new(),
+            lines: HashMap::new(),
+            queue: VecDeque::new(),
+            current: None,
+            session_id: 0,
+            correct: 0,
+            wrong: 0,
+        };
+        trainer.collect_lines();
+        trainer
+    }
+
+    /// 收录的局面的着法序列，按长度排序
+    fn lines(trainer: &Trainer) -> Vec<Vec<Move>> {
+        let mut lines: Vec<Vec<Move>> = trainer.lines.values().cloned().collect();
+        lines.sort_by_key(|line| line.len());
+        lines
+    }
+
+    #[test]
+    fn collect_lines_follows_prepared_moves() {
+        // 红方：开局走炮二平五，对方只走未避免的马8进7，之后只考主着
+        let red: Trainer = trainer(PlayerColor::Red);
+        assert_eq!(lines(&red), [vec![], vec![mv("h2e2"), mv("h9g7")]]);
+        let mut state: GameState = GameState::new();
+        for m in ["h2e2", "h9g7"] {
+            state.apply_move(m).unwrap();
+        }
+        assert_eq!(red.prepared(&state), [mv("h0g2")]);
+
+        // 黑方：只有炮二平五之后需要应着，避免的马2进3不算
+        let black: Trainer = trainer(PlayerColor::Black);
+        assert_eq!(lines(&black), [vec![mv("h2e2")]]);
+    }
+}
@@ -0,0 +1,318 @@
+use crate::utils::*;
+use crate::game::{GameRecord, Move, RecordMove};
+
+/// 根节点编号，对应起始局面
+pub const ROOT: usize = 0;
+
+/// 着法评注符号，对应 PGN 数字注释 $1-$6
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Glyph {
+    /// 好棋 !
+    Good,
+    /// 错着 ?
+    Mistake,
+    /// 妙着 !!
+    Brilliant,
+    /// 败着 ??
+    Blunder,
+    /// 有趣的着法 !?
+    Interesting,
+    /// 值得怀疑的着法 ?!
+    Dubious,
+}
+
+impl Glyph {
+    const ALL: [Glyph; 6] = [
+        Glyph::Good, Glyph::Mistake, Glyph::Brilliant,
+        Glyph::Blunder, Glyph::Interesting, Glyph::Dubious,
+    ];
+
+    /// 评注符号
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Glyph::Good => "!",
+            Glyph::Mistake => "?",
+            Glyph::Brilliant => "!!",
+            Glyph::Blunder => "??",
+            Glyph::Interesting => "!?",
+            Glyph::Dubious => "?!",
+        }
+    }
+
+    /// PGN 数字注释编号
+    pub fn nag(&self) -> u8 {
+        Self::ALL.iter().position(|g| g == self).unwrap() as u8 + 1
+    }
+
+    /// 由 PGN 数字注释编号转换，不支持的编号返回 None
+    pub fn from_nag(nag: u8) -> Option<Self> {
+        Self::ALL.get((nag as usize).checked_sub(1)?).copied()
+    }
+}
+
+impl FromStr for Glyph {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL.into_iter()
+            .find(|g| g.symbol() == s)
+            .ok_or_else(|| anyhow!("无效的评注符号: {}，使用 ! ? !! ?? !? ?!", s))
+    }
+}
+
+/// 着法树节点
+#[derive(Debug, Clone)]
+pub struct MoveNode {
+    /// 所走着法，根节点为 None
+    pub mv: Option<Move>,
+    /// 父节点，根节点指向自身
+    pub parent: usize,
+    /// 后续着法，第一个为主变
+    pub children: Vec<usize>,
+    /// 着法后的注释，根节点为开局前的注释
+    pub comment: Option<String>,
+    /// 评注符号
+    pub glyph: Option<Glyph>,
+    /// 着法后局面的红方视角分数，来自引擎走子和分析，不写入棋谱
+    pub eval: Option<isize>,
+}
+
+/// 着法树，保存主线和全部变着；删除的分支只从父节点摘除，不回收
+#[derive(Debug, Clone)]
+pub struct MoveTree {
+    nodes: Vec<MoveNode>,
+}
+
+impl Default for MoveTree {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MoveTree {
+    /// 只有根节点的空树
+    pub fn new() -> Self {
+        Self {
+            nodes: vec![MoveNode { mv: None, parent: ROOT, children: Vec::new(), comment: None, glyph: None, eval: None }],
+        }
+    }
+
+    /// 由棋谱记录构建
+    pub fn from_record(record: &GameRecord) -> Self {
+        let mut tree: MoveTree = Self::new();
+        tree.nodes[ROOT].comment = record.comment.clone();
+        tree.insert_line(ROOT, &record.moves);
+        tree
+    }
+
+    fn insert_line(&mut self, parent: usize, moves: &[RecordMove]) {
+        let mut parent: usize = parent;
+        for record_move in moves {
+            let id: usize = self.add(parent, record_move.mv);
+            self.nodes[id].comment = record_move.comment.clone();
+            self.nodes[id].glyph = record_move.glyph;
+            for variation in &record_move.variations {
+                self.insert_line(parent, variation);
+            }
+            parent = id;
+        }
+    }
+
+    /// 导出主线着法，其余分支作为变着
+    pub fn record_moves(&self) -> Vec<RecordMove> {
+        self.nodes[ROOT].children.first().map_or_else(Vec::new, |&first| self.record_line(first, true))
+    }
+
+    /// 从 first 开始沿主变导出一串着法；with_siblings 表示第一步需要带上其兄弟分支
+    fn record_line(&self, first: usize, with_siblings: bool) -> Vec<RecordMove> {
+        let mut line: Vec<RecordMove> = Vec::new();
+        let mut current: Option<usize> = Some(first);
+        let mut with_siblings: bool = with_siblings;
+        while let Some(id) = current {
+            let node: &MoveNode = &self.nodes[id];
+            let mut record_move: RecordMove = RecordMove::new(node.mv.unwrap());
+            record_move.comment = node.comment.clone();
+            record_move.glyph = node.glyph;
+            if with_siblings {
+                record_move.variations = self.nodes[node.parent].children[1..].iter()
+                    .map(|&sibling| self.record_line(sibling, false))
+                    .collect();
+            }
+            line.push(record_move);
+            current = node.children.first().copied();
+            with_siblings = true;
+        }
+        line
+    }
+
+    /// 读取节点
+    pub fn node(&self, id: usize) -> &MoveNode {
+        &self.nodes[id]
+    }
+
+    /// 修改节点
+    pub fn node_mut(&mut self, id: usize) -> &mut MoveNode {
+        &mut self.nodes[id]
+    }
+
+    /// 在 parent 之后添加着法，已有相同着法时返回已有节点
+    pub fn add(&mut self, parent: usize, mv: Move) -> usize {
+        if let Some(&existing) = self.nodes[parent].children.iter().find(|&&c| self.nodes[c].mv == Some(mv)) {
+            return existing;
+        }
+        let id: usize = self.nodes.len();
+        self.nodes.push(MoveNode { mv: Some(mv), parent, children: Vec::new(), comment: None, glyph: None, eval: None });
+        self.nodes[parent].children.push(id);
+        id
+    }
+
+    /// 从第一步到 id 的节点路径，不含根节点
+    pub fn path(&self, id: usize) -> Vec<usize> {
+        let mut path: Vec<usize> = Vec::new();
+        let mut id: usize = id;
+        while id != ROOT {
+            path.push(id);
+            id = self.nodes[id].parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// id 所在的半回合数
+    pub fn depth(&self, id: usize) -> usize {
+        self.path(id).len()
+    }
+
+    /// 从 id 沿主变走到底的节点
+    pub fn main_leaf(&self, id: usize) -> usize {
+        let mut id: usize = id;
+        while let Some(&child) = self.nodes[id].children.first() {
+            id = child;
+        }
+        id
+    }
+
+    /// 与 id 同一位置的全部候选着法（含自身），第一个为主变
+    pub fn alternatives(&self, id: usize) -> &[usize] {
+        if id == ROOT {
+            return &[];
+        }
+        &self.nodes[self.nodes[id].parent].children
+    }
+
+    /// id 是否在主线上
+    pub fn is_main_line(&self, id: usize) -> bool {
+        self.path(id).iter().all(|&n| self.nodes[self.nodes[n].parent].children[0] == n)
+    }
+
+    /// 把 id 所在的变着提升为主线
+    pub fn promote(&mut self, id: usize) {
+        for node in self.path(id) {
+            let parent: usize = self.nodes[node].parent;
+            let children: &mut Vec<usize> = &mut self.nodes[parent].children;
+            let index: usize = children.iter().position(|&c| c == node).unwrap();
+            children[..=index].rotate_right(1);
+        }
+    }
+
+    /// 删除 id 及其后续全部着法，返回父节点
+    pub fn remove(&mut self, id: usize) -> Result<usize> {
+        if id == ROOT {
+            return Err(anyhow!("不能删除起始局面"));
+        }
+        let parent: usize = self.nodes[id].parent;
+        self.nodes[parent].children.retain(|&c| c != id);
+        Ok(parent)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::{MoveFormat, START_FEN, parse_pgn};
+
+    fn mv(s: &str) -> Move {
+        Move::from_str(s).unwrap()
+    }
+
+    /// 主线 h2e2 h9g7，第一步另有变着 b2e2
+    fn sample() -> (MoveTree, [usize; 3]) {
+        let mut tree: MoveTree = MoveTree::new();
+        let main: usize = tree.add(ROOT, mv("h2e2"));
+        let reply: usize = tree.add(main, mv("h9g7"));
+        let side: usize = tree.add(ROOT, mv("b2e2"));
+        (tree, [main, reply, side])
+    }
+
+    #[test]
+    fn add_reuses_existing_move() {
+        let (mut tree, [main, reply, side]) = sample();
+        assert_eq!(tree.add(ROOT, mv("h2e2")), main);
+        assert_eq!(tree.add(main, mv("h9g7")), reply);
+        assert_eq!(tree.add(ROOT, mv("b2e2")), side);
+        assert_eq!(tree.alternatives(main), &[main, side]);
+        assert_eq!(tree.node(main).children, vec![reply]);
+    }
+
+    #[test]
+    fn promote_variation() {
+        let (mut tree, [main, reply, side]) = sample();
+        let deep: usize = tree.add(side, mv("b9c7"));
+        assert!(!tree.is_main_line(deep));
+        tree.promote(deep);
+        assert!(tree.is_main_line(deep));
+        assert!(!tree.is_main_line(reply));
+        assert_eq!(tree.alternatives(side), &[side, main]);
+        assert_eq!(tree.main_leaf(ROOT), deep);
+    }
+
+    #[test]
+    fn remove_branch() {
+        let (mut tree, [main, reply, side]) = sample();
+        assert!(tree.remove(ROOT).is_err());
+        assert_eq!(tree.remove(main).unwrap(), ROOT);
+        assert_eq!(tree.alternatives(side), &[side]);
+        assert!(tree.is_main_line(side));
+        // 删除的节点不再导出
+        let moves: Vec<RecordMove> = tree.record_moves();
+        assert_eq!(moves.len(), 1);
+        assert!(moves[0].variations.is_empty());
+        assert_eq!(tree.remove(reply).unwrap(), main);
+    }
+
+    #[test]
+    fn glyphs_in_nested_variations_round_trip() {
+        let (mut tree, [main, reply, side]) = sample();
+        let nested: usize = tree.add(side, mv("h9g7"));
+        let inner: usize = tree.add(side, mv("b9c7"));
+        tree.node_mut(main).glyph = Some(Glyph::Good);
+        tree.node_mut(reply).comment = Some("屏风马".to_string());
+        tree.node_mut(side).glyph = Some(Glyph::Dubious);
+        tree.node_mut(inner).glyph = Some(Glyph::Blunder);
+        tree.node_mut(inner).comment = Some("嵌套变着".to_string());
+
+        let mut record: GameRecord = GameRecord::new(START_FEN);
+        record.moves = tree.record_moves();
+        let pgn: String = record.to_pgn(MoveFormat::Iccs).unwrap();
+        let parsed: GameRecord = parse_pgn(&pgn).unwrap().remove(0);
+        let copy: MoveTree = MoveTree::from_record(&parsed);
+
+        let main_copy: usize = copy.node(ROOT).children[0];
+        let side_copy: usize = copy.node(ROOT).children[1];
+        assert_eq!(copy.node(main_copy).glyph, Some(Glyph::Good));
+        assert_eq!(copy.node(copy.node(main_copy).children[0]).comment.as_deref(), Some("屏风马"));
+        assert_eq!(copy.node(side_copy).glyph, Some(Glyph::Dubious));
+        let children: &[usize] = &copy.node(side_copy).children;
+        assert_eq!(children.len(), 2);
+        assert_eq!(copy.node(children[0]).mv, tree.node(nested).mv);
+        assert_eq!(copy.node(children[1]).glyph, Some(Glyph::Blunder));
+        assert_eq!(copy.node(children[1]).comment.as_deref(), Some("嵌套变着"));
+
+        // 显示用的着法文本直接写出评注符号
+        let movetext: String = record.movetext(MoveFormat::Chinese).unwrap();
+        assert!(movetext.contains("炮二平五!"), "{}", movetext);
+        assert!(movetext.contains("炮八平五?!"), "{}", movetext);
+        assert!(movetext.contains("??"), "{}", movetext);
+    }
+}
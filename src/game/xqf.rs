@@ -0,0 +1,384 @@
+use crate::utils::*;
+use crate::game::{Board, GameRecord, GameResult, GameState, Move, Piece, PieceKind, PlayerColor, Position, RecordMove};
+
+/// 文件头长度，着法记录从此处开始
+const HEADER_SIZE: usize = 1024;
+
+/// 着法记录加密流的密钥字符串
+const ENC_STREAM_KEY: &[u8; 32] = b"[(C) Copyright Mr. Dong Shiwei.]";
+
+/// 棋子位置表中32个棋子的顺序：红方车马相仕帅仕相马车炮炮兵兵兵兵兵，黑方相同
+const PIECE_ORDER: [PieceKind; 16] = [
+    PieceKind::Rook, PieceKind::Horse, PieceKind::Elephant, PieceKind::Advisor,
+    PieceKind::General, PieceKind::Advisor, PieceKind::Elephant, PieceKind::Horse,
+    PieceKind::Rook, PieceKind::Cannon, PieceKind::Cannon, PieceKind::Pawn,
+    PieceKind::Pawn, PieceKind::Pawn, PieceKind::Pawn, PieceKind::Pawn,
+];
+
+/// 文件头中的文本字段：(PGN标签, 偏移, 长度)
+const TEXT_FIELDS: [(&str, usize, usize); 8] = [
+    ("Title", 80, 64),
+    ("Event", 208, 64),
+    ("Date", 272, 16),
+    ("Site", 288, 16),
+    ("Red", 304, 16),
+    ("Black", 320, 16),
+    ("TimeControl", 336, 64),
+    ("Annotator", 464, 16),
+];
+
+/// 密钥生成函数
+fn square54_plus221(x: u32) -> u32 {
+    x * x * 54 + 221
+}
+
+/// 解密参数，版本11以下全部为0即不加密
+struct Keys {
+    xy: u32,
+    xyf: u32,
+    xyt: u32,
+    rmk_size: u32,
+    stream: [u8; 32],
+}
+
+impl Keys {
+    fn from_header(header: &[u8]) -> Self {
+        let version: u8 = header[2];
+        if version < 11 {
+            return Self { xy: 0, xyf: 0, xyt: 0, rmk_size: 0, stream: [0; 32] };
+        }
+        let tag = |i: usize| -> u32 { header[i] as u32 };
+        let xy: u32 = square54_plus221(tag(13)).wrapping_mul(tag(13));
+        let xyf: u32 = square54_plus221(tag(14)).wrapping_mul(xy);
+        let xyt: u32 = square54_plus221(tag(15)).wrapping_mul(xyf);
+        let rmk_size: u32 = (tag(12) * 256 + tag(13)) % 32000 + 767;
+        let mask: u8 = header[3];
+        let key_bytes: [u8; 4] = [
+            (header[12] & mask) | header[8],
+            (header[13] & mask) | header[9],
+            (header[14] & mask) | header[10],
+            (header[15] & mask) | header[11],
+        ];
+        let mut stream: [u8; 32] = [0; 32];
+        for (i, byte) in stream.iter_mut().enumerate() {
+            *byte = ENC_STREAM_KEY[i] & key_bytes[i % 4];
+        }
+        Self { xy, xyf, xyt, rmk_size, stream }
+    }
+}
+
+/// 着法记录读取器，逐字节解密
+struct RecordReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    enc_index: usize,
+    version: u8,
+    keys: Keys,
+}
+
+/// 一条着法记录
+struct RawRecord {
+    from: u8,
+    to: u8,
+    has_next: bool,
+    has_variation: bool,
+    comment: Option<String>,
+}
+
+impl RecordReader<'_> {
+    fn read(&mut self, len: usize) -> Result<Vec<u8>> {
+        let bytes: &[u8] = self.data.get(self.offset..self.offset + len)
+            .ok_or_else(|| anyhow!("XQF文件意外结束"))?;
+        self.offset += len;
+        Ok(bytes.iter()
+            .map(|&b| {
+                let plain: u8 = b.wrapping_sub(self.keys.stream[self.enc_index]);
+                self.enc_index = (self.enc_index + 1) % 32;
+                plain
+            })
+            .collect())
+    }
+
+    fn read_i32(&mut self) -> Result<i32> {
+        let bytes: Vec<u8> = self.read(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn next_record(&mut self) -> Result<RawRecord> {
+        let bytes: Vec<u8> = self.read(4)?;
+        let tag: u8 = bytes[2];
+        let (has_next, has_variation, comment_len) = if self.version < 11 {
+            (tag & 0xF0 != 0, tag & 0x0F != 0, self.read_i32()?)
+        } else {
+            let len: i32 = if tag & 0x20 != 0 {
+                self.read_i32()?.wrapping_sub(self.keys.rmk_size as i32)
+            } else {
+                0
+            };
+            (tag & 0x80 != 0, tag & 0x40 != 0, len)
+        };
+        let comment: Option<String> = if comment_len > 0 {
+            let text: String = decode_gbk(&self.read(comment_len as usize)?);
+            Some(text.trim().to_string()).filter(|s| !s.is_empty())
+        } else {
+            None
+        };
+        Ok(RawRecord {
+            from: bytes[0].wrapping_sub(24).wrapping_sub(self.keys.xyf as u8),
+            to: bytes[1].wrapping_sub(32).wrapping_sub(self.keys.xyt as u8),
+            has_next,
+            has_variation,
+            comment,
+        })
+    }
+
+    /// 读取从 state 开始的着法树，返回带变着的主线。
+    /// 记录按先序排列：每步之后先是其后续着法，再是同一位置的下一个候选着法。
+    /// 用显式栈代替递归，超长棋谱也不会栈溢出
+    fn read_tree(&mut self, state: &GameState) -> Result<Vec<RecordMove>> {
+        // 正在读取的着法序列及其末尾局面，第一个为主线，其余为未读完的变着
+        let mut lines: Vec<(Vec<RecordMove>, GameState)> = vec![(Vec::new(), state.snapshot())];
+        let mut groups: Vec<Alternatives> = vec![Alternatives::new(0, 0, 1)];
+        loop {
+            let group: &mut Alternatives = groups.last_mut().unwrap();
+            let raw: RawRecord = self.next_record()?;
+            let mv: Move = Move::new(square_to_position(raw.from)?, square_to_position(raw.to)?);
+
+            // 第一个候选接在所在序列之后，其余候选各自另起一条变着
+            if group.count > 0 {
+                lines.push((Vec::new(), group.state.take().unwrap()));
+            }
+            group.count += 1;
+            group.has_more = raw.has_variation;
+            let depth: usize = lines.len() - 1;
+            let (line, state) = &mut lines[depth];
+            group.state = raw.has_variation.then(|| state.snapshot());
+            state.play_move(mv)
+                .with_context(|| format!("第{}步着法无效", group.ply))?;
+            line.push(RecordMove { comment: raw.comment, ..RecordMove::new(mv) });
+            if raw.has_next {
+                let next: Alternatives = Alternatives::new(depth, line.len(), group.ply + 1);
+                groups.push(next);
+                continue;
+            }
+
+            // 当前候选已读完，变着挂到该位置第一个候选上；没有更多候选时回到上一层
+            while let Some(group) = groups.last() {
+                if group.count > 1 {
+                    let (variation, _) = lines.pop().unwrap();
+                    lines[group.line].0[group.index].variations.push(variation);
+                }
+                if group.has_more {
+                    break;
+                }
+                groups.pop();
+            }
+            if groups.is_empty() {
+                return Ok(lines.pop().unwrap().0);
+            }
+        }
+    }
+}
+
+/// 同一位置的候选着法
+struct Alternatives {
+    /// 第一个候选所在的序列及其在序列中的位置
+    line: usize,
+    index: usize,
+    /// 候选着法的序号（从1开始）
+    ply: usize,
+    /// 已读取的候选数
+    count: usize,
+    /// 最后一个候选之后是否还有候选
+    has_more: bool,
+    /// 还有候选时保存候选之前的局面
+    state: Option<GameState>,
+}
+
+impl Alternatives {
+    fn new(line: usize, index: usize, ply: usize) -> Self {
+        Self { line, index, ply, count: 0, has_more: false, state: None }
+    }
+}
+
+/// XQF 格子编号：十位为列（0-8从左到右），个位为行（0为红方底线）
+fn square_to_position(sq: u8) -> Result<Position> {
+    if sq >= 90 {
+        return Err(anyhow!("XQF着法坐标无效: {}", sq));
+    }
+    Ok(Position { row: sq as usize % 10, col: sq as usize / 10 })
+}
+
+/// 以GBK解码文本，XQF文件均使用GBK编码
+fn decode_gbk(bytes: &[u8]) -> String {
+    let (text, _, _) = encoding_rs::GBK.decode(bytes);
+    text.trim_end_matches('\0').to_string()
+}
+
+/// 读取文件头中的 Pascal 字符串（首字节为长度）
+fn read_text(header: &[u8], offset: usize, size: usize) -> String {
+    let len: usize = (header[offset] as usize).min(size - 1);
+    decode_gbk(&header[offset + 1..offset + 1 + len]).trim().to_string()
+}
+
+/// 解析 XQF 棋谱文件
+pub fn parse_xqf(data: &[u8]) -> Result<GameRecord> {
+    if data.len() < HEADER_SIZE || &data[0..2] != b"XQ" {
+        return Err(anyhow!("不是有效的XQF文件"));
+    }
+    let header: &[u8] = &data[..HEADER_SIZE];
+    let version: u8 = header[2];
+    let keys: Keys = Keys::from_header(header);
+
+    // 棋子位置，版本12起位置表按密钥循环移位
+    let mut positions: [u8; 32] = [0; 32];
+    for (i, &raw) in header[16..48].iter().enumerate() {
+        let idx: usize = if version >= 12 { (i + keys.xy as usize + 1) % 32 } else { i };
+        positions[idx] = raw;
+    }
+    let mut board: Board = Board::empty();
+    for (i, &raw) in positions.iter().enumerate() {
+        let sq: u8 = raw.wrapping_sub(keys.xy as u8);
+        if sq >= 90 {
+            continue;
+        }
+        let color: PlayerColor = if i < 16 { PlayerColor::Red } else { PlayerColor::Black };
+        let piece: Piece = Piece { color, kind: PIECE_ORDER[i % 16], hidden: false };
+        board.put(square_to_position(sq)?, piece)?;
+    }
+
+    let mut reader: RecordReader<'_> = RecordReader { data, offset: HEADER_SIZE, enc_index: 0, version, keys };
+
+    // 第一条记录不含着法，仅携带开局注释
+    let root: RawRecord = reader.next_record()?;
+
+    // 首步着法的棋子决定先走方
+    let mut first_mover: PlayerColor = PlayerColor::Red;
+    if root.has_next {
+        let save: (usize, usize) = (reader.offset, reader.enc_index);
+        let first: RawRecord = reader.next_record()?;
+        if let Some(piece) = board.get(square_to_position(first.from)?.square()) {
+            first_mover = piece.color;
+        }
+        (reader.offset, reader.enc_index) = save;
+    }
+    let state: GameState = GameState::from_board(board, first_mover);
+    state.validate_setup().context("XQF初始局面不合法")?;
+
+    let mut record: GameRecord = GameRecord::new(&state.to_fen());
+    record.comment = root.comment;
+    for (tag, offset, size) in TEXT_FIELDS {
+        let value: String = read_text(header, offset, size);
+        if !value.is_empty() {
+            record.set_tag(tag, &value);
+        }
+    }
+    let result: GameResult = match header[51] {
+        1 => GameResult::RedWin,
+        2 => GameResult::BlackWin,
+        3 => GameResult::Draw,
+        _ => GameResult::Unknown,
+    };
+    record.set_tag("Result", result.as_str());
+
+    if root.has_next {
+        record.moves = reader.read_tree(&state)?;
+    }
+    Ok(record)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game::START_FEN;
+
+    /// 标准开局32个棋子的XQF格子编号，顺序同 PIECE_ORDER
+    const START_SQUARES: [u8; 32] = [
+        0, 10, 20, 30, 40, 50, 60, 70, 80, 12, 72, 3, 23, 43, 63, 83,
+        9, 19, 29, 39, 49, 59, 69, 79, 89, 17, 77, 6, 26, 46, 66, 86,
+    ];
+
+    /// 着法记录：起点、终点、是否有后续、是否有下一个候选、注释
+    type Record<'a> = (u8, u8, bool, bool, &'a str);
+
+    /// 生成未加密的版本10 XQF文件，第一条记录为根记录
+    fn build(squares: [u8; 32], red: &str, records: &[Record<'_>]) -> Vec<u8> {
+        let mut data: Vec<u8> = vec![0; HEADER_SIZE];
+        data[0..2].copy_from_slice(b"XQ");
+        data[2] = 10;
+        data[16..48].copy_from_slice(&squares);
+        data[51] = 1;
+        data[304] = red.len() as u8;
+        data[305..305 + red.len()].copy_from_slice(red.as_bytes());
+        for &(from, to, has_next, has_variation, comment) in records {
+            let tag: u8 = if has_next { 0xF0 } else { 0 } | if has_variation { 0x0F } else { 0 };
+            data.extend([from + 24, to + 32, tag, 0]);
+            let (comment, _, _) = encoding_rs::GBK.encode(comment);
+            data.extend((comment.len() as i32).to_le_bytes());
+            data.extend(comment.iter());
+        }
+        data
+    }
+
+    #[test]
+    fn parses_moves_variations_and_comments() {
+        // 1. 炮二平五 {中炮} (1. 炮八平五) 1... 马8进7
+        let data: Vec<u8> = build(START_SQUARES, "Red", &[
+            (0, 0, true, false, "开局"),
+            (72, 42, true, true, "中炮"),
+            (79, 67, false, false, ""),
+            (12, 42, false, false, ""),
+        ]);
+        let record: GameRecord = parse_xqf(&data).unwrap();
+        assert_eq!(record.start_fen, START_FEN);
+        assert_eq!(record.tag("Red"), Some("Red"));
+        assert_eq!(record.result(), GameResult::RedWin);
+        assert_eq!(record.comment.as_deref(), Some("开局"));
+        let moves: Vec<String> = record.moves.iter().map(|m| m.mv.to_string()).collect();
+        assert_eq!(moves, ["h2e2", "h9g7"]);
+        assert_eq!(record.moves[0].comment.as_deref(), Some("中炮"));
+        assert_eq!(record.moves[0].variations.len(), 1);
+        assert_eq!(record.moves[0].variations[0][0].mv.to_string(), "b2e2");
+    }
+
+    #[test]
+    fn long_game_does_not_overflow_the_stack() {
+        // 双方车来回走动
+        let mut squares: [u8; 32] = [0xFF; 32];
+        squares[0] = 0;
+        squares[4] = 30;
+        squares[16] = 89;
+        squares[20] = 59;
+        let mut records: Vec<Record<'_>> = vec![(0, 0, true, false, "")];
+        const PLIES: usize = 10_000;
+        for i in 0..PLIES {
+            let (from, to) = [(0, 1), (89, 88), (1, 0), (88, 89)][i % 4];
+            records.push((from, to, i + 1 < PLIES, false, ""));
+        }
+        let data: Vec<u8> = build(squares, "", &records);
+        let record: GameRecord = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || parse_xqf(&data).unwrap())
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(record.moves.len(), PLIES);
+    }
+
+    #[test]
+    fn malformed_files_are_rejected() {
+        let valid: Vec<u8> = build(START_SQUARES, "", &[(0, 0, true, false, ""), (72, 42, false, false, "")]);
+        assert!(parse_xqf(&valid).is_ok());
+
+        let mut bad_magic: Vec<u8> = valid.clone();
+        bad_magic[0] = b'X';
+        bad_magic[1] = b'X';
+        let truncated: Vec<u8> = valid[..valid.len() - 2].to_vec();
+        let bad_square: Vec<u8> = build(START_SQUARES, "", &[(0, 0, true, false, ""), (95, 42, false, false, "")]);
+        let illegal: Vec<u8> = build(START_SQUARES, "", &[(0, 0, true, false, ""), (40, 42, false, false, "")]);
+        let missing_next: Vec<u8> = build(START_SQUARES, "", &[(0, 0, true, false, ""), (72, 42, true, false, "")]);
+        for data in [&valid[..100], &bad_magic[..], &truncated[..], &bad_square[..], &illegal[..], &missing_next[..]] {
+            assert!(parse_xqf(data).is_err());
+        }
+    }
+}
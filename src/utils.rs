@@ -5,7 +5,7 @@ pub use tokio::{
     sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender},
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Stdin, Lines, stdin},
     process::{Child, Command, ChildStdout, ChildStdin},
-    time::{sleep, Duration},
+    time::{sleep, Duration, Instant},
     runtime::Runtime,
     spawn, select
 };
@@ -13,7 +13,7 @@ pub use crossterm::{
     cursor::{MoveTo, Show},
     event::{DisableMouseCapture, EnableMouseCapture, read, Event, KeyCode},
     execute,
-    style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor, Stylize, StyledContent},
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetBackgroundColor, SetForegroundColor, Stylize, StyledContent},
     terminal::{
         disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
         LeaveAlternateScreen,
@@ -29,6 +29,7 @@ pub use std::{
     convert::TryFrom,
 };
 pub use async_trait::async_trait;
+pub use rand::Rng;
 
 pub fn init_logger() -> Result<()> {
     #[cfg(debug_assertions)]
@@ -43,6 +44,7 @@ pub fn init_logger() -> Result<()> {
             let log_file = OpenOptions::new()
                 .create(true)
                 .write(true)
+                .truncate(true)
                 .open(LOG_FILE)
                 .expect("Failed to open log file");
 
@@ -63,6 +65,75 @@ pub fn init_logger() -> Result<()> {
     Ok(())
 }
 
+/// 用户数据目录下的子目录，不存在时创建；sub 为空时返回数据目录本身
+pub fn data_dir(sub: &str) -> Result<PathBuf> {
+    let mut dir: PathBuf = dirs::data_dir().ok_or_else(|| anyhow!("无法确定用户数据目录"))?;
+    dir.push("chess-cli");
+    if !sub.is_empty() {
+        dir.push(sub);
+    }
+    create_dir_all(&dir).with_context(|| format!("无法创建目录 {}", dir.display()))?;
+    Ok(dir)
+}
+
+/// 写入文件，先写同目录下的临时文件再改名，避免中途退出损坏原文件
+pub fn write_atomic(path: &Path, data: impl AsRef<[u8]>) -> Result<()> {
+    let mut temp: std::ffi::OsString = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    std::fs::write(&temp, data).with_context(|| format!("无法写入 {}", path.display()))?;
+    std::fs::rename(&temp, path).with_context(|| format!("无法写入 {}", path.display()))?;
+    Ok(())
+}
+
+/// 当前时间戳（秒）
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// FNV-1a 散列，结果不随编译器版本变化，可以保存到文件中
+pub fn stable_hash(data: impl AsRef<[u8]>) -> u64 {
+    data.as_ref().iter().fold(0xcbf2_9ce4_8422_2325, |hash: u64, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// 为用户文件生成数据文件名：文件名加规范化路径的散列，不同目录下的同名文件互不干扰
+pub fn data_file_name(path: &Path) -> String {
+    let stem: String = path.file_stem().map_or(String::new(), |s| s.to_string_lossy().to_string());
+    let canonical: PathBuf = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    format!("{}-{:016x}", stem, stable_hash(canonical.to_string_lossy().as_bytes()))
+}
+
+/// 时间戳对应的公历日期（UTC），返回 (年, 月, 日)
+fn civil_date(secs: u64) -> (i64, i64, i64) {
+    // 由1970-01-01起的天数换算公历日期
+    let days: i64 = (secs / 86_400) as i64 + 719_468;
+    let era: i64 = days.div_euclid(146_097);
+    let doe: i64 = days - era * 146_097;
+    let yoe: i64 = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy: i64 = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp: i64 = (5 * doy + 2) / 153;
+    let day: i64 = doy - (153 * mp + 2) / 5 + 1;
+    let month: i64 = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year: i64 = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// 当前日期（UTC），格式为 PGN 使用的 "YYYY.MM.DD"
+pub fn today() -> String {
+    let (year, month, day) = civil_date(unix_time());
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+/// 格式化时间戳（UTC），格式为 "YYYY.MM.DD HH:MM"
+pub fn format_time(secs: u64) -> String {
+    let (year, month, day) = civil_date(secs);
+    format!("{:04}.{:02}.{:02} {:02}:{:02}", year, month, day, secs % 86_400 / 3_600, secs % 3_600 / 60)
+}
+
 #[macro_export]
 macro_rules! log_info {
     ($($arg:expr),* $(,)?) => {